@@ -1,8 +1,13 @@
 // Specialized AI Agent Modules
 // Different agents for different types of improvements
 
+use async_trait::async_trait;
 use serde::{Deserialize, Serialize};
-use std::path::PathBuf;
+use serde::de::DeserializeOwned;
+use std::path::Path;
+use crate::agents::file_ops::FileLocks;
+use crate::agents::version_control::{Change, VersionControl};
+use std::sync::Arc;
 use chrono::Utc;
 use std::collections::HashMap;
 
@@ -25,8 +30,61 @@ pub struct AgentTask {
     pub priority: u8, // 1-10, higher is more important
     pub description: String,
     pub target_file: Option<String>,
+    #[serde(default)]
+    pub target_files: Vec<String>, // Further files, each worked on as if it were the target_file
     pub parameters: HashMap<String, String>,
     pub created_at: chrono::DateTime<Utc>,
+    #[serde(default)]
+    pub depends_on: Vec<String>, // Task IDs that must finish first
+}
+
+impl AgentTask {
+    /// `target_file` followed by `target_files`, without repeats
+    pub fn targets(&self) -> Vec<String> {
+        let mut targets: Vec<String> = Vec::new();
+        for target in self.target_file.iter().chain(&self.target_files) {
+            if !targets.contains(target) {
+                targets.push(target.clone());
+            }
+        }
+        targets
+    }
+
+    /// This task narrowed to `target` alone
+    pub fn for_target(&self, target: String) -> AgentTask {
+        let mut task = self.clone();
+        task.target_file = Some(target);
+        task.target_files.clear();
+        task
+    }
+
+    pub fn get_param(&self, key: &str) -> Option<&str> {
+        self.parameters.get(key).map(|v| v.as_str())
+    }
+
+    pub fn get_bool(&self, key: &str) -> Result<Option<bool>, String> {
+        self.get_param(key)
+            .map(|raw| match raw.trim().to_lowercase().as_str() {
+                "true" | "1" | "yes" => Ok(true),
+                "false" | "0" | "no" => Ok(false),
+                _ => Err(format!("Parameter {} is not a boolean: {}", key, raw)),
+            })
+            .transpose()
+    }
+
+    pub fn get_u64(&self, key: &str) -> Result<Option<u64>, String> {
+        self.get_param(key)
+            .map(|raw| raw.trim().parse::<u64>()
+                .map_err(|e| format!("Parameter {} is not an unsigned integer: {} ({})", key, raw, e)))
+            .transpose()
+    }
+
+    pub fn get_json<T: DeserializeOwned>(&self, key: &str) -> Result<Option<T>, String> {
+        self.get_param(key)
+            .map(|raw| serde_json::from_str(raw)
+                .map_err(|e| format!("Parameter {} is not valid JSON: {}", key, e)))
+            .transpose()
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -35,15 +93,155 @@ pub struct AgentResult {
     pub agent_id: String,
     pub success: bool,
     pub changes: Vec<String>, // Change IDs
+    #[serde(default)]
+    pub records: Vec<Change>, // Full changes behind `changes`; recorded by the orchestrator if the agent couldn't
     pub message: String,
     pub metrics: HashMap<String, f64>,
+    #[serde(default)]
+    pub confidence: Option<f64>, // 0.0-1.0, how sure the agent is of its changes
+    #[serde(default)]
+    pub follow_up_tasks: Vec<AgentTask>, // Work discovered while running, queued by the orchestrator
+}
+
+impl AgentResult {
+    /// Combine the results of running `task` once per target: changes and
+    /// follow-ups are concatenated, and the task succeeds only if every run
+    /// did, at the lowest confidence of any run. Metrics combine by their
+    /// kind in `schema`: counts and deltas are summed, ratios averaged over
+    /// the runs that reported them. Undeclared metrics are summed.
+    pub fn merge(task: &AgentTask, schema: &[MetricDef], results: Vec<AgentResult>) -> AgentResult {
+        let mut merged = AgentResult {
+            task_id: task.id.clone(),
+            agent_id: String::new(),
+            success: true,
+            changes: Vec::new(),
+            records: Vec::new(),
+            message: String::new(),
+            metrics: HashMap::new(),
+            confidence: None,
+            follow_up_tasks: Vec::new(),
+        };
+
+        let mut messages = Vec::new();
+        let mut reports: HashMap<String, usize> = HashMap::new();
+        for result in results {
+            merged.agent_id = result.agent_id;
+            merged.success &= result.success;
+            merged.changes.extend(result.changes);
+            merged.records.extend(result.records);
+            messages.push(result.message);
+            for (key, value) in result.metrics {
+                *reports.entry(key.clone()).or_insert(0) += 1;
+                *merged.metrics.entry(key).or_insert(0.0) += value;
+            }
+            merged.follow_up_tasks.extend(result.follow_up_tasks);
+            merged.confidence = match (merged.confidence, result.confidence) {
+                (Some(a), Some(b)) => Some(a.min(b)),
+                (a, b) => a.or(b),
+            };
+        }
+        for def in schema.iter().filter(|def| def.kind == MetricKind::Ratio) {
+            if let (Some(total), Some(count)) = (merged.metrics.get_mut(&def.key), reports.get(&def.key)) {
+                *total /= *count as f64;
+            }
+        }
+        merged.message = messages.join("; ");
+        merged
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub enum MetricKind {
+    Count, // Non-negative tally
+    Delta, // Signed change relative to the before state
+    Ratio, // 0.0 - 1.0
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MetricDef {
+    pub key: String,
+    pub unit: String,
+    pub kind: MetricKind,
+}
+
+impl MetricDef {
+    pub fn new(key: &str, unit: &str, kind: MetricKind) -> Self {
+        Self {
+            key: key.to_string(),
+            unit: unit.to_string(),
+            kind,
+        }
+    }
+
+    pub fn render(&self, value: f64) -> String {
+        match self.kind {
+            MetricKind::Delta => format!("{}: {:+} {}", self.key, value, self.unit),
+            MetricKind::Ratio => format!("{}: {:.1}%", self.key, value * 100.0),
+            MetricKind::Count => format!("{}: {} {}", self.key, value, self.unit),
+        }
+    }
+}
+
+/// Metric keys in `metrics` that `schema` doesn't declare
+pub fn undeclared_metrics(schema: &[MetricDef], metrics: &HashMap<String, f64>) -> Vec<String> {
+    let mut undeclared: Vec<String> = metrics.keys()
+        .filter(|key| !schema.iter().any(|def| &def.key == *key))
+        .cloned()
+        .collect();
+    undeclared.sort();
+    undeclared
+}
+
+/// Run a task with `target_files` once per target on `agent` and merge the
+/// results. Returns `None` for a task with at most a `target_file`, which
+/// the agent handles itself.
+pub async fn execute_each_target<A: Agent + Sync + ?Sized>(
+    agent: &A,
+    task: &AgentTask,
+    base_path: &Path,
+) -> Option<Result<AgentResult, String>> {
+    if task.target_files.is_empty() {
+        return None;
+    }
+    let mut results = Vec::new();
+    for target in task.targets() {
+        match agent.execute_task(&task.for_target(target), base_path).await {
+            Ok(result) => results.push(result),
+            Err(e) => return Some(Err(e)),
+        }
+    }
+    Some(Ok(AgentResult::merge(task, &agent.metrics_schema(), results)))
 }
 
+/// Agents run on the orchestrator's async runtime; `execute_task` can await
+/// network calls or external tools without blocking a worker thread
+#[async_trait]
 pub trait Agent {
     fn get_type(&self) -> AgentType;
     fn get_id(&self) -> &str;
-    fn execute_task(&self, task: &AgentTask, base_path: &PathBuf) -> Result<AgentResult, String>;
+    async fn execute_task(&self, task: &AgentTask, base_path: &Path) -> Result<AgentResult, String>;
     fn can_handle(&self, task: &AgentTask) -> bool;
+
+    /// Called at registration so agents record into the orchestrator's
+    /// version control. Agents that don't record changes can ignore it.
+    fn set_version_control(&mut self, _vc: Arc<VersionControl>) {}
+
+    /// Ask the agent to record changes without writing them to disk, leaving
+    /// the orchestrator to apply them after evaluation. Returns false if the
+    /// agent doesn't support it.
+    fn set_deferred_apply(&mut self, _deferred: bool) -> bool {
+        false
+    }
+
+    /// Called at registration with the orchestrator's file locks, so the
+    /// agent locks files against the other agents and the orchestrator.
+    /// Agents that don't write files can ignore it.
+    fn set_file_locks(&mut self, _locks: FileLocks) {}
+
+    /// Metrics this agent may emit in `AgentResult::metrics`
+    fn metrics_schema(&self) -> Vec<MetricDef> {
+        Vec::new()
+    }
 }
 
 pub struct UIAgent {
@@ -58,6 +256,13 @@ impl UIAgent {
     }
 }
 
+impl Default for UIAgent {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait]
 impl Agent for UIAgent {
     fn get_type(&self) -> AgentType {
         AgentType::UIAgent
@@ -71,7 +276,7 @@ impl Agent for UIAgent {
         task.agent_type == AgentType::UIAgent
     }
 
-    fn execute_task(&self, task: &AgentTask, base_path: &PathBuf) -> Result<AgentResult, String> {
+    async fn execute_task(&self, task: &AgentTask, _base_path: &Path) -> Result<AgentResult, String> {
         // UI improvements would be implemented here
         // This is a placeholder for the actual implementation
         Ok(AgentResult {
@@ -79,8 +284,11 @@ impl Agent for UIAgent {
             agent_id: self.id.clone(),
             success: true,
             changes: vec![],
+            records: vec![],
             message: "UI improvements applied".to_string(),
             metrics: HashMap::new(),
+            confidence: None,
+            follow_up_tasks: Vec::new(),
         })
     }
 }
@@ -97,6 +305,13 @@ impl PerformanceAgent {
     }
 }
 
+impl Default for PerformanceAgent {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait]
 impl Agent for PerformanceAgent {
     fn get_type(&self) -> AgentType {
         AgentType::PerformanceAgent
@@ -110,15 +325,18 @@ impl Agent for PerformanceAgent {
         task.agent_type == AgentType::PerformanceAgent
     }
 
-    fn execute_task(&self, task: &AgentTask, base_path: &PathBuf) -> Result<AgentResult, String> {
+    async fn execute_task(&self, task: &AgentTask, _base_path: &Path) -> Result<AgentResult, String> {
         // Performance optimizations would be implemented here
         Ok(AgentResult {
             task_id: task.id.clone(),
             agent_id: self.id.clone(),
             success: true,
             changes: vec![],
+            records: vec![],
             message: "Performance optimizations applied".to_string(),
             metrics: HashMap::new(),
+            confidence: None,
+            follow_up_tasks: Vec::new(),
         })
     }
 }
@@ -135,6 +353,13 @@ impl ContentAgent {
     }
 }
 
+impl Default for ContentAgent {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait]
 impl Agent for ContentAgent {
     fn get_type(&self) -> AgentType {
         AgentType::ContentAgent
@@ -148,15 +373,18 @@ impl Agent for ContentAgent {
         task.agent_type == AgentType::ContentAgent
     }
 
-    fn execute_task(&self, task: &AgentTask, base_path: &PathBuf) -> Result<AgentResult, String> {
+    async fn execute_task(&self, task: &AgentTask, _base_path: &Path) -> Result<AgentResult, String> {
         // Content generation would be implemented here
         Ok(AgentResult {
             task_id: task.id.clone(),
             agent_id: self.id.clone(),
             success: true,
             changes: vec![],
+            records: vec![],
             message: "Content updated".to_string(),
             metrics: HashMap::new(),
+            confidence: None,
+            follow_up_tasks: Vec::new(),
         })
     }
 }
@@ -173,6 +401,13 @@ impl FeatureAgent {
     }
 }
 
+impl Default for FeatureAgent {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait]
 impl Agent for FeatureAgent {
     fn get_type(&self) -> AgentType {
         AgentType::FeatureAgent
@@ -186,16 +421,100 @@ impl Agent for FeatureAgent {
         task.agent_type == AgentType::FeatureAgent
     }
 
-    fn execute_task(&self, task: &AgentTask, base_path: &PathBuf) -> Result<AgentResult, String> {
+    async fn execute_task(&self, task: &AgentTask, _base_path: &Path) -> Result<AgentResult, String> {
         // Feature development would be implemented here
         Ok(AgentResult {
             task_id: task.id.clone(),
             agent_id: self.id.clone(),
             success: true,
             changes: vec![],
+            records: vec![],
             message: "New feature implemented".to_string(),
             metrics: HashMap::new(),
+            confidence: None,
+            follow_up_tasks: Vec::new(),
         })
     }
 }
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn task() -> AgentTask {
+        AgentTask {
+            id: "task".to_string(),
+            agent_type: AgentType::PerformanceAgent,
+            priority: 5,
+            description: "Optimize".to_string(),
+            target_file: None,
+            target_files: vec!["a.css".to_string(), "b.css".to_string()],
+            parameters: HashMap::new(),
+            created_at: Utc::now(),
+            depends_on: Vec::new(),
+        }
+    }
+
+    fn result(metrics: &[(&str, f64)]) -> AgentResult {
+        AgentResult {
+            task_id: "task".to_string(),
+            agent_id: "agent".to_string(),
+            success: true,
+            changes: Vec::new(),
+            records: Vec::new(),
+            message: String::new(),
+            metrics: metrics.iter().map(|(key, value)| (key.to_string(), *value)).collect(),
+            confidence: None,
+            follow_up_tasks: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn merge_combines_metrics_by_kind() {
+        let schema = vec![
+            MetricDef::new("bytes_saved", "bytes", MetricKind::Count),
+            MetricDef::new("lines_added", "lines", MetricKind::Delta),
+            MetricDef::new("compression", "ratio", MetricKind::Ratio),
+        ];
+        let merged = AgentResult::merge(&task(), &schema, vec![
+            result(&[("bytes_saved", 100.0), ("lines_added", 3.0), ("compression", 0.2)]),
+            result(&[("bytes_saved", 50.0), ("lines_added", -5.0), ("compression", 0.6)]),
+            result(&[("bytes_saved", 10.0)]),
+        ]);
+
+        assert_eq!(merged.metrics["bytes_saved"], 160.0);
+        assert_eq!(merged.metrics["lines_added"], -2.0);
+        assert!((merged.metrics["compression"] - 0.4).abs() < 1e-9);
+    }
+
+    #[test]
+    fn typed_parameters_parse_or_explain_why_not() {
+        #[derive(Debug, Deserialize, PartialEq)]
+        struct Palette { name: String, colors: Vec<String> }
+        #[derive(Debug, Deserialize, PartialEq)]
+        struct Theme { palette: Palette, dark: bool }
+
+        let mut task = task();
+        task.parameters.insert("minify".to_string(), "Yes".to_string());
+        task.parameters.insert("max_files".to_string(), " 12 ".to_string());
+        task.parameters.insert("theme".to_string(),
+            r##"{"palette":{"name":"ocean","colors":["#004","#08f"]},"dark":true}"##.to_string());
+        task.parameters.insert("broken".to_string(), "{\"palette\":".to_string());
+
+        assert_eq!(task.get_bool("minify"), Ok(Some(true)));
+        assert_eq!(task.get_u64("max_files"), Ok(Some(12)));
+        assert_eq!(task.get_json::<Theme>("theme"), Ok(Some(Theme {
+            palette: Palette {
+                name: "ocean".to_string(),
+                colors: vec!["#004".to_string(), "#08f".to_string()],
+            },
+            dark: true,
+        })));
+        assert_eq!(task.get_bool("missing"), Ok(None));
+
+        assert!(task.get_bool("max_files").unwrap_err().contains("max_files"));
+        assert!(task.get_u64("minify").is_err());
+        assert!(task.get_json::<Theme>("broken").unwrap_err().contains("not valid JSON"));
+    }
+}
@@ -0,0 +1,163 @@
+// Benchmarks for the agent system's hot paths: queueing tasks and
+// evaluating changes.
+//
+// Needs `criterion` as a dev-dependency and a `[[bench]]` entry with
+// `name = "agent_system"` and `harness = false` in the backend manifest.
+// Run with `cargo bench --bench agent_system`.
+//
+// Queue operations are O(log n) in the tasks queued for one agent type,
+// so the per-task time of `add_task`/`get_next_task` should stay roughly
+// flat from 1k to 100k tasks; a jump between sizes means a linear scan
+// crept back in. Evaluation is linear in the file, so the large case
+// should take about 100x the small one.
+
+use brion_qt_backend::agents::{AgentTask, AgentType, ChangeEvaluator, FileOperations, TaskQueue};
+use brion_qt_backend::agents::version_control::ChangeType;
+use chrono::Utc;
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion, Throughput};
+use std::collections::HashMap;
+
+const QUEUE_SIZES: [usize; 3] = [1_000, 10_000, 100_000];
+
+const AGENT_TYPES: [AgentType; 4] = [
+    AgentType::UIAgent,
+    AgentType::PerformanceAgent,
+    AgentType::SEOAgent,
+    AgentType::ContentAgent,
+];
+
+fn task(i: usize) -> AgentTask {
+    AgentTask {
+        id: format!("task-{}", i),
+        agent_type: AGENT_TYPES[i % AGENT_TYPES.len()].clone(),
+        priority: (i % 10) as u8 + 1,
+        description: format!("Task {}", i),
+        target_file: Some(format!("pages/page-{}.html", i)),
+        target_files: Vec::new(),
+        parameters: HashMap::new(),
+        created_at: Utc::now(),
+        depends_on: Vec::new(),
+    }
+}
+
+fn filled_queue(size: usize) -> TaskQueue {
+    let queue = TaskQueue::new();
+    for i in 0..size {
+        queue.add_task(task(i));
+    }
+    queue
+}
+
+fn task_queue(c: &mut Criterion) {
+    let mut group = c.benchmark_group("task_queue");
+    for size in QUEUE_SIZES {
+        group.throughput(Throughput::Elements(size as u64));
+
+        group.bench_with_input(BenchmarkId::new("add_task", size), &size, |b, &size| {
+            b.iter_batched(
+                || (0..size).map(task).collect::<Vec<_>>(),
+                |tasks| {
+                    let queue = TaskQueue::new();
+                    for task in tasks {
+                        queue.add_task(task);
+                    }
+                    queue
+                },
+                BatchSize::LargeInput,
+            );
+        });
+
+        group.bench_with_input(BenchmarkId::new("get_next_task", size), &size, |b, &size| {
+            b.iter_batched(
+                || filled_queue(size),
+                |queue| {
+                    let mut popped = 0;
+                    while queue.get_next_task(None).is_some() {
+                        popped += 1;
+                    }
+                    assert_eq!(popped, size, "every queued task should pop exactly once");
+                },
+                BatchSize::LargeInput,
+            );
+        });
+
+        group.bench_with_input(BenchmarkId::new("get_next_task_typed", size), &size, |b, &size| {
+            b.iter_batched(
+                || filled_queue(size),
+                |queue| {
+                    let mut popped = 0;
+                    while queue.get_next_task(Some(AgentType::SEOAgent)).is_some() {
+                        popped += 1;
+                    }
+                    assert_eq!(popped, size / AGENT_TYPES.len());
+                },
+                BatchSize::LargeInput,
+            );
+        });
+    }
+    group.finish();
+}
+
+/// A page of `sections` sections, with or without the viewport meta tag
+fn page(sections: usize, viewport: bool) -> String {
+    let mut html = String::from("<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<title>Bench</title>\n");
+    if viewport {
+        html.push_str("<meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">\n");
+    }
+    html.push_str("<link rel=\"stylesheet\" href=\"styles/main.css\">\n</head>\n<body>\n<main>\n");
+    for i in 0..sections {
+        html.push_str(&format!(
+            "<section id=\"s{0}\">\n<h2>Section {0}</h2>\n<p>Paragraph {0} with <a href=\"/p/{0}\">a link</a>.</p>\n<img src=\"img/{0}.png\" alt=\"Image {0}\">\n</section>\n",
+            i
+        ));
+    }
+    html.push_str("</main>\n</body>\n</html>\n");
+    html
+}
+
+fn stylesheet(rules: usize) -> String {
+    (0..rules)
+        .map(|i| format!(".card-{0} {{ color: var(--primary); padding: {1}px; transition: opacity 0.2s ease; }}\n", i, i % 32))
+        .collect()
+}
+
+fn evaluator(c: &mut Criterion) {
+    let evaluator = ChangeEvaluator::new();
+    let mut group = c.benchmark_group("evaluate_change");
+
+    for (label, sections) in [("small", 10), ("medium", 100), ("large", 1_000)] {
+        let before = page(sections, false);
+        let after = page(sections, true);
+        group.throughput(Throughput::Bytes(after.len() as u64));
+        let change = FileOperations::create_change(
+            "bench-agent", "UIAgent", "index.html".to_string(), ChangeType::Modify, before, after,
+        );
+        group.bench_with_input(BenchmarkId::new("html", label), &change, |b, change| {
+            b.iter(|| {
+                let result = evaluator.evaluate_change(change);
+                assert!(result.overall_score.is_finite());
+                result
+            });
+        });
+    }
+
+    for (label, rules) in [("small", 20), ("medium", 200), ("large", 2_000)] {
+        let before = format!(":root {{ --primary: #00d4ff; }}\n{}", stylesheet(rules));
+        let after = format!("{}@media (prefers-reduced-motion: reduce) {{ * {{ transition: none; }} }}\n", before);
+        group.throughput(Throughput::Bytes(after.len() as u64));
+        let change = FileOperations::create_change(
+            "bench-agent", "UIAgent", "styles/main.css".to_string(), ChangeType::UpdateStyle, before, after,
+        );
+        group.bench_with_input(BenchmarkId::new("css", label), &change, |b, change| {
+            b.iter(|| {
+                let result = evaluator.evaluate_change(change);
+                assert!(result.overall_score.is_finite());
+                result
+            });
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, task_queue, evaluator);
+criterion_main!(benches);
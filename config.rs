@@ -0,0 +1,102 @@
+// Agent System Configuration
+// Environment-specific profiles for thresholds, safe mode, and enabled agents
+
+use serde::{Deserialize, Serialize};
+use crate::agents::agents::AgentType;
+use crate::agents::file_ops::{FileOperations, UnreadablePolicy};
+use std::collections::HashMap;
+use std::path::Path;
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash)]
+pub enum Environment {
+    Dev,
+    Staging,
+    Prod,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AgentProfile {
+    #[serde(default = "default_threshold")]
+    pub min_score_threshold: f64,
+    #[serde(default)]
+    pub safe_mode: bool, // Roll back any change the evaluator flags, even above threshold
+    #[serde(default)]
+    pub enabled_agents: Option<Vec<AgentType>>, // None enables every agent type
+    #[serde(default)]
+    pub max_diff_lines: Option<usize>, // Truncate diffs in rendered reports
+    #[serde(default)]
+    pub max_snapshots: Option<usize>, // Oldest snapshots are pruned beyond this many
+    #[serde(default)]
+    pub unreadable_files: UnreadablePolicy, // What diagnostics do with files they can't read
+}
+
+fn default_threshold() -> f64 {
+    0.6
+}
+
+impl Default for AgentProfile {
+    fn default() -> Self {
+        Self {
+            min_score_threshold: default_threshold(),
+            safe_mode: false,
+            enabled_agents: None,
+            max_diff_lines: None,
+            max_snapshots: None,
+            unreadable_files: UnreadablePolicy::default(),
+        }
+    }
+}
+
+impl AgentProfile {
+    pub fn is_enabled(&self, agent_type: &AgentType) -> bool {
+        self.enabled_agents.as_ref()
+            .map(|enabled| enabled.contains(agent_type))
+            .unwrap_or(true)
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AgentConfig {
+    pub profiles: HashMap<Environment, AgentProfile>,
+}
+
+impl AgentConfig {
+    pub fn from_json(json: &str) -> Result<Self, String> {
+        serde_json::from_str(json)
+            .map_err(|e| format!("Failed to parse agent config: {}", e))
+    }
+
+    pub fn load(path: &Path) -> Result<Self, String> {
+        Self::from_json(&FileOperations::read_file(path)?)
+    }
+
+    pub fn profile(&self, environment: Environment) -> Result<AgentProfile, String> {
+        self.profiles.get(&environment)
+            .cloned()
+            .ok_or_else(|| format!("No profile configured for {:?}", environment))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn prod_profile_is_stricter_than_dev() {
+        let config = AgentConfig::from_json(r#"{
+            "profiles": {
+                "Dev": { "min_score_threshold": 0.4 },
+                "Prod": { "min_score_threshold": 0.8, "safe_mode": true, "enabled_agents": ["SEOAgent"] }
+            }
+        }"#).unwrap();
+
+        let dev = config.profile(Environment::Dev).unwrap();
+        let prod = config.profile(Environment::Prod).unwrap();
+        assert!(!dev.safe_mode);
+        assert!(prod.safe_mode);
+        assert!(prod.min_score_threshold > dev.min_score_threshold);
+        assert!(dev.is_enabled(&AgentType::UIAgent));
+        assert!(!prod.is_enabled(&AgentType::UIAgent));
+        assert!(config.profile(Environment::Staging).is_err());
+    }
+}
@@ -0,0 +1,343 @@
+// Site-wide Consistency Checks
+// Finds references that no single change breaks on its own but the site as a whole no longer satisfies
+
+use crate::agents::error::BrionError;
+use crate::agents::file_ops::{FileOperations, UnreadablePolicy};
+use crate::agents::html_head::HtmlHead;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+
+/// `rel` values of `<link>` tags whose `href` is a file the page loads
+const FILE_LINK_RELS: &[&str] = &["stylesheet", "preload", "modulepreload", "icon", "apple-touch-icon"];
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub enum InconsistencyKind {
+    UndefinedCssVariable,
+    MissingElementId,
+    MissingClass,
+    BrokenReference, // <script src> or stylesheet, preload or icon <link href> pointing at a file that doesn't exist
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Inconsistency {
+    pub file: String,
+    pub kind: InconsistencyKind,
+    pub reference: String,
+}
+
+pub struct ConsistencyChecker;
+
+impl ConsistencyChecker {
+    /// Load every HTML, CSS and JS file under `base_path` and check them,
+    /// skipping files that can't be read
+    pub fn check(base_path: &Path) -> Vec<Inconsistency> {
+        Self::check_with_policy(base_path, UnreadablePolicy::SkipAndLog)
+            .map(|(found, _)| found)
+            .unwrap_or_default()
+    }
+
+    /// Like `check`, with `policy` deciding what happens to files that can't
+    /// be read. Errors gathered under `Collect` are returned alongside.
+    pub fn check_with_policy(
+        base_path: &Path,
+        policy: UnreadablePolicy,
+    ) -> Result<(Vec<Inconsistency>, Vec<BrionError>), BrionError> {
+        let mut discovery = FileOperations::discover_files(base_path, policy)?;
+
+        // Every file is a valid reference target, only site sources are read
+        let mut files = HashMap::new();
+        for relative in std::mem::take(&mut discovery.files) {
+            let content = if Self::is_css(&relative) || Self::is_html(&relative) || Self::is_js(&relative) {
+                match FileOperations::read_file(&base_path.join(&relative)) {
+                    Ok(content) => content,
+                    Err(e) => {
+                        discovery.handle(policy, e)?;
+                        continue;
+                    }
+                }
+            } else {
+                String::new()
+            };
+            files.insert(relative, content);
+        }
+        Ok((Self::check_files(&files), discovery.errors))
+    }
+
+    /// Check a set of site files keyed by path relative to the site root
+    pub fn check_files(files: &HashMap<String, String>) -> Vec<Inconsistency> {
+        let mut defined_vars = HashSet::new();
+        let mut ids = HashSet::new();
+        let mut classes = HashSet::new();
+        for (path, content) in files {
+            if Self::is_css(path) || Self::is_html(path) {
+                defined_vars.extend(Self::defined_css_variables(content));
+            }
+            if Self::is_html(path) {
+                ids.extend(Self::attribute_values(content, "id"));
+                for value in Self::attribute_values(content, "class") {
+                    classes.extend(value.split_whitespace().map(str::to_string));
+                }
+            }
+            if Self::is_js(path) {
+                for method in ["classList.add(", "classList.toggle("] {
+                    classes.extend(Self::call_arguments(content, method));
+                }
+                defined_vars.extend(Self::call_arguments(content, "setProperty("));
+            }
+        }
+
+        let mut paths: Vec<&String> = files.keys().collect();
+        paths.sort();
+
+        let mut found = Vec::new();
+        for path in paths {
+            let content = &files[path];
+            let mut report = |kind: InconsistencyKind, reference: String| {
+                found.push(Inconsistency { file: path.clone(), kind, reference });
+            };
+
+            if Self::is_css(path) || Self::is_html(path) {
+                for var in Self::used_css_variables(content) {
+                    if !defined_vars.contains(&var) {
+                        report(InconsistencyKind::UndefinedCssVariable, var);
+                    }
+                }
+            }
+
+            if Self::is_js(path) {
+                for id in Self::call_arguments(content, "getElementById(") {
+                    if !ids.contains(&id) {
+                        report(InconsistencyKind::MissingElementId, id);
+                    }
+                }
+                for method in ["querySelector(", "querySelectorAll("] {
+                    for selector in Self::call_arguments(content, method) {
+                        let (kind, name, known) = match selector.chars().next() {
+                            Some('#') => (InconsistencyKind::MissingElementId, &selector[1..], &ids),
+                            Some('.') => (InconsistencyKind::MissingClass, &selector[1..], &classes),
+                            _ => continue,
+                        };
+                        // Only simple selectors; compound ones are left alone
+                        if Self::is_identifier(name) && !known.contains(name) {
+                            report(kind, name.to_string());
+                        }
+                    }
+                }
+            }
+
+            if Self::is_html(path) {
+                let targets = Self::tag_attributes(content, "script", "src").into_iter()
+                    .chain(Self::linked_files(content));
+                for target in targets {
+                    if let Some(resolved) = Self::resolve_local(path, &target) {
+                        if !files.contains_key(&resolved) {
+                            report(InconsistencyKind::BrokenReference, target);
+                        }
+                    }
+                }
+            }
+        }
+
+        found
+    }
+
+    fn is_css(path: &str) -> bool {
+        path.ends_with(".css")
+    }
+
+    fn is_html(path: &str) -> bool {
+        path.ends_with(".html") || path.ends_with(".htm")
+    }
+
+    fn is_js(path: &str) -> bool {
+        path.ends_with(".js")
+    }
+
+    fn is_identifier(name: &str) -> bool {
+        !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    }
+
+    fn identifier_at(content: &str, start: usize) -> &str {
+        let end = content[start..]
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '-' || c == '_'))
+            .map(|offset| start + offset)
+            .unwrap_or(content.len());
+        &content[start..end]
+    }
+
+    /// Custom properties declared anywhere, as `--name: value`
+    fn defined_css_variables(content: &str) -> HashSet<String> {
+        let mut defined = HashSet::new();
+        for (start, _) in content.match_indices("--") {
+            let preceded_ok = content[..start].chars().next_back()
+                .map(|c| c.is_whitespace() || c == '{' || c == ';' || c == '"' || c == '\'')
+                .unwrap_or(true);
+            let name = Self::identifier_at(content, start + 2);
+            if preceded_ok && !name.is_empty() && content[start + 2 + name.len()..].trim_start().starts_with(':') {
+                defined.insert(format!("--{}", name));
+            }
+        }
+        defined
+    }
+
+    /// Custom properties read through `var()` without a fallback
+    fn used_css_variables(content: &str) -> Vec<String> {
+        let mut used = Vec::new();
+        for (start, _) in content.match_indices("var(") {
+            let rest = content[start + 4..].trim_start();
+            if let Some(after_dashes) = rest.strip_prefix("--") {
+                let name = Self::identifier_at(after_dashes, 0);
+                let has_fallback = after_dashes[name.len()..].trim_start().starts_with(',');
+                if !name.is_empty() && !has_fallback {
+                    let var = format!("--{}", name);
+                    if !used.contains(&var) {
+                        used.push(var);
+                    }
+                }
+            }
+        }
+        used
+    }
+
+    /// Values of `attr="..."` (or single-quoted) where `attr` is a whole
+    /// attribute name, so `id` doesn't match `data-id`
+    fn attribute_values(content: &str, attr: &str) -> Vec<String> {
+        let mut values = Vec::new();
+        let pattern = format!("{}=", attr);
+        for (start, _) in content.match_indices(&pattern) {
+            let whole_name = content[..start].chars().next_back()
+                .map(|c| c.is_whitespace())
+                .unwrap_or(false);
+            if !whole_name {
+                continue;
+            }
+            if let Some(value) = Self::quoted_at(content, start + pattern.len()) {
+                values.push(value);
+            }
+        }
+        values
+    }
+
+    /// `attr` values on every `<tag ...>` in the document
+    fn tag_attributes(content: &str, tag: &str, attr: &str) -> Vec<String> {
+        let mut values = Vec::new();
+        let opening = format!("<{}", tag);
+        for (start, _) in content.match_indices(&opening) {
+            let end = content[start..].find('>').map(|e| start + e).unwrap_or(content.len());
+            values.extend(Self::attribute_values(&content[start..end], attr));
+        }
+        values
+    }
+
+    /// `href`s of the `<link>` tags that load a file. Canonical, alternate
+    /// and preconnect links name pages or origins, not files, so they're left out.
+    fn linked_files(content: &str) -> Vec<String> {
+        let mut hrefs = Vec::new();
+        for (start, _) in content.match_indices("<link") {
+            let end = content[start..].find('>').map(|e| start + e + 1).unwrap_or(content.len());
+            let attributes = HtmlHead::attributes(&content[start..end]);
+            let loads_file = attributes.iter()
+                .filter(|(name, _)| name == "rel")
+                .flat_map(|(_, rel)| rel.split_whitespace())
+                .any(|rel| FILE_LINK_RELS.contains(&rel.to_ascii_lowercase().as_str()));
+            if loads_file {
+                hrefs.extend(attributes.into_iter().filter(|(name, _)| name == "href").map(|(_, href)| href));
+            }
+        }
+        hrefs
+    }
+
+    /// String literal first arguments of calls to `method`
+    fn call_arguments(content: &str, method: &str) -> Vec<String> {
+        content.match_indices(method)
+            .filter_map(|(start, _)| Self::quoted_at(content, start + method.len()))
+            .collect()
+    }
+
+    fn quoted_at(content: &str, start: usize) -> Option<String> {
+        let rest = content[start..].trim_start();
+        let quote = rest.chars().next().filter(|c| *c == '"' || *c == '\'' || *c == '`')?;
+        let end = rest[1..].find(quote)?;
+        Some(rest[1..1 + end].to_string())
+    }
+
+    /// Whether `target` starts with a URL scheme such as `https:`,
+    /// `data:` or `mailto:`
+    fn has_scheme(target: &str) -> bool {
+        match target.split_once(':') {
+            Some((scheme, _)) => scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+                && scheme.chars().all(|c| c.is_ascii_alphanumeric() || c == '+' || c == '-' || c == '.'),
+            None => false,
+        }
+    }
+
+    /// Site-relative path for a local reference, or `None` for external
+    /// URLs, data URIs and fragments
+    fn resolve_local(from: &str, target: &str) -> Option<String> {
+        let target = target.split(['?', '#']).next().unwrap_or("");
+        if target.is_empty() || target.starts_with("//") || Self::has_scheme(target) {
+            return None;
+        }
+
+        let mut parts: Vec<&str> = if target.starts_with('/') {
+            Vec::new()
+        } else {
+            let mut dir: Vec<&str> = from.split('/').collect();
+            dir.pop();
+            dir
+        };
+        for segment in target.split('/') {
+            match segment {
+                "" | "." => {}
+                ".." => {
+                    parts.pop();
+                }
+                segment => parts.push(segment),
+            }
+        }
+        Some(parts.join("/"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn site(files: &[(&str, &str)]) -> HashMap<String, String> {
+        files.iter().map(|(path, content)| (path.to_string(), content.to_string())).collect()
+    }
+
+    #[test]
+    fn undefined_css_variable_is_reported() {
+        let files = site(&[
+            ("styles/main.css", ":root { --primary: #00d4ff; }\nbody { color: var(--primary); background: var(--missing); }"),
+        ]);
+
+        let found = ConsistencyChecker::check_files(&files);
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].kind, InconsistencyKind::UndefinedCssVariable);
+        assert_eq!(found[0].reference, "--missing");
+    }
+
+    #[test]
+    fn only_links_that_load_files_are_checked() {
+        let files = site(&[
+            ("index.html", "<head>\n<link rel=\"canonical\" href=\"/about\">\n<link rel=\"preconnect\" href=\"https://fonts.gstatic.com\">\n<link rel=\"alternate\" href=\"/feed\">\n<link rel=\"stylesheet\" href=\"styles/missing.css\">\n<link rel=\"icon\" href=\"favicon.ico\">\n</head>"),
+            ("favicon.ico", ""),
+        ]);
+
+        let found = ConsistencyChecker::check_files(&files);
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].kind, InconsistencyKind::BrokenReference);
+        assert_eq!(found[0].reference, "styles/missing.css");
+    }
+
+    #[test]
+    fn references_with_a_scheme_are_external() {
+        let files = site(&[
+            ("index.html", "<script src=\"https://cdn.example.com/lib.js\"></script><script src=\"data:text/javascript,1\"></script>"),
+        ]);
+        assert!(ConsistencyChecker::check_files(&files).is_empty());
+    }
+}
@@ -0,0 +1,136 @@
+// CSS Property Editing
+// Locates rules by selector and edits individual declarations regardless of formatting
+
+pub struct CssRule {
+    pub selector: String,
+    pub body_start: usize, // Byte offset just after '{'
+    pub body_end: usize,   // Byte offset of the closing '}'
+}
+
+pub struct CssEditor;
+
+impl CssEditor {
+    /// Scan a stylesheet and return every rule (including at-rule blocks)
+    /// in source order, skipping comments and quoted strings
+    pub fn parse_rules(css: &str) -> Vec<CssRule> {
+        let bytes = css.as_bytes();
+        let mut rules = Vec::new();
+        let mut open: Vec<(String, usize)> = Vec::new();
+        let mut selector_start = 0;
+        let mut i = 0;
+
+        while i < bytes.len() {
+            match bytes[i] {
+                b'/' if bytes.get(i + 1) == Some(&b'*') => {
+                    i = css[i + 2..].find("*/").map(|end| i + 2 + end + 2).unwrap_or(bytes.len());
+                    continue;
+                }
+                quote @ (b'"' | b'\'') => {
+                    i += 1;
+                    while i < bytes.len() && bytes[i] != quote {
+                        if bytes[i] == b'\\' {
+                            i += 1;
+                        }
+                        i += 1;
+                    }
+                }
+                b'{' => {
+                    let selector = Self::strip_comments(&css[selector_start..i]);
+                    open.push((selector, i + 1));
+                    selector_start = i + 1;
+                }
+                b'}' => {
+                    if let Some((selector, body_start)) = open.pop() {
+                        rules.push(CssRule { selector, body_start, body_end: i });
+                    }
+                    selector_start = i + 1;
+                }
+                b';' => selector_start = i + 1,
+                _ => {}
+            }
+            i += 1;
+        }
+
+        rules
+    }
+
+    /// Find the first rule whose selector list contains `selector`
+    pub fn find_rule(css: &str, selector: &str) -> Option<CssRule> {
+        let target = Self::normalize(selector);
+        Self::parse_rules(css).into_iter().find(|rule| {
+            rule.selector.split(',').map(Self::normalize).any(|part| {
+                part == target || (target.starts_with(':') && part.ends_with(&target))
+            })
+        })
+    }
+
+    pub fn get_property(css: &str, selector: &str, property: &str) -> Option<String> {
+        let rule = Self::find_rule(css, selector)?;
+        css[rule.body_start..rule.body_end]
+            .split(';')
+            .filter_map(|decl| decl.split_once(':'))
+            .find(|(name, _)| Self::strip_comments(name) == property)
+            .map(|(_, value)| value.trim().to_string())
+    }
+
+    /// Insert or update `property` inside the rule matching `selector`.
+    /// Returns `None` when no rule matches.
+    pub fn set_property(css: &str, selector: &str, property: &str, value: &str) -> Option<String> {
+        let rule = Self::find_rule(css, selector)?;
+        Some(Self::set_property_in(css, &rule, property, value))
+    }
+
+    /// Like `set_property`, on a rule already located with `parse_rules`
+    pub fn set_property_in(css: &str, rule: &CssRule, property: &str, value: &str) -> String {
+        let body = &css[rule.body_start..rule.body_end];
+
+        // Update in place when the property is already declared. A comment
+        // ahead of the declaration ends up in `name`, so it's left out.
+        let mut offset = 0;
+        for decl in body.split(';') {
+            if let Some((name, old_value)) = decl.split_once(':') {
+                if Self::strip_comments(name) == property {
+                    let value_start = rule.body_start + offset + name.len() + 1;
+                    let value_end = value_start + old_value.len();
+                    let leading = &old_value[..old_value.len() - old_value.trim_start().len()];
+                    let trailing = &old_value[old_value.trim_end().len()..];
+                    return format!(
+                        "{}{}{}{}{}", &css[..value_start], leading, value, trailing, &css[value_end..]
+                    );
+                }
+            }
+            offset += decl.len() + 1;
+        }
+
+        // Otherwise append a declaration matching the rule's formatting
+        let trimmed = body.trim_end();
+        let needs_semicolon = !trimmed.trim().is_empty() && !trimmed.ends_with(';');
+        let insert_at = rule.body_start + trimmed.len();
+        let declaration = if body.contains('\n') {
+            let indent: String = body.lines()
+                .find(|line| !line.trim().is_empty())
+                .map(|line| line.chars().take_while(|c| c.is_whitespace()).collect())
+                .unwrap_or_else(|| "    ".to_string());
+            format!("{}\n{}{}: {};", if needs_semicolon { ";" } else { "" }, indent, property, value)
+        } else {
+            format!("{}{}: {};", if needs_semicolon { ";" } else { "" }, property, value)
+        };
+
+        format!("{}{}{}", &css[..insert_at], declaration, &css[insert_at..])
+    }
+
+    fn normalize(selector: &str) -> String {
+        selector.split_whitespace().collect::<Vec<_>>().join(" ")
+    }
+
+    fn strip_comments(text: &str) -> String {
+        let mut result = String::new();
+        let mut rest = text;
+        while let Some(start) = rest.find("/*") {
+            result.push_str(&rest[..start]);
+            rest = rest[start + 2..].find("*/").map(|end| &rest[start + 2 + end + 2..]).unwrap_or("");
+        }
+        result.push_str(rest);
+        result.trim().to_string()
+    }
+}
@@ -0,0 +1,83 @@
+// Error Types for AI Agent System
+// Typed failures so callers can tell a missing file from a bad change id
+
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug)]
+pub enum BrionError {
+    FileNotFound(PathBuf),
+    PermissionDenied(PathBuf),
+    PathOutsideBase(String),  // A change's file path is absolute or resolves outside the site root
+    FileLocked(PathBuf),      // Held by a task that can't run until the caller stops waiting
+    Io(io::Error),
+    Parse(String),            // A journal entry, config or other stored data couldn't be decoded
+    ChangeNotFound(String),
+    VersionNotFound(String),
+    EvaluationFailed(String), // The evaluator or a change transform refused a change
+    Other(String),
+}
+
+impl BrionError {
+    /// Classify an I/O error raised while working on `path`, keeping the
+    /// path for the variants callers are most likely to act on
+    pub fn io_at(path: &Path, error: io::Error) -> Self {
+        match error.kind() {
+            io::ErrorKind::NotFound => BrionError::FileNotFound(path.to_path_buf()),
+            io::ErrorKind::PermissionDenied => BrionError::PermissionDenied(path.to_path_buf()),
+            _ => BrionError::Io(io::Error::new(error.kind(), format!("{}: {}", path.display(), error))),
+        }
+    }
+}
+
+impl fmt::Display for BrionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BrionError::FileNotFound(path) => write!(f, "File not found: {}", path.display()),
+            BrionError::PermissionDenied(path) => write!(f, "Permission denied: {}", path.display()),
+            BrionError::PathOutsideBase(path) => write!(f, "Path {} is outside the site root", path),
+            BrionError::FileLocked(path) => write!(f, "File is locked: {}", path.display()),
+            BrionError::Io(e) => write!(f, "I/O error: {}", e),
+            BrionError::Parse(message) => write!(f, "Parse error: {}", message),
+            BrionError::ChangeNotFound(id) => write!(f, "Change {} not found", id),
+            BrionError::VersionNotFound(id) => write!(f, "Version {} not found", id),
+            BrionError::EvaluationFailed(message) => write!(f, "Evaluation failed: {}", message),
+            BrionError::Other(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl std::error::Error for BrionError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            BrionError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for BrionError {
+    fn from(error: io::Error) -> Self {
+        BrionError::Io(error)
+    }
+}
+
+impl From<serde_json::Error> for BrionError {
+    fn from(error: serde_json::Error) -> Self {
+        BrionError::Parse(error.to_string())
+    }
+}
+
+// Agents and config still report plain strings; these let `?` cross between them
+impl From<String> for BrionError {
+    fn from(message: String) -> Self {
+        BrionError::Other(message)
+    }
+}
+
+impl From<BrionError> for String {
+    fn from(error: BrionError) -> Self {
+        error.to_string()
+    }
+}
@@ -2,8 +2,16 @@
 // Assesses aesthetics and functionality of changes
 
 use serde::{Deserialize, Serialize};
+use crate::agents::agents::AgentType;
+use crate::agents::error::BrionError;
 use crate::agents::version_control::Change;
+use crate::agents::file_ops::{FileKind, FileOperations};
+use crate::agents::html_head::HtmlHead;
+use crate::agents::external_tools::{check_with_retry, ExternalTool, ToolOutcome};
 use std::collections::HashMap;
+use std::fmt;
+use std::path::Path;
+use log::warn;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct EvaluationResult {
@@ -12,71 +20,855 @@ pub struct EvaluationResult {
     pub functionality_score: f64,
     pub overall_score: f64,
     pub issues: Vec<String>,
-    pub recommendations: Vec<String>,
+    pub recommendations: Vec<Recommendation>,
     pub should_keep: bool,
 }
 
+/// Something a change should do differently. `code` is stable for tooling
+/// to match on; `suggested_agent` is the agent type that could act on it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Recommendation {
+    pub code: String,
+    pub message: String,
+    pub suggested_agent: Option<AgentType>,
+}
+
+impl Recommendation {
+    pub fn new(code: &str, message: impl Into<String>) -> Self {
+        Self {
+            code: code.to_string(),
+            message: message.into(),
+            suggested_agent: None,
+        }
+    }
+
+    pub fn for_agent(mut self, agent_type: AgentType) -> Self {
+        self.suggested_agent = Some(agent_type);
+        self
+    }
+}
+
+impl fmt::Display for Recommendation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SimulatedDecision {
+    pub change_id: String,
+    pub old_score: f64,
+    pub new_score: f64,
+    pub old_should_keep: bool,
+    pub new_should_keep: bool,
+}
+
+impl SimulatedDecision {
+    pub fn flipped(&self) -> bool {
+        self.old_should_keep != self.new_should_keep
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SimulationReport {
+    pub decisions: Vec<SimulatedDecision>,
+    pub flipped_to_keep: usize,
+    pub flipped_to_rollback: usize,
+}
+
+impl SimulationReport {
+    pub fn total_flips(&self) -> usize {
+        self.flipped_to_keep + self.flipped_to_rollback
+    }
+}
+
+/// Smallest fraction of its original size a file may shrink to
+const SHRINK_FLOOR: f64 = 0.1;
+
+/// Widths above this many pixels overflow a phone screen
+const MOBILE_WIDTH_LIMIT_PX: f64 = 480.0;
+
+/// WCAG AA contrast ratio for body text
+const MIN_CONTRAST_RATIO: f64 = 4.5;
+
+/// At-rules `validate_css` accepts, without vendor prefixes
+const KNOWN_AT_RULES: &[&str] = &[
+    "charset", "import", "namespace", "media", "supports", "font-face", "keyframes", "page",
+    "layer", "container", "property", "counter-style", "font-feature-values", "font-palette-values",
+    "scope", "starting-style", "document", "viewport",
+    // Feature blocks inside @font-feature-values
+    "swash", "annotation", "ornaments", "stylistic", "styleset", "character-variant",
+    // Margin boxes inside @page
+    "top-left-corner", "top-left", "top-center", "top-right", "top-right-corner",
+    "bottom-left-corner", "bottom-left", "bottom-center", "bottom-right", "bottom-right-corner",
+    "left-top", "left-middle", "left-bottom", "right-top", "right-middle", "right-bottom",
+];
+
+/// Which score a scoring rule adjusts
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScoreKind {
+    Aesthetic,
+    Functionality,
+}
+
+/// What a `ScoringRule` made of a change
+#[derive(Debug, Clone)]
+pub struct RuleOutcome {
+    pub score: ScoreKind,
+    pub delta: f64, // Added to the score; negative to penalize
+    pub issue: Option<String>,
+    pub recommendation: Option<Recommendation>,
+}
+
+impl RuleOutcome {
+    pub fn new(score: ScoreKind, delta: f64) -> Self {
+        Self {
+            score,
+            delta,
+            issue: None,
+            recommendation: None,
+        }
+    }
+
+    /// No effect on the change
+    pub fn pass() -> Self {
+        Self::new(ScoreKind::Functionality, 0.0)
+    }
+
+    pub fn with_issue(mut self, issue: impl Into<String>) -> Self {
+        self.issue = Some(issue.into());
+        self
+    }
+
+    pub fn with_recommendation(mut self, recommendation: Recommendation) -> Self {
+        self.recommendation = Some(recommendation);
+        self
+    }
+}
+
+/// A project-specific check run after the built-in ones, e.g. penalizing
+/// changes that add `!important`
+pub trait ScoringRule: Send + Sync {
+    fn name(&self) -> &str;
+    fn score(&self, change: &Change) -> RuleOutcome;
+}
+
+/// Scoring settings for `ChangeEvaluator::from_config`. Anything left out
+/// keeps its default, including individual weights.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct EvaluatorConfig {
+    #[serde(default)]
+    pub aesthetic_weights: HashMap<String, f64>, // Keyed by `AESTHETIC_CATEGORIES`
+    #[serde(default)]
+    pub functionality_weights: HashMap<String, f64>, // Keyed by `FUNCTIONALITY_CATEGORIES`
+    #[serde(default)]
+    pub min_score_threshold: Option<f64>,
+    #[serde(default)]
+    pub aesthetic_ratio: Option<f64>, // Share of the overall score from aesthetics; functionality gets the rest
+}
+
+/// Share of the overall score from aesthetics unless configured
+const DEFAULT_AESTHETIC_RATIO: f64 = 0.4;
+
+/// Categories of aesthetic signals, each scaled by its weight in
+/// `aesthetic_weights` (1.0 unless configured, 0.0 to ignore it)
+pub const AESTHETIC_CATEGORIES: &[&str] = &["color_scheme", "layout", "motion", "visual_harmony"];
+
+/// Categories of functionality signals, weighted like the aesthetic ones
+pub const FUNCTIONALITY_CATEGORIES: &[&str] = &["accessibility", "performance", "code_quality", "security"];
+
+/// Substrings that add their weight to a score once any of them appears
+/// in a change, scaled by the weight of their category; negative weights
+/// are penalties
+type Signals = &'static [(&'static str, &'static [&'static str], f64)];
+
+// Stylesheets, matched against lowercased content
+const CSS_AESTHETIC_SIGNALS: Signals = &[
+    ("color_scheme", &["var(--", "rgba(", "hsl("], 0.1),
+    ("layout", &["@media", "clamp(", "min(", "max("], 0.1),                 // Responsive design
+    ("motion", &["transition", "animation"], 0.1),
+    ("visual_harmony", &["quantum", "#00d4ff"], 0.1),                       // Theme consistency
+    ("layout", &["display: grid", "display:grid", "display: flex", "display:flex"], 0.1),
+];
+const CSS_FUNCTIONALITY_SIGNALS: Signals = &[
+    ("accessibility", &["prefers-reduced-motion", "prefers-contrast"], 0.15),
+    ("accessibility", &[":focus"], 0.1),
+    ("performance", &["font-display", "will-change", "contain:"], 0.1),     // Rendering performance
+    ("code_quality", &["!important"], -0.05),
+];
+
+// Scripts, matched as written
+const JS_AESTHETIC_SIGNALS: Signals = &[
+    ("motion", &["transition", "animation", "animate("], 0.1),
+    ("visual_harmony", &["classList"], 0.1),                                // Styling through classes, not inline styles
+];
+const JS_FUNCTIONALITY_SIGNALS: Signals = &[
+    ("code_quality", &["try", "catch"], 0.1),                               // Error handling
+    ("code_quality", &["async", "await"], 0.1),
+    ("accessibility", &["aria-", "focus()"], 0.15),
+    ("performance", &["requestAnimationFrame", "debounce", "throttle"], 0.1),
+    ("security", &["escapeHtml", "sanitize", "textContent"], 0.1),
+    ("security", &["eval(", "document.write("], -0.1),
+];
+
+// Pages, matched against lowercased content
+const HTML_AESTHETIC_SIGNALS: Signals = &[
+    ("layout", &["<section", "<article", "<main", "<nav"], 0.1),            // Semantic structure
+    ("layout", &["name=\"viewport\""], 0.1),
+    ("visual_harmony", &["quantum", "#00d4ff"], 0.1),
+    ("layout", &["<picture", "srcset="], 0.1),
+];
+const HTML_FUNCTIONALITY_SIGNALS: Signals = &[
+    ("accessibility", &["aria-", "alt=", "role="], 0.15),
+    ("accessibility", &["<html lang=", " lang=\""], 0.1),
+    ("performance", &["loading=\"lazy\"", " defer", " async"], 0.1),
+    ("security", &["noopener"], 0.1),
+    ("security", &["onclick=", "onload=", "javascript:"], -0.05),          // Inline handlers
+];
+
+// Other files, matched against lowercased content for aesthetics and as
+// written for functionality
+const GENERIC_AESTHETIC_SIGNALS: Signals = &[
+    ("color_scheme", &["var(--", "rgba("], 0.1),
+    ("layout", &["@media", "viewport"], 0.1),
+    ("motion", &["transition", "animation"], 0.1),
+    ("visual_harmony", &["quantum", "#00d4ff"], 0.1),
+    ("layout", &["<section", "<article"], 0.1),
+];
+const GENERIC_FUNCTIONALITY_SIGNALS: Signals = &[
+    ("code_quality", &["try", "catch", "error"], 0.1),
+    ("code_quality", &["async", "await"], 0.1),
+    ("accessibility", &["aria-", "alt=", "role="], 0.15),
+    ("performance", &["requestAnimationFrame", "debounce", "throttle"], 0.1),
+];
+
 pub struct ChangeEvaluator {
     aesthetic_weights: HashMap<String, f64>,
     functionality_weights: HashMap<String, f64>,
     min_score_threshold: f64,
+    aesthetic_ratio: f64,
+    diff_mode: bool,
+    csp_check: bool,
+    mobile_check: bool,
+    tools: Vec<Box<dyn ExternalTool>>,
+    tool_retries: u32,
+    rules: Vec<Box<dyn ScoringRule>>,
 }
 
 impl ChangeEvaluator {
     pub fn new() -> Self {
-        let mut aesthetic_weights = HashMap::new();
-        aesthetic_weights.insert("visual_harmony".to_string(), 0.25);
-        aesthetic_weights.insert("color_scheme".to_string(), 0.20);
-        aesthetic_weights.insert("typography".to_string(), 0.15);
-        aesthetic_weights.insert("spacing".to_string(), 0.15);
-        aesthetic_weights.insert("modern_design".to_string(), 0.25);
-
-        let mut functionality_weights = HashMap::new();
-        functionality_weights.insert("performance".to_string(), 0.30);
-        functionality_weights.insert("accessibility".to_string(), 0.20);
-        functionality_weights.insert("code_quality".to_string(), 0.25);
-        functionality_weights.insert("user_experience".to_string(), 0.25);
+        let unit_weights = |categories: &[&str]| categories.iter().map(|name| (name.to_string(), 1.0)).collect();
 
         Self {
-            aesthetic_weights,
-            functionality_weights,
+            aesthetic_weights: unit_weights(AESTHETIC_CATEGORIES),
+            functionality_weights: unit_weights(FUNCTIONALITY_CATEGORIES),
             min_score_threshold: 0.6, // Minimum score to keep changes
+            aesthetic_ratio: DEFAULT_AESTHETIC_RATIO,
+            diff_mode: false,
+            csp_check: true,
+            mobile_check: true,
+            tools: Vec::new(),
+            tool_retries: 2,
+            rules: Vec::new(),
+        }
+    }
+
+    /// Build an evaluator from a JSON file of `EvaluatorConfig` settings
+    pub fn from_config(path: &Path) -> Result<Self, BrionError> {
+        let config: EvaluatorConfig = serde_json::from_str(&FileOperations::read_file(path)?)
+            .map_err(|e| BrionError::Parse(format!("Evaluator config {}: {}", path.display(), e)))?;
+        Self::new().with_config(config)
+    }
+
+    /// Apply `config` over the current settings, refusing unknown weight
+    /// names, negative weights, and thresholds or ratios outside 0.0-1.0
+    pub fn with_config(mut self, config: EvaluatorConfig) -> Result<Self, BrionError> {
+        let invalid = |message: String| BrionError::Parse(format!("Invalid evaluator config: {}", message));
+        let weights = [
+            ("aesthetic_weights", &config.aesthetic_weights, AESTHETIC_CATEGORIES),
+            ("functionality_weights", &config.functionality_weights, FUNCTIONALITY_CATEGORIES),
+        ];
+        for (field, configured, categories) in weights {
+            for (name, weight) in configured {
+                if !categories.contains(&name.as_str()) {
+                    return Err(invalid(format!("unknown {} entry {}, expected one of {}", field, name, categories.join(", "))));
+                }
+                if !weight.is_finite() || *weight < 0.0 {
+                    return Err(invalid(format!("weight {} is {}, weights must be non-negative", name, weight)));
+                }
+            }
+        }
+        for (name, value) in [("min_score_threshold", config.min_score_threshold), ("aesthetic_ratio", config.aesthetic_ratio)] {
+            if let Some(value) = value {
+                if !(0.0..=1.0).contains(&value) {
+                    return Err(invalid(format!("{} is {}, expected 0.0-1.0", name, value)));
+                }
+            }
+        }
+
+        self.aesthetic_weights.extend(config.aesthetic_weights);
+        self.functionality_weights.extend(config.functionality_weights);
+        if let Some(threshold) = config.min_score_threshold {
+            self.min_score_threshold = threshold;
+        }
+        if let Some(ratio) = config.aesthetic_ratio {
+            self.aesthetic_ratio = ratio;
+        }
+        Ok(self)
+    }
+
+    pub fn with_threshold(mut self, threshold: f64) -> Self {
+        self.min_score_threshold = threshold;
+        self
+    }
+
+    pub fn set_threshold(&mut self, threshold: f64) {
+        self.min_score_threshold = threshold;
+    }
+
+    pub fn get_threshold(&self) -> f64 {
+        self.min_score_threshold
+    }
+
+    /// Score content signals against the lines the change added rather than
+    /// the whole file, so credit and blame track what the change did
+    pub fn with_diff_mode(mut self, enabled: bool) -> Self {
+        self.diff_mode = enabled;
+        self
+    }
+
+    /// Penalize inline scripts, styles, and event handlers added to a page
+    /// whose Content-Security-Policy doesn't allow `unsafe-inline`
+    pub fn with_csp_check(mut self, enabled: bool) -> Self {
+        self.csp_check = enabled;
+        self
+    }
+
+    /// Penalize pages without a `width=device-width` viewport and fixed
+    /// widths too wide for a phone
+    pub fn with_mobile_check(mut self, enabled: bool) -> Self {
+        self.mobile_check = enabled;
+        self
+    }
+
+    /// Hard checks that hold regardless of heuristic score: the path stays
+    /// inside the project, brackets stay balanced, and the file isn't gutted
+    pub fn safety_guards(change: &Change) -> Vec<String> {
+        let mut failures = Vec::new();
+
+        let path = std::path::Path::new(&change.file_path);
+        if path.is_absolute() || path.components().any(|c| c == std::path::Component::ParentDir) {
+            failures.push(format!("Path {} escapes the project directory", change.file_path));
+        }
+
+        let balanced = |content: &str| content.matches('{').count() == content.matches('}').count()
+            && content.matches('(').count() == content.matches(')').count();
+        if balanced(&change.before) && !balanced(&change.after) {
+            failures.push("Change leaves unbalanced brackets".to_string());
+        }
+
+        if !change.before.is_empty() && (change.after.len() as f64) < change.before.len() as f64 * SHRINK_FLOOR {
+            failures.push(format!("File shrank below {:.0}% of its original size", SHRINK_FLOOR * 100.0));
+        }
+
+        failures
+    }
+
+    /// Result for a trusted agent's change: skip the heuristics and keep it
+    /// unless a safety guard trips
+    pub fn evaluate_trusted(&self, change: &Change) -> EvaluationResult {
+        let issues = Self::safety_guards(change);
+        let should_keep = issues.is_empty();
+        let score = if should_keep { 1.0 } else { 0.0 };
+
+        EvaluationResult {
+            change_id: change.id.clone(),
+            aesthetic_score: score,
+            functionality_score: score,
+            overall_score: score,
+            recommendations: if should_keep { Vec::new() } else { vec![Recommendation::new("rollback", "Consider rolling back this change")] },
+            issues,
+            should_keep,
+        }
+    }
+
+    /// Validate changed files with an external checker. A tool that can't
+    /// run is skipped rather than counted against the change.
+    pub fn with_external_tool(mut self, tool: Box<dyn ExternalTool>) -> Self {
+        self.tools.push(tool);
+        self
+    }
+
+    /// Run `rule` on every evaluated change, after the built-in checks
+    pub fn register_rule(&mut self, rule: Box<dyn ScoringRule>) {
+        self.rules.push(rule);
+    }
+
+    /// Extra attempts for a tool that fails to run
+    pub fn with_tool_retries(mut self, retries: u32) -> Self {
+        self.tool_retries = retries;
+        self
+    }
+
+    /// The policy from a `<meta http-equiv="Content-Security-Policy">` tag
+    pub fn csp_policy(content: &str) -> Option<String> {
+        // ASCII lowercasing keeps byte offsets valid for slicing `content`
+        let lower = content.to_ascii_lowercase();
+        let marker = lower.find("content-security-policy")?;
+        let tag_start = lower[..marker].rfind('<')?;
+        let tag_end = marker + lower[marker..].find('>')?;
+        let tag = &content[tag_start..tag_end];
+
+        let attr = tag.to_ascii_lowercase().find("content=")? + "content=".len();
+        let quote = tag[attr..].chars().next()?;
+        if quote != '"' && quote != '\'' {
+            return None;
+        }
+        let value_end = tag[attr + 1..].find(quote)?;
+        Some(tag[attr + 1..attr + 1 + value_end].to_string())
+    }
+
+    /// Inline code the change added that a strict CSP would block
+    pub fn csp_violations(change: &Change) -> Vec<String> {
+        let strict = match Self::csp_policy(&change.after) {
+            Some(policy) => !policy.to_lowercase().contains("'unsafe-inline'"),
+            None => false,
+        };
+        if !strict {
+            return Vec::new();
+        }
+
+        let added = Self::added_lines(&change.before, &change.after).to_lowercase();
+        let mut violations = Vec::new();
+
+        let mut rest = added.as_str();
+        while let Some(pos) = rest.find("<script") {
+            let tag = &rest[pos..pos + rest[pos..].find('>').unwrap_or(rest.len() - pos)];
+            if !tag.contains("src=") {
+                violations.push("Inline <script> blocked by Content-Security-Policy".to_string());
+            }
+            rest = &rest[pos + "<script".len()..];
+        }
+
+        for _ in added.matches("<style") {
+            violations.push("Inline <style> blocked by Content-Security-Policy".to_string());
+        }
+        for _ in added.matches(" style=") {
+            violations.push("Inline style attribute blocked by Content-Security-Policy".to_string());
+        }
+
+        let mut rest = added.as_str();
+        while let Some(pos) = rest.find(" on") {
+            let after_on = &rest[pos + 3..];
+            let name: String = after_on.chars().take_while(|c| c.is_ascii_alphabetic()).collect();
+            if name.len() >= 3 && after_on[name.len()..].trim_start().starts_with('=') {
+                violations.push(format!("Inline event handler on{} blocked by Content-Security-Policy", name));
+            }
+            rest = after_on;
+        }
+
+        violations
+    }
+
+    /// What's wrong with the viewport of an HTML page, if anything. Pages
+    /// without a `<head>` (fragments, templates) aren't checked.
+    pub fn viewport_issue(change: &Change) -> Option<String> {
+        let path = std::path::Path::new(&change.file_path);
+        let lower = change.after.to_lowercase();
+        if FileOperations::file_kind(path, &change.after) != FileKind::Html || !lower.contains("<head") {
+            return None;
+        }
+
+        let viewport = lower.match_indices("<meta").find_map(|(start, _)| {
+            let end = start + lower[start..].find('>')? + 1;
+            let attributes = HtmlHead::attributes(&lower[start..end]);
+            let is_viewport = attributes.iter().any(|(name, value)| name == "name" && value.trim() == "viewport");
+            if !is_viewport {
+                return None;
+            }
+            Some(attributes.into_iter()
+                .find(|(name, _)| name == "content")
+                .map(|(_, value)| value)
+                .unwrap_or_default())
+        });
+
+        let content = match viewport {
+            Some(content) => content,
+            None => return Some("Page has no viewport meta tag".to_string()),
+        };
+        let settings: Vec<String> = content.split(',').map(|s| s.split_whitespace().collect()).collect();
+        if !settings.iter().any(|s| s == "width=device-width") {
+            Some("Viewport meta tag doesn't set width=device-width".to_string())
+        } else if settings.iter().any(|s| s == "user-scalable=no" || s == "maximum-scale=1" || s == "maximum-scale=1.0") {
+            Some("Viewport meta tag prevents zooming".to_string())
+        } else {
+            None
+        }
+    }
+
+    /// Fixed `width` declarations the change added that are wider than a
+    /// phone screen, e.g. `width: 1200px` on the body
+    pub fn fixed_widths(change: &Change) -> Vec<String> {
+        let added = Self::added_lines(&change.before, &change.after).to_lowercase();
+        let mut found = Vec::new();
+        for (start, _) in added.match_indices("width") {
+            // Only the `width` property itself, not max-width, min-width or border-width
+            let whole_property = added[..start].chars().next_back()
+                .map(|c| c.is_whitespace() || c == '{' || c == ';' || c == '"')
+                .unwrap_or(true);
+            let value = match added[start + "width".len()..].trim_start().strip_prefix(':') {
+                Some(value) if whole_property => value.trim_start(),
+                _ => continue,
+            };
+            let number: String = value.chars().take_while(|c| c.is_ascii_digit() || *c == '.').collect();
+            let is_px = value[number.len()..].starts_with("px");
+            if let (Ok(px), true) = (number.parse::<f64>(), is_px) {
+                if px > MOBILE_WIDTH_LIMIT_PX {
+                    found.push(format!("Fixed width of {}px overflows small screens", number));
+                }
+            }
+        }
+        found
+    }
+
+    /// Rules setting both `color` and `background`/`background-color` to hex
+    /// colors whose WCAG contrast ratio is below `MIN_CONTRAST_RATIO`
+    pub fn low_contrast(content: &str) -> Vec<String> {
+        let lower = content.to_lowercase();
+        let mut found = Vec::new();
+        for body in lower.split('{').skip(1).filter_map(|rest| rest.split('}').next()) {
+            let (mut foreground, mut background) = (None, None);
+            for declaration in body.split(';') {
+                let (property, value) = match declaration.split_once(':') {
+                    Some(pair) => pair,
+                    None => continue,
+                };
+                let color = value.split_whitespace().find_map(Self::hex_color);
+                match property.trim() {
+                    "color" => foreground = color.or(foreground),
+                    "background" | "background-color" => background = color.or(background),
+                    _ => {}
+                }
+            }
+            if let (Some(foreground), Some(background)) = (foreground, background) {
+                let ratio = Self::contrast_ratio(foreground, background);
+                if ratio < MIN_CONTRAST_RATIO {
+                    found.push(format!(
+                        "Text color #{:02x}{:02x}{:02x} on #{:02x}{:02x}{:02x} has a contrast of {:.2}:1, below {}:1",
+                        foreground[0], foreground[1], foreground[2],
+                        background[0], background[1], background[2],
+                        ratio, MIN_CONTRAST_RATIO,
+                    ));
+                }
+            }
+        }
+        found
+    }
+
+    /// RGB channels of a `#rgb` or `#rrggbb` color
+    fn hex_color(value: &str) -> Option<[u8; 3]> {
+        let hex = value.strip_prefix('#')?;
+        if !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+            return None;
+        }
+        let channel = |digits: &str| u8::from_str_radix(digits, 16).ok();
+        match hex.len() {
+            3 => {
+                let mut rgb = [0; 3];
+                for (i, digit) in hex.chars().enumerate() {
+                    rgb[i] = channel(&digit.to_string().repeat(2))?;
+                }
+                Some(rgb)
+            }
+            6 => Some([channel(&hex[0..2])?, channel(&hex[2..4])?, channel(&hex[4..6])?]),
+            _ => None,
+        }
+    }
+
+    /// WCAG contrast ratio between two colors, from 1.0 to 21.0
+    fn contrast_ratio(a: [u8; 3], b: [u8; 3]) -> f64 {
+        let luminance = |rgb: [u8; 3]| {
+            let linear = |channel: u8| {
+                let c = channel as f64 / 255.0;
+                if c <= 0.03928 { c / 12.92 } else { ((c + 0.055) / 1.055).powf(2.4) }
+            };
+            0.2126 * linear(rgb[0]) + 0.7152 * linear(rgb[1]) + 0.0722 * linear(rgb[2])
+        };
+        let (a, b) = (luminance(a), luminance(b));
+        (a.max(b) + 0.05) / (a.min(b) + 0.05)
+    }
+
+    /// Syntax problems in a stylesheet: unbalanced braces, parentheses and
+    /// brackets, rules that never get a body, unterminated comments and
+    /// strings, and unknown at-rules
+    pub fn validate_css(content: &str) -> Vec<String> {
+        let chars: Vec<char> = content.chars().collect();
+        let mut problems = Vec::new();
+        let mut open: Vec<(char, usize)> = Vec::new(); // Bracket and the line it opened on
+        let mut rule_start: Option<usize> = None;      // Top-level text not yet ended by a body or `;`
+        let mut line = 1;
+        let mut i = 0;
+
+        while i < chars.len() {
+            let c = chars[i];
+            match c {
+                '\n' => line += 1,
+                '/' if chars.get(i + 1) == Some(&'*') => {
+                    let start_line = line;
+                    i += 2;
+                    while i < chars.len() && !(chars[i] == '*' && chars.get(i + 1) == Some(&'/')) {
+                        if chars[i] == '\n' {
+                            line += 1;
+                        }
+                        i += 1;
+                    }
+                    if i >= chars.len() {
+                        problems.push(format!("Unterminated comment starting on line {}", start_line));
+                    }
+                    i += 2;
+                    continue;
+                }
+                '"' | '\'' => {
+                    i += 1;
+                    while i < chars.len() && chars[i] != c && chars[i] != '\n' {
+                        if chars[i] == '\\' {
+                            i += 1;
+                            if chars.get(i) == Some(&'\n') {
+                                line += 1;
+                            }
+                        }
+                        i += 1;
+                    }
+                    if i >= chars.len() || chars[i] == '\n' {
+                        problems.push(format!("Unterminated string on line {}", line));
+                        continue;
+                    }
+                }
+                // `@2x` in an unquoted url() isn't an at-rule
+                '@' if open.last().map(|(o, _)| *o != '(').unwrap_or(true)
+                    && chars.get(i + 1).map(|n| n.is_ascii_alphabetic() || *n == '-').unwrap_or(false) =>
+                {
+                    let name: String = chars[i + 1..].iter()
+                        .take_while(|n| n.is_ascii_alphanumeric() || **n == '-')
+                        .collect();
+                    let lower = name.to_ascii_lowercase();
+                    let unprefixed = match lower.strip_prefix('-').and_then(|rest| rest.split_once('-')) {
+                        Some((_, rest)) => rest,
+                        None => lower.as_str(),
+                    };
+                    if !KNOWN_AT_RULES.contains(&unprefixed) {
+                        problems.push(format!("Unknown at-rule @{} on line {}", name, line));
+                    }
+                    if open.is_empty() {
+                        rule_start.get_or_insert(line);
+                    }
+                    i += 1 + name.len();
+                    continue;
+                }
+                '{' | '(' | '[' => {
+                    if c == '{' && open.is_empty() {
+                        rule_start = None;
+                    }
+                    open.push((c, line));
+                }
+                '}' | ')' | ']' => {
+                    let opener = match c {
+                        '}' => '{',
+                        ')' => '(',
+                        _ => '[',
+                    };
+                    // Anything opened after the matching bracket was never closed
+                    match open.iter().rposition(|(o, _)| *o == opener) {
+                        Some(position) => {
+                            for (unclosed, opened) in open.drain(position..).skip(1) {
+                                problems.push(format!("Unclosed '{}' opened on line {}", unclosed, opened));
+                            }
+                        }
+                        None => problems.push(format!("Unexpected '{}' on line {}", c, line)),
+                    }
+                }
+                ';' if open.is_empty() => rule_start = None,
+                c if open.is_empty() && !c.is_whitespace() => {
+                    rule_start.get_or_insert(line);
+                }
+                _ => {}
+            }
+            i += 1;
+        }
+
+        for (unclosed, opened) in open {
+            if unclosed == '{' {
+                problems.push(format!("Unterminated rule: '{{' opened on line {} is never closed", opened));
+            } else {
+                problems.push(format!("Unclosed '{}' opened on line {}", unclosed, opened));
+            }
+        }
+        if let Some(start) = rule_start {
+            problems.push(format!("Rule starting on line {} has no body", start));
+        }
+        problems
+    }
+
+    /// Lines present in `after` that weren't in `before`
+    pub fn added_lines(before: &str, after: &str) -> String {
+        let mut remaining: HashMap<&str, usize> = HashMap::new();
+        for line in before.lines() {
+            *remaining.entry(line).or_insert(0) += 1;
+        }
+
+        let mut added = Vec::new();
+        for line in after.lines() {
+            match remaining.get_mut(line) {
+                Some(count) if *count > 0 => *count -= 1,
+                _ => added.push(line),
+            }
+        }
+        added.join("\n")
+    }
+
+    fn signal_content(&self, change: &Change) -> String {
+        if self.diff_mode {
+            Self::added_lines(&change.before, &change.after)
+        } else {
+            change.after.clone()
         }
     }
 
     pub fn evaluate_change(&self, change: &Change) -> EvaluationResult {
-        let aesthetic_score = self.evaluate_aesthetics(change);
-        let functionality_score = self.evaluate_functionality(change);
-        let overall_score = (aesthetic_score * 0.4 + functionality_score * 0.6);
-        
+        let (mut aesthetic_score, mut functionality_score) = self.score_by_language(change);
+
         let mut issues = Vec::new();
         let mut recommendations = Vec::new();
 
+        // Inline code that a strict CSP on the page would block at runtime
+        let csp_violations = if self.csp_check { Self::csp_violations(change) } else { Vec::new() };
+        if !csp_violations.is_empty() {
+            let penalty = (0.1 * csp_violations.len() as f64).min(0.3);
+            functionality_score = (functionality_score - penalty).max(0.0);
+            recommendations.push(
+                Recommendation::new("externalize-inline-code", "Move inline scripts, styles, and handlers to external files")
+                    .for_agent(AgentType::SecurityAgent)
+            );
+            issues.extend(csp_violations);
+        }
+
+        // Layouts that won't fit or scale on a phone
+        if self.mobile_check {
+            let viewport_issue = Self::viewport_issue(change);
+            let fixed_widths = Self::fixed_widths(change);
+            let count = usize::from(viewport_issue.is_some()) + fixed_widths.len();
+            if count > 0 {
+                functionality_score = (functionality_score - (0.1 * count as f64).min(0.3)).max(0.0);
+            }
+            if let Some(issue) = viewport_issue {
+                issues.push(issue);
+                recommendations.push(
+                    Recommendation::new("add-viewport", "Add <meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">")
+                        .for_agent(AgentType::UIAgent)
+                );
+            }
+            if !fixed_widths.is_empty() {
+                issues.extend(fixed_widths);
+                recommendations.push(
+                    Recommendation::new("responsive-widths", "Use max-width or relative units instead of fixed pixel widths")
+                        .for_agent(AgentType::UIAgent)
+                );
+            }
+        }
+
+        // Broken stylesheets; problems the file already had aren't held against the change
+        if FileKind::from_extension(std::path::Path::new(&change.file_path)) == FileKind::Css {
+            let problems = Self::validate_css(&change.after);
+            if problems.len() > Self::validate_css(&change.before).len() {
+                functionality_score = (functionality_score - (0.1 * problems.len() as f64).min(0.3)).max(0.0);
+                issues.extend(problems);
+                recommendations.push(
+                    Recommendation::new("fix-css-syntax", "Fix the CSS syntax errors before deployment")
+                        .for_agent(AgentType::UIAgent)
+                );
+            }
+        }
+
+        // Text that's hard to read against its background; pairs the file already had aren't counted
+        let mut existing_contrast = Self::low_contrast(&change.before);
+        let low_contrast: Vec<String> = Self::low_contrast(&change.after).into_iter()
+            .filter(|issue| match existing_contrast.iter().position(|existing| existing == issue) {
+                Some(position) => {
+                    existing_contrast.swap_remove(position);
+                    false
+                }
+                None => true,
+            })
+            .collect();
+        if !low_contrast.is_empty() {
+            functionality_score = (functionality_score - (0.1 * low_contrast.len() as f64).min(0.3)).max(0.0);
+            issues.extend(low_contrast);
+            recommendations.push(
+                Recommendation::new("improve-contrast", format!("Raise text contrast to at least {}:1 against its background", MIN_CONTRAST_RATIO))
+                    .for_agent(AgentType::AccessibilityAgent)
+            );
+        }
+
+        for tool in self.tools.iter().filter(|t| t.applies_to(&change.file_path)) {
+            match check_with_retry(tool.as_ref(), &change.after, self.tool_retries) {
+                ToolOutcome::Passed => {}
+                ToolOutcome::ParseError(message) => {
+                    functionality_score = (functionality_score - 0.2).max(0.0);
+                    issues.push(format!("{} reported errors: {}", tool.name(), message));
+                    recommendations.push(Recommendation::new("fix-tool-errors", format!("Fix the {} errors before deployment", tool.name())));
+                }
+                ToolOutcome::Unavailable(reason) => {
+                    warn!("Skipping {} for change {}: {}", tool.name(), change.id, reason);
+                }
+            }
+        }
+
+        for rule in &self.rules {
+            let outcome = rule.score(change);
+            let score = match outcome.score {
+                ScoreKind::Aesthetic => &mut aesthetic_score,
+                ScoreKind::Functionality => &mut functionality_score,
+            };
+            *score = (*score + outcome.delta).clamp(0.0, 1.0);
+            issues.extend(outcome.issue.map(|issue| format!("{}: {}", rule.name(), issue)));
+            recommendations.extend(outcome.recommendation);
+        }
+
+        let overall_score = aesthetic_score * self.aesthetic_ratio + functionality_score * (1.0 - self.aesthetic_ratio);
+
         // Analyze issues
         if aesthetic_score < 0.5 {
             issues.push("Aesthetic quality below acceptable threshold".to_string());
-            recommendations.push("Review color scheme and visual design".to_string());
+            recommendations.push(
+                Recommendation::new("review-design", "Review color scheme and visual design").for_agent(AgentType::DesignAgent)
+            );
         }
 
         if functionality_score < 0.5 {
             issues.push("Functionality concerns detected".to_string());
-            recommendations.push("Review code quality and performance impact".to_string());
+            recommendations.push(Recommendation::new("review-functionality", "Review code quality and performance impact"));
         }
 
         if overall_score < self.min_score_threshold {
             issues.push("Overall score below minimum threshold".to_string());
-            recommendations.push("Consider rolling back this change".to_string());
+            recommendations.push(Recommendation::new("rollback", "Consider rolling back this change"));
         }
 
         // Check for specific patterns
         if change.after.len() > change.before.len() * 2 {
-            issues.push("Significant size increase detected".to_string());
-            recommendations.push("Consider optimization".to_string());
+            let (added, removed) = change.diff_stat();
+            issues.push(format!("Significant size increase detected (+{} -{} lines)", added, removed));
+            recommendations.push(Recommendation::new("optimize-size", "Consider optimization").for_agent(AgentType::PerformanceAgent));
+        }
+
+        if FileOperations::has_bom(&change.before) && !FileOperations::has_bom(&change.after) {
+            issues.push("Change removes the UTF-8 byte order mark".to_string());
+            recommendations.push(Recommendation::new("preserve-bom", "Preserve the BOM for files that require it"));
         }
 
         if change.after.contains("TODO") || change.after.contains("FIXME") {
             issues.push("Incomplete code detected".to_string());
-            recommendations.push("Complete implementation before deployment".to_string());
+            recommendations.push(Recommendation::new("complete-implementation", "Complete implementation before deployment"));
         }
 
         EvaluationResult {
@@ -90,81 +882,121 @@ impl ChangeEvaluator {
         }
     }
 
-    fn evaluate_aesthetics(&self, change: &Change) -> f64 {
-        let mut score = 0.5; // Base score
-
-        // Analyze content for aesthetic indicators
-        let content = &change.after.to_lowercase();
-
-        // Check for modern CSS features
-        if content.contains("var(--") || content.contains("rgba(") {
-            score += 0.1;
+    /// Aesthetic and functionality scores from the heuristics for the
+    /// change's language, or the generic ones for other files
+    fn score_by_language(&self, change: &Change) -> (f64, f64) {
+        match FileOperations::file_kind(std::path::Path::new(&change.file_path), &change.after) {
+            FileKind::Css => self.evaluate_css(change),
+            FileKind::Js => self.evaluate_js(change),
+            FileKind::Html => self.evaluate_html(change),
+            FileKind::Json | FileKind::Other => (self.evaluate_aesthetics(change), self.evaluate_functionality(change)),
         }
+    }
 
-        // Check for responsive design
-        if content.contains("@media") || content.contains("viewport") {
-            score += 0.1;
-        }
+    /// 0.5 plus the weight of every signal found in `content`, each scaled
+    /// by its category's entry in `weights`
+    fn score_signals(content: &str, signals: Signals, weights: &HashMap<String, f64>) -> f64 {
+        let bonus: f64 = signals.iter()
+            .filter(|(_, patterns, _)| patterns.iter().any(|pattern| content.contains(pattern)))
+            .map(|(category, _, weight)| weight * Self::weight(weights, category))
+            .sum();
+        (0.5 + bonus).clamp(0.0, 1.0)
+    }
 
-        // Check for animations/transitions
-        if content.contains("transition") || content.contains("animation") {
-            score += 0.1;
-        }
+    /// How much `category` counts for; categories never configured count fully
+    fn weight(weights: &HashMap<String, f64>, category: &str) -> f64 {
+        weights.get(category).copied().unwrap_or(1.0)
+    }
 
-        // Check for quantum theme consistency
-        if content.contains("quantum") || content.contains("#00d4ff") {
-            score += 0.1;
+    /// Bonus for balanced braces over the whole file, however much of it
+    /// the change touched
+    fn structure_bonus(&self, change: &Change) -> f64 {
+        if change.after.matches('{').count() == change.after.matches('}').count() {
+            0.05 * Self::weight(&self.functionality_weights, "code_quality")
+        } else {
+            0.0
         }
+    }
 
-        // Check for semantic HTML
-        if content.contains("<section") || content.contains("<article") {
-            score += 0.1;
+    /// Penalty for leftover `console.log` calls not marked as debugging
+    fn console_penalty(&self, content: &str) -> f64 {
+        if content.contains("console.log") && !content.contains("// debug") {
+            0.05 * Self::weight(&self.functionality_weights, "code_quality")
+        } else {
+            0.0
         }
-
-        score.min(1.0)
     }
 
-    fn evaluate_functionality(&self, change: &Change) -> f64 {
-        let mut score = 0.5; // Base score
+    fn evaluate_css(&self, change: &Change) -> (f64, f64) {
+        let content = self.signal_content(change).to_lowercase();
+        let aesthetic = Self::score_signals(&content, CSS_AESTHETIC_SIGNALS, &self.aesthetic_weights);
+        let functionality = Self::score_signals(&content, CSS_FUNCTIONALITY_SIGNALS, &self.functionality_weights);
 
-        let content = &change.after;
+        // `validate_css` penalizes breakage separately
+        (aesthetic, (functionality + self.structure_bonus(change)).min(1.0))
+    }
 
-        // Check for error handling
-        if content.contains("try") || content.contains("catch") || content.contains("error") {
-            score += 0.1;
-        }
+    fn evaluate_js(&self, change: &Change) -> (f64, f64) {
+        let content = self.signal_content(change);
+        let aesthetic = Self::score_signals(&content, JS_AESTHETIC_SIGNALS, &self.aesthetic_weights);
+        let functionality = Self::score_signals(&content, JS_FUNCTIONALITY_SIGNALS, &self.functionality_weights)
+            + self.structure_bonus(change)
+            - self.console_penalty(&content);
+        (aesthetic, functionality.clamp(0.0, 1.0))
+    }
 
-        // Check for async/await (modern JavaScript)
-        if content.contains("async") || content.contains("await") {
-            score += 0.1;
-        }
+    fn evaluate_html(&self, change: &Change) -> (f64, f64) {
+        let content = self.signal_content(change).to_lowercase();
+        (
+            Self::score_signals(&content, HTML_AESTHETIC_SIGNALS, &self.aesthetic_weights),
+            Self::score_signals(&content, HTML_FUNCTIONALITY_SIGNALS, &self.functionality_weights),
+        )
+    }
 
-        // Check for accessibility
-        if content.contains("aria-") || content.contains("alt=") || content.contains("role=") {
-            score += 0.15;
-        }
+    fn evaluate_aesthetics(&self, change: &Change) -> f64 {
+        let content = self.signal_content(change).to_lowercase();
+        Self::score_signals(&content, GENERIC_AESTHETIC_SIGNALS, &self.aesthetic_weights)
+    }
 
-        // Check for performance optimizations
-        if content.contains("requestAnimationFrame") || content.contains("debounce") || content.contains("throttle") {
-            score += 0.1;
-        }
+    fn evaluate_functionality(&self, change: &Change) -> f64 {
+        let content = self.signal_content(change);
+        let mut score = Self::score_signals(&content, GENERIC_FUNCTIONALITY_SIGNALS, &self.functionality_weights);
 
-        // Check for security
+        // Sanitized output, or no raw HTML written at all
         if content.contains("escapeHtml") || content.contains("sanitize") || !content.contains("innerHTML") {
-            score += 0.1;
+            score += 0.1 * Self::weight(&self.functionality_weights, "security");
         }
+        score += self.structure_bonus(change) - self.console_penalty(&content);
 
-        // Check for code quality (proper structure)
-        if content.matches('{').count() == content.matches('}').count() {
-            score += 0.05;
-        }
+        score.clamp(0.0, 1.0)
+    }
 
-        // Penalize for obvious issues
-        if content.contains("console.log") && !content.contains("// debug") {
-            score -= 0.05;
+    /// Replay historical changes through this evaluator and `baseline`
+    /// (typically the config currently in production), reporting which
+    /// keep/rollback decisions would flip
+    pub fn simulate(&self, baseline: &ChangeEvaluator, changes: &[Change]) -> SimulationReport {
+        let mut report = SimulationReport::default();
+
+        for change in changes {
+            let old = baseline.evaluate_change(change);
+            let new = self.evaluate_change(change);
+            let decision = SimulatedDecision {
+                change_id: change.id.clone(),
+                old_score: old.overall_score,
+                new_score: new.overall_score,
+                old_should_keep: old.should_keep,
+                new_should_keep: new.should_keep,
+            };
+
+            match (decision.old_should_keep, decision.new_should_keep) {
+                (false, true) => report.flipped_to_keep += 1,
+                (true, false) => report.flipped_to_rollback += 1,
+                _ => {}
+            }
+            report.decisions.push(decision);
         }
 
-        score.min(1.0).max(0.0)
+        report
     }
 
     pub fn compare_changes(&self, old_change: &Change, new_change: &Change) -> EvaluationResult {
@@ -177,7 +1009,7 @@ impl ChangeEvaluator {
         // Check if new change is an improvement
         let old_eval = self.evaluate_change(old_change);
         if result.overall_score > old_eval.overall_score {
-            result.recommendations.push("This change improves upon the previous version".to_string());
+            result.recommendations.push(Recommendation::new("improvement", "This change improves upon the previous version"));
         } else if result.overall_score < old_eval.overall_score {
             result.issues.push("This change may be a regression".to_string());
             result.should_keep = false;
@@ -193,3 +1025,203 @@ impl Default for ChangeEvaluator {
     }
 }
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn change(file: &str, before: &str, after: &str) -> Change {
+        FileOperations::create_change(
+            "test-agent",
+            "UIAgent",
+            file.to_string(),
+            crate::agents::version_control::ChangeType::Modify,
+            before.to_string(),
+            after.to_string(),
+        )
+    }
+
+    const STRICT_PAGE: &str = "<html><head>\n<meta http-equiv=\"Content-Security-Policy\" content=\"default-src 'self'\">\n</head><body>\n<button>Go</button>\n</body></html>";
+
+    #[test]
+    fn inline_handler_under_strict_csp_is_penalized() {
+        let with_handler = STRICT_PAGE.replace("<button>", "<button onclick=\"go()\">");
+        let evaluator = ChangeEvaluator::new().with_mobile_check(false);
+        let checked = evaluator.evaluate_change(&change("index.html", STRICT_PAGE, &with_handler));
+        let unchecked = evaluator.with_csp_check(false).evaluate_change(&change("index.html", STRICT_PAGE, &with_handler));
+
+        assert!(checked.functionality_score < unchecked.functionality_score);
+        assert!(checked.issues.iter().any(|issue| issue.contains("onclick")));
+    }
+
+    #[test]
+    fn csp_policy_survives_non_ascii_text_before_the_tag() {
+        let page = format!("<p>\u{130}stanbul</p>{}", STRICT_PAGE);
+        assert_eq!(ChangeEvaluator::csp_policy(&page), Some("default-src 'self'".to_string()));
+    }
+
+    #[test]
+    fn low_contrast_change_is_left_to_the_accessibility_agent() {
+        let before = ".card {\n  color: #222;\n  background-color: #fff;\n}\n";
+        let after = ".card {\n  color: #999;\n  background-color: #aaa;\n}\n";
+        let evaluation = ChangeEvaluator::new().evaluate_change(&change("style.css", before, after));
+
+        let contrast = evaluation.recommendations.iter().find(|r| r.code == "improve-contrast")
+            .expect("low contrast should be recommended against");
+        assert_eq!(contrast.suggested_agent, Some(AgentType::AccessibilityAgent));
+        assert!(ChangeEvaluator::low_contrast(before).is_empty());
+        assert_eq!(ChangeEvaluator::low_contrast(".x { color: #fff; background: #ffffff url(bg.png); }").len(), 1);
+    }
+
+    #[test]
+    fn configured_weights_scale_their_signals() {
+        let before = "body { color: #222; }\n";
+        let after = "body { color: #222; }\n:focus { outline: 2px solid; }\n";
+        let config = |weight: f64| EvaluatorConfig {
+            functionality_weights: HashMap::from([("accessibility".to_string(), weight)]),
+            ..EvaluatorConfig::default()
+        };
+        let score = |weight: f64| ChangeEvaluator::new().with_config(config(weight)).unwrap()
+            .evaluate_change(&change("style.css", before, after)).functionality_score;
+
+        assert!(score(0.0) < score(1.0));
+        assert!(score(1.0) < score(2.0));
+
+        let unknown = EvaluatorConfig {
+            aesthetic_weights: HashMap::from([("typography".to_string(), 1.0)]),
+            ..EvaluatorConfig::default()
+        };
+        assert!(ChangeEvaluator::new().with_config(unknown).is_err());
+    }
+
+    #[test]
+    fn diff_mode_blames_only_the_console_log_a_change_adds() {
+        let excellent = "async function load() {\n  try {\n    const data = await fetch('/api');\n    el.textContent = sanitize(data);\n    requestAnimationFrame(() => el.focus());\n  } catch (e) {\n    el.setAttribute('aria-live', 'polite');\n  }\n}\n";
+        let logged = format!("{}try {{ await load(); }} catch (e) {{ console.log(e); }}\n", excellent);
+        let reported = format!("{}try {{ await load(); }} catch (e) {{ report(e); }}\n", excellent);
+        let evaluator = ChangeEvaluator::new().with_diff_mode(true);
+
+        let penalized = evaluator.evaluate_change(&change("app.js", excellent, &logged));
+        let clean = evaluator.evaluate_change(&change("app.js", excellent, &reported));
+        assert!(penalized.functionality_score < clean.functionality_score);
+
+        // A console.log that was already there isn't the change's fault
+        let inherited = format!("{}console.log('ready');\n", excellent);
+        let untouched = evaluator.evaluate_change(&change("app.js", &inherited, &format!("{}try {{ await load(); }} catch (e) {{ report(e); }}\n", inherited)));
+        assert_eq!(untouched.functionality_score, clean.functionality_score);
+    }
+
+    /// Sinks any change that adds `reject-me`
+    struct RejectMarked;
+
+    impl ScoringRule for RejectMarked {
+        fn name(&self) -> &str {
+            "reject-marked"
+        }
+
+        fn score(&self, change: &Change) -> RuleOutcome {
+            if ChangeEvaluator::added_lines(&change.before, &change.after).contains("reject-me") {
+                RuleOutcome::new(ScoreKind::Functionality, -1.0).with_issue("marked for rejection")
+            } else {
+                RuleOutcome::pass()
+            }
+        }
+    }
+
+    #[test]
+    fn simulation_reports_exactly_the_decisions_a_new_config_flips() {
+        let page = "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<title>Home</title>\n</head>\n<body>\n<h1>Welcome</h1>\n</body>\n</html>\n";
+        let changes: Vec<Change> = ["kept", "reject-me", "also kept"].iter()
+            .map(|stamp| change("index.html", page, &format!("{}<!-- {} -->\n", page, stamp)))
+            .collect();
+        let baseline = ChangeEvaluator::new().with_threshold(0.3);
+        let mut stricter = ChangeEvaluator::new().with_threshold(0.3);
+        stricter.register_rule(Box::new(RejectMarked));
+
+        let report = stricter.simulate(&baseline, &changes);
+
+        assert_eq!(report.decisions.len(), 3);
+        assert!(report.decisions.iter().all(|decision| decision.old_should_keep));
+        let flipped: Vec<&str> = report.decisions.iter()
+            .filter(|decision| decision.flipped())
+            .map(|decision| decision.change_id.as_str())
+            .collect();
+        assert_eq!(flipped, vec![changes[1].id.as_str()]);
+        assert!(report.decisions[1].new_score < report.decisions[1].old_score);
+        assert_eq!((report.flipped_to_keep, report.flipped_to_rollback), (0, 1));
+        assert_eq!(report.total_flips(), 1);
+    }
+
+    /// Answers with `outcome` once it has been unavailable `failures` times
+    struct FlakyTool {
+        failures: usize,
+        outcome: ToolOutcome,
+        calls: std::sync::atomic::AtomicUsize,
+    }
+
+    impl FlakyTool {
+        fn boxed(failures: usize, outcome: ToolOutcome) -> Box<dyn ExternalTool> {
+            Box::new(FlakyTool { failures, outcome, calls: std::sync::atomic::AtomicUsize::new(0) })
+        }
+    }
+
+    impl ExternalTool for FlakyTool {
+        fn name(&self) -> &str {
+            "flaky"
+        }
+
+        fn applies_to(&self, file_path: &str) -> bool {
+            file_path.ends_with(".css")
+        }
+
+        fn check(&self, _content: &str) -> ToolOutcome {
+            let call = self.calls.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            if call < self.failures {
+                ToolOutcome::Unavailable("flaky: command not found".to_string())
+            } else {
+                self.outcome.clone()
+            }
+        }
+    }
+
+    #[test]
+    fn an_unavailable_tool_is_retried_and_never_penalized() {
+        let edit = change("style.css", "body { color: #222; }\n", "body { color: #222; }\n:focus { outline: 2px solid; }\n");
+        let without_tool = ChangeEvaluator::new().evaluate_change(&edit);
+
+        let recovered = ChangeEvaluator::new().with_tool_retries(2)
+            .with_external_tool(FlakyTool::boxed(2, ToolOutcome::Passed))
+            .evaluate_change(&edit);
+        let never_ran = ChangeEvaluator::new().with_tool_retries(2)
+            .with_external_tool(FlakyTool::boxed(usize::MAX, ToolOutcome::Passed))
+            .evaluate_change(&edit);
+        let rejected = ChangeEvaluator::new().with_tool_retries(2)
+            .with_external_tool(FlakyTool::boxed(1, ToolOutcome::ParseError("unexpected }".to_string())))
+            .evaluate_change(&edit);
+
+        assert_eq!(recovered.functionality_score, without_tool.functionality_score);
+        assert_eq!(never_ran.functionality_score, without_tool.functionality_score);
+        assert_eq!(never_ran.issues, without_tool.issues);
+        assert!(rejected.functionality_score < without_tool.functionality_score);
+        assert!(rejected.issues.iter().any(|issue| issue.contains("flaky reported errors")));
+    }
+
+    #[test]
+    fn a_page_without_a_proper_viewport_is_penalized_and_told_to_add_one() {
+        let before = "<html><head>\n<title>Home</title>\n</head><body>\n<h1>Home</h1>\n</body></html>";
+        let after = before.replace("<h1>Home</h1>", "<h1>Home</h1>\n<p>Welcome</p>");
+        let fixed = after.replace("<title>", "<meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">\n<title>");
+        let evaluator = ChangeEvaluator::new();
+
+        let checked = evaluator.evaluate_change(&change("index.html", before, &after));
+        let unchecked = ChangeEvaluator::new().with_mobile_check(false).evaluate_change(&change("index.html", before, &after));
+        assert!(checked.functionality_score < unchecked.functionality_score);
+        assert!(checked.issues.iter().any(|issue| issue == "Page has no viewport meta tag"));
+        let advice = checked.recommendations.iter().find(|r| r.code == "add-viewport")
+            .expect("a missing viewport should be recommended");
+        assert_eq!(advice.suggested_agent, Some(AgentType::UIAgent));
+
+        let proper = evaluator.evaluate_change(&change("index.html", before, &fixed));
+        assert!(proper.recommendations.iter().all(|r| r.code != "add-viewport"));
+    }
+}
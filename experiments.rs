@@ -0,0 +1,60 @@
+// Experiment Flags
+// Wraps agent additions so they stay inert until enabled in the browser
+
+/// Change metadata key recording which experiment a change belongs to
+pub const EXPERIMENT_METADATA_KEY: &str = "experiment";
+
+const GUARD_MARKER: &str = "data-brion-experiment-guard";
+
+/// Activates experiments listed in the `brion-exp` query parameter or
+/// cookie (comma separated): matching templates are stamped into the page
+/// and the names are exposed on `<html data-brion-experiment>` for CSS.
+const GUARD_SCRIPT: &str = r#"
+<script data-brion-experiment-guard>
+(function () {
+    var active = (new URLSearchParams(location.search).get('brion-exp') || '').split(',');
+    document.cookie.split(';').forEach(function (cookie) {
+        var pair = cookie.trim().split('=');
+        if (pair[0] === 'brion-exp') {
+            active = active.concat(decodeURIComponent(pair[1] || '').split(','));
+        }
+    });
+    active = active.filter(Boolean);
+    document.documentElement.setAttribute('data-brion-experiment', active.join(' '));
+    document.querySelectorAll('template[data-brion-experiment]').forEach(function (template) {
+        if (active.indexOf(template.getAttribute('data-brion-experiment')) !== -1) {
+            template.replaceWith(template.content.cloneNode(true));
+        }
+    });
+})();
+</script>
+"#;
+
+pub struct Experiments;
+
+impl Experiments {
+    /// Wrap an HTML fragment in a template that only renders when the
+    /// experiment is active
+    pub fn wrap_html(name: &str, fragment: &str) -> String {
+        format!("<template data-brion-experiment=\"{}\">{}</template>", name, fragment)
+    }
+
+    /// Selector matching the document only while the experiment is active
+    pub fn css_selector(name: &str) -> String {
+        format!("html[data-brion-experiment~=\"{}\"]", name)
+    }
+
+    /// Add the activation script before `</body>` if the page lacks it
+    pub fn ensure_guard(html: &str) -> String {
+        if html.contains(GUARD_MARKER) {
+            return html.to_string();
+        }
+
+        let mut guarded = html.to_string();
+        match guarded.rfind("</body>") {
+            Some(body_end) => guarded.insert_str(body_end, GUARD_SCRIPT),
+            None => guarded.push_str(GUARD_SCRIPT),
+        }
+        guarded
+    }
+}
@@ -0,0 +1,91 @@
+// External Validation Tools
+// Runs command-line checkers (stylelint, swc, ...) over changed content
+
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ToolOutcome {
+    Passed,
+    ParseError(String),  // The tool ran and rejected the content
+    Unavailable(String), // The tool couldn't run; says nothing about the content
+}
+
+pub trait ExternalTool: Send + Sync {
+    fn name(&self) -> &str;
+    fn applies_to(&self, file_path: &str) -> bool;
+    fn check(&self, content: &str) -> ToolOutcome;
+}
+
+/// A checker that reads the content on stdin and signals problems with a
+/// non-zero exit status
+pub struct CommandTool {
+    name: String,
+    program: String,
+    args: Vec<String>,
+    extensions: Vec<String>,
+}
+
+impl CommandTool {
+    pub fn new(name: &str, program: &str, args: &[&str], extensions: &[&str]) -> Self {
+        Self {
+            name: name.to_string(),
+            program: program.to_string(),
+            args: args.iter().map(|a| a.to_string()).collect(),
+            extensions: extensions.iter().map(|e| e.to_string()).collect(),
+        }
+    }
+}
+
+impl ExternalTool for CommandTool {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn applies_to(&self, file_path: &str) -> bool {
+        self.extensions.iter().any(|ext| file_path.ends_with(&format!(".{}", ext)))
+    }
+
+    fn check(&self, content: &str) -> ToolOutcome {
+        let mut child = match Command::new(&self.program)
+            .args(&self.args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+        {
+            Ok(child) => child,
+            Err(e) => return ToolOutcome::Unavailable(format!("Failed to start {}: {}", self.program, e)),
+        };
+
+        if let Some(mut stdin) = child.stdin.take() {
+            if let Err(e) = stdin.write_all(content.as_bytes()) {
+                return ToolOutcome::Unavailable(format!("Failed to write to {}: {}", self.program, e));
+            }
+        }
+
+        let output = match child.wait_with_output() {
+            Ok(output) => output,
+            Err(e) => return ToolOutcome::Unavailable(format!("Failed to wait for {}: {}", self.program, e)),
+        };
+
+        match output.status.code() {
+            Some(0) => ToolOutcome::Passed,
+            Some(_) => ToolOutcome::ParseError(String::from_utf8_lossy(&output.stderr).trim().to_string()),
+            None => ToolOutcome::Unavailable(format!("{} was terminated by a signal", self.program)),
+        }
+    }
+}
+
+/// Run `tool`, retrying up to `retries` more times while it's unavailable
+pub fn check_with_retry(tool: &dyn ExternalTool, content: &str, retries: u32) -> ToolOutcome {
+    let mut outcome = tool.check(content);
+    for attempt in 1..=retries {
+        if !matches!(outcome, ToolOutcome::Unavailable(_)) {
+            break;
+        }
+        std::thread::sleep(std::time::Duration::from_millis(50 * attempt as u64));
+        outcome = tool.check(content);
+    }
+    outcome
+}
@@ -1,36 +1,565 @@
 // File Operations for AI Agents
 // Handles reading, writing, and modifying website files
 
-use std::path::{Path, PathBuf};
+use std::collections::HashSet;
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
 use std::fs;
-use std::io::Write;
+use std::io::{self, Write};
+use serde::{Deserialize, Serialize};
+use crate::agents::error::BrionError;
 use crate::agents::version_control::{Change, ChangeType};
 use chrono::Utc;
+use log::warn;
+use parking_lot::{Condvar, Mutex};
+use tokio::runtime::{Handle, RuntimeFlavor};
+use tokio::sync::Notify;
 use uuid::Uuid;
 
+pub const UTF8_BOM: char = '\u{FEFF}';
+
+/// Directories never scanned when listing site files
+const SKIPPED_DIRS: &[&str] = &["node_modules", "target"];
+
+/// Appended to a file's path for the copy kept before a change is applied
+pub const BACKUP_SUFFIX: &str = ".brion.bak";
+
+/// How hard writes try to reach stable storage before returning
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DurabilityPolicy {
+    #[default]
+    Fast,   // Leave flushing to the OS; a crash can lose recent writes
+    Strict, // fsync every write, and the directory after an atomic rename
+}
+
+/// The file system calls behind a write, so the syncing a durability
+/// policy asks for can be observed without a real crash
+pub trait DiskIo {
+    fn write(&self, path: &Path, content: &[u8]) -> io::Result<()>;
+    fn sync_file(&self, path: &Path) -> io::Result<()>;
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()>;
+    fn sync_dir(&self, dir: &Path) -> io::Result<()>;
+}
+
+/// `DiskIo` on the real file system
+pub struct OsDisk;
+
+impl DiskIo for OsDisk {
+    fn write(&self, path: &Path, content: &[u8]) -> io::Result<()> {
+        fs::File::create(path)?.write_all(content)
+    }
+
+    fn sync_file(&self, path: &Path) -> io::Result<()> {
+        fs::File::open(path)?.sync_all()
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+        fs::rename(from, to)
+    }
+
+    #[cfg(unix)]
+    fn sync_dir(&self, dir: &Path) -> io::Result<()> {
+        fs::File::open(dir)?.sync_all()
+    }
+
+    #[cfg(not(unix))]
+    fn sync_dir(&self, _dir: &Path) -> io::Result<()> {
+        // Directories can't be opened for syncing here; renames are durable
+        // once the file system flushes its metadata
+        Ok(())
+    }
+}
+
+/// What discovery does with a file or directory it can't read
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum UnreadablePolicy {
+    #[default]
+    SkipAndLog, // Leave it out and log a warning
+    Fail,       // Abort discovery with the first error
+    Collect,    // Leave it out and return the error alongside the files
+}
+
+/// Files found by `discover_files`, plus the errors gathered under
+/// `UnreadablePolicy::Collect`
+#[derive(Debug, Default)]
+pub struct FileDiscovery {
+    pub files: Vec<String>,
+    pub errors: Vec<BrionError>,
+}
+
+impl FileDiscovery {
+    /// Apply `policy` to an error hit while discovering or reading files
+    pub fn handle(&mut self, policy: UnreadablePolicy, error: BrionError) -> Result<(), BrionError> {
+        match policy {
+            UnreadablePolicy::SkipAndLog => {
+                warn!("Skipping unreadable file: {}", error);
+                Ok(())
+            }
+            UnreadablePolicy::Fail => Err(error),
+            UnreadablePolicy::Collect => {
+                self.errors.push(error);
+                Ok(())
+            }
+        }
+    }
+}
+
+/// What a file holds, from its content when that's conclusive and its
+/// extension otherwise
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FileKind {
+    Html,
+    Css,
+    Js,
+    Json,
+    Other,
+}
+
+impl FileKind {
+    pub fn from_extension(path: &Path) -> Self {
+        match path.extension().and_then(|e| e.to_str()).map(|e| e.to_ascii_lowercase()).as_deref() {
+            Some("html") | Some("htm") => FileKind::Html,
+            Some("css") => FileKind::Css,
+            Some("js") | Some("mjs") => FileKind::Js,
+            Some("json") => FileKind::Json,
+            _ => FileKind::Other,
+        }
+    }
+}
+
+/// Bytes of content inspected when sniffing
+const SNIFF_LEN: usize = 512;
+
+/// Files currently held, shared by every clone of one `FileLocks`
+#[derive(Default)]
+struct LockTable {
+    held: Mutex<HashSet<PathBuf>>,
+    released: Condvar,       // Wakes threads in `lock_blocking`
+    released_async: Notify,  // Wakes tasks in `lock`
+}
+
+/// Table of per-file locks. The orchestrator owns one and hands clones to
+/// its agents, so they all see the same locks while separate orchestrators
+/// stay independent. Paths are keyed by their canonical form (the
+/// parent's, for files that don't exist yet), so different spellings of
+/// one file share a lock.
+#[derive(Clone, Default)]
+pub struct FileLocks {
+    table: Arc<LockTable>,
+}
+
+impl FileLocks {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Wait until no one else holds `path`, then lock it, yielding to the
+    /// runtime rather than blocking the thread while waiting
+    pub async fn lock(&self, path: &Path) -> FileLockGuard {
+        let key = Self::key(path);
+        loop {
+            let released = self.table.released_async.notified();
+            let mut released = std::pin::pin!(released);
+            // Registered before the check, so a release in between isn't missed
+            released.as_mut().enable();
+            if let Some(guard) = self.try_lock_key(&key) {
+                return guard;
+            }
+            released.await;
+        }
+    }
+
+    /// Lock several files, in sorted order so two callers locking
+    /// overlapping sets can't deadlock each other
+    pub async fn lock_files(&self, paths: &[PathBuf]) -> Vec<FileLockGuard> {
+        let mut guards = Vec::new();
+        for key in Self::sorted_keys(paths) {
+            guards.push(self.lock(&key).await);
+        }
+        guards
+    }
+
+    /// `lock` for synchronous callers. Blocks the thread while waiting; on
+    /// a multi-threaded runtime the worker's other tasks are handed off
+    /// first so they keep running. On a current-thread runtime the holder
+    /// may be a task that only runs once this thread yields, so a file
+    /// that's already held is `FileLocked` there rather than waited for.
+    pub fn lock_blocking(&self, path: &Path) -> Result<FileLockGuard, BrionError> {
+        let key = Self::key(path);
+        if let Some(guard) = self.try_lock_key(&key) {
+            return Ok(guard);
+        }
+        match Handle::try_current().map(|handle| handle.runtime_flavor()) {
+            Ok(RuntimeFlavor::MultiThread) => Ok(tokio::task::block_in_place(|| self.wait_for(key))),
+            Ok(_) => Err(BrionError::FileLocked(key)),
+            Err(_) => Ok(self.wait_for(key)),
+        }
+    }
+
+    /// `lock_files` for synchronous callers. Locks taken before one fails
+    /// are released again.
+    pub fn lock_files_blocking(&self, paths: &[PathBuf]) -> Result<Vec<FileLockGuard>, BrionError> {
+        Self::sorted_keys(paths).iter().map(|key| self.lock_blocking(key)).collect()
+    }
+
+    fn wait_for(&self, key: PathBuf) -> FileLockGuard {
+        let mut held = self.table.held.lock();
+        while held.contains(&key) {
+            self.table.released.wait(&mut held);
+        }
+        held.insert(key.clone());
+        FileLockGuard { path: key, table: Arc::clone(&self.table) }
+    }
+
+    fn try_lock_key(&self, key: &Path) -> Option<FileLockGuard> {
+        if !self.table.held.lock().insert(key.to_path_buf()) {
+            return None;
+        }
+        Some(FileLockGuard { path: key.to_path_buf(), table: Arc::clone(&self.table) })
+    }
+
+    fn sorted_keys(paths: &[PathBuf]) -> Vec<PathBuf> {
+        let mut keys: Vec<PathBuf> = paths.iter().map(|path| Self::key(path)).collect();
+        keys.sort();
+        keys.dedup();
+        keys
+    }
+
+    fn key(path: &Path) -> PathBuf {
+        if let Ok(canonical) = fs::canonicalize(path) {
+            return canonical;
+        }
+        match (path.parent(), path.file_name()) {
+            (Some(parent), Some(name)) => fs::canonicalize(parent)
+                .map(|parent| parent.join(name))
+                .unwrap_or_else(|_| path.to_path_buf()),
+            _ => path.to_path_buf(),
+        }
+    }
+}
+
+/// Exclusive hold on one file, taken before reading it and kept until the
+/// change made from that read is applied. Dropping the guard releases the
+/// file, so an early return or `?` can't leave it locked.
+///
+/// Locks aren't reentrant; don't lock a file twice.
+#[must_use = "the file is unlocked as soon as the guard is dropped"]
+pub struct FileLockGuard {
+    path: PathBuf,
+    table: Arc<LockTable>,
+}
+
+impl FileLockGuard {
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl std::fmt::Debug for FileLockGuard {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("FileLockGuard").field("path", &self.path).finish()
+    }
+}
+
+impl Drop for FileLockGuard {
+    fn drop(&mut self) {
+        self.table.held.lock().remove(&self.path);
+        self.table.released.notify_all();
+        self.table.released_async.notify_waiters();
+    }
+}
+
 pub struct FileOperations;
 
 impl FileOperations {
-    pub fn read_file(path: &Path) -> Result<String, String> {
-        fs::read_to_string(path)
-            .map_err(|e| format!("Failed to read file {}: {}", path.display(), e))
+    pub fn has_bom(content: &str) -> bool {
+        content.starts_with(UTF8_BOM)
+    }
+
+    pub fn strip_bom(content: &str) -> &str {
+        content.strip_prefix(UTF8_BOM).unwrap_or(content)
     }
 
-    pub fn write_file(path: &Path, content: &str) -> Result<(), String> {
+    /// Carry the BOM state of `original` over to `updated` so transforms
+    /// can't add or drop one by accident
+    pub fn preserve_bom(original: &str, updated: &str) -> String {
+        if Self::has_bom(original) {
+            format!("{}{}", UTF8_BOM, Self::strip_bom(updated))
+        } else {
+            Self::strip_bom(updated).to_string()
+        }
+    }
+
+    /// True when the two contents differ only by a leading BOM
+    pub fn is_bom_only_change(before: &str, after: &str) -> bool {
+        before != after && Self::strip_bom(before) == Self::strip_bom(after)
+    }
+
+    pub fn read_file(path: &Path) -> Result<String, BrionError> {
+        fs::read_to_string(path).map_err(|e| BrionError::io_at(path, e))
+    }
+
+    /// Write via a temporary sibling file and rename it into place, so a
+    /// crash mid-write leaves either the old content or the new, never a mix
+    pub fn write_file(path: &Path, content: &str, policy: DurabilityPolicy) -> Result<(), BrionError> {
+        Self::write_file_on(&OsDisk, path, content, policy)
+    }
+
+    /// `write_file` through `disk`
+    pub fn write_file_on(disk: &dyn DiskIo, path: &Path, content: &str, policy: DurabilityPolicy) -> Result<(), BrionError> {
+        let file_name = path.file_name()
+            .ok_or_else(|| BrionError::Other(format!("Invalid file path {}", path.display())))?;
+        // Unique per write, so concurrent writers never share a temp file
+        let temp_path = path.with_file_name(format!(".{}.{}-{}.tmp",
+            file_name.to_string_lossy(), std::process::id(), Uuid::new_v4().simple()));
         if let Some(parent) = path.parent() {
-            fs::create_dir_all(parent)
-                .map_err(|e| format!("Failed to create directory {}: {}", parent.display(), e))?;
+            fs::create_dir_all(parent).map_err(|e| BrionError::io_at(parent, e))?;
+        }
+
+        let written = disk.write(&temp_path, content.as_bytes())
+            .and_then(|_| match policy {
+                DurabilityPolicy::Strict => disk.sync_file(&temp_path),
+                DurabilityPolicy::Fast => Ok(()),
+            })
+            .and_then(|_| disk.rename(&temp_path, path));
+        if let Err(e) = written {
+            let _ = fs::remove_file(&temp_path);
+            return Err(BrionError::io_at(path, e));
+        }
+
+        // The rename itself only survives a crash once the directory is synced
+        if policy == DurabilityPolicy::Strict {
+            if let Some(parent) = path.parent() {
+                disk.sync_dir(parent).map_err(|e| BrionError::io_at(parent, e))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Where `apply_change` keeps a copy of a file's content while a change
+    /// is being applied to it. The copy is removed once the change lands,
+    /// so one left behind means the apply failed or was interrupted.
+    pub fn backup_path(path: &Path) -> PathBuf {
+        let mut backup = path.as_os_str().to_os_string();
+        backup.push(BACKUP_SUFFIX);
+        PathBuf::from(backup)
+    }
+
+    fn backup(path: &Path) -> Result<(), BrionError> {
+        if path.is_file() {
+            fs::copy(path, Self::backup_path(path)).map_err(|e| BrionError::io_at(path, e))?;
         }
-        
-        let mut file = fs::File::create(path)
-            .map_err(|e| format!("Failed to create file {}: {}", path.display(), e))?;
-        
-        file.write_all(content.as_bytes())
-            .map_err(|e| format!("Failed to write file {}: {}", path.display(), e))?;
-        
         Ok(())
     }
 
+    /// Remove the backup of `path`, if there is one
+    pub fn discard_backup(path: &Path) -> Result<(), BrionError> {
+        let backup = Self::backup_path(path);
+        match fs::remove_file(&backup) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(BrionError::io_at(&backup, e)),
+            _ => Ok(()),
+        }
+    }
+
+    /// Files under `root` with a backup left beside them, i.e. those whose
+    /// apply was interrupted, skipping the directories discovery skips
+    pub fn leftover_backups(root: &Path) -> Vec<PathBuf> {
+        let mut backed_up = Vec::new();
+        let mut pending = vec![root.to_path_buf()];
+        while let Some(dir) = pending.pop() {
+            let entries = match fs::read_dir(&dir) {
+                Ok(entries) => entries,
+                Err(_) => continue,
+            };
+            for entry in entries.flatten() {
+                let path = entry.path();
+                let name = entry.file_name().to_string_lossy().to_string();
+                if path.is_dir() {
+                    if !name.starts_with('.') && !SKIPPED_DIRS.contains(&name.as_str()) {
+                        pending.push(path);
+                    }
+                } else if let Some(original) = name.strip_suffix(BACKUP_SUFFIX) {
+                    backed_up.push(path.with_file_name(original));
+                }
+            }
+        }
+        backed_up.sort();
+        backed_up
+    }
+
+    /// Put back the content `path` had before a change whose apply didn't
+    /// finish, consuming the backup
+    pub fn restore_from_backup(path: &Path, policy: DurabilityPolicy) -> Result<(), BrionError> {
+        let backup = Self::backup_path(path);
+        if !backup.exists() {
+            return Err(BrionError::FileNotFound(backup));
+        }
+        fs::rename(&backup, path).map_err(|e| BrionError::io_at(path, e))?;
+
+        if policy == DurabilityPolicy::Strict {
+            if let Some(parent) = path.parent() {
+                OsDisk.sync_dir(parent).map_err(|e| BrionError::io_at(parent, e))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Classify content by its first bytes. Returns `None` when they don't
+    /// clearly identify a kind, e.g. for CSS and JS, which have no marker.
+    pub fn sniff(content: &str) -> Option<FileKind> {
+        let mut head = Self::strip_bom(content).trim_start();
+        // Pages often open with a comment before the doctype
+        while head.starts_with("<!--") {
+            head = head.find("-->").map(|end| head[end + 3..].trim_start()).unwrap_or("");
+        }
+
+        let prefix: String = head.chars().take(SNIFF_LEN).collect::<String>().to_ascii_lowercase();
+        if ["<!doctype html", "<html", "<head", "<body"].iter().any(|marker| prefix.starts_with(marker)) {
+            return Some(FileKind::Html);
+        }
+        if (prefix.starts_with('{') || prefix.starts_with('['))
+            && serde_json::from_str::<serde_json::Value>(Self::strip_bom(content)).is_ok()
+        {
+            return Some(FileKind::Json);
+        }
+        None
+    }
+
+    /// Kind of the file at `path` with the given content: sniffed when the
+    /// content is conclusive, so a `.txt` or extensionless page still counts
+    /// as HTML, and taken from the extension otherwise
+    pub fn file_kind(path: &Path, content: &str) -> FileKind {
+        Self::sniff(content).unwrap_or_else(|| FileKind::from_extension(path))
+    }
+
+    /// Every file under `root` as a `/`-separated path relative to it,
+    /// skipping hidden directories, build/dependency output and backups.
+    /// Unreadable entries are skipped and logged.
+    pub fn list_files(root: &Path) -> Vec<String> {
+        Self::discover_files(root, UnreadablePolicy::SkipAndLog)
+            .map(|discovery| discovery.files)
+            .unwrap_or_default()
+    }
+
+    /// Files under `root` that may be HTML pages: those with an HTML
+    /// extension, and those without a recognized one, which are only known
+    /// by their content
+    pub fn possible_pages(root: &Path) -> Vec<String> {
+        Self::list_files(root).into_iter()
+            .filter(|file| matches!(FileKind::from_extension(&root.join(file)), FileKind::Html | FileKind::Other))
+            .collect()
+    }
+
+    /// Files under `root` whose relative path matches `pattern`, sorted.
+    /// `*` and `?` match within one path segment, `**` across segments.
+    pub fn glob(root: &Path, pattern: &str) -> Vec<String> {
+        let mut files: Vec<String> = Self::list_files(root).into_iter()
+            .filter(|file| Self::glob_matches(pattern, file))
+            .collect();
+        files.sort();
+        files
+    }
+
+    /// Whether the relative `path` matches `pattern`, as in `glob`
+    pub fn glob_matches(pattern: &str, path: &str) -> bool {
+        fn matches(pattern: &[char], path: &[char]) -> bool {
+            match pattern {
+                [] => path.is_empty(),
+                // `**/` also matches no directories at all
+                ['*', '*', '/', rest @ ..] => {
+                    matches(rest, path)
+                        || (1..=path.len()).any(|i| path[i - 1] == '/' && matches(rest, &path[i..]))
+                }
+                ['*', '*', rest @ ..] => (0..=path.len()).any(|i| matches(rest, &path[i..])),
+                ['*', rest @ ..] => {
+                    let segment_end = path.iter().position(|c| *c == '/').unwrap_or(path.len());
+                    (0..=segment_end).any(|i| matches(rest, &path[i..]))
+                }
+                ['?', rest @ ..] => path.first().map(|c| *c != '/').unwrap_or(false) && matches(rest, &path[1..]),
+                [c, rest @ ..] => path.first() == Some(c) && matches(rest, &path[1..]),
+            }
+        }
+
+        let pattern: Vec<char> = pattern.chars().collect();
+        let path: Vec<char> = path.replace('\\', "/").chars().collect();
+        matches(&pattern, &path)
+    }
+
+    /// Like `list_files`, with `policy` deciding what happens to
+    /// directories that can't be listed and files that can't be opened,
+    /// such as broken symlinks or files without read permission
+    pub fn discover_files(root: &Path, policy: UnreadablePolicy) -> Result<FileDiscovery, BrionError> {
+        let mut discovery = FileDiscovery::default();
+        let mut pending = vec![root.to_path_buf()];
+        while let Some(dir) = pending.pop() {
+            let entries = match fs::read_dir(&dir) {
+                Ok(entries) => entries,
+                Err(e) => {
+                    discovery.handle(policy, BrionError::io_at(&dir, e))?;
+                    continue;
+                }
+            };
+            for entry in entries {
+                let entry = match entry {
+                    Ok(entry) => entry,
+                    Err(e) => {
+                        discovery.handle(policy, BrionError::io_at(&dir, e))?;
+                        continue;
+                    }
+                };
+                let path = entry.path();
+                let name = entry.file_name().to_string_lossy().to_string();
+                if path.is_dir() {
+                    if !name.starts_with('.') && !SKIPPED_DIRS.contains(&name.as_str()) {
+                        pending.push(path);
+                    }
+                } else if name.ends_with(BACKUP_SUFFIX) {
+                    continue;
+                } else if let Err(e) = fs::File::open(&path) {
+                    discovery.handle(policy, BrionError::io_at(&path, e))?;
+                } else if let Ok(relative) = path.strip_prefix(root) {
+                    discovery.files.push(relative.to_string_lossy().replace('\\', "/"));
+                }
+            }
+        }
+        discovery.files.sort();
+        Ok(discovery)
+    }
+
+    /// Join a change's relative `file_path` onto `base_path`, refusing
+    /// absolute paths and anything that resolves outside `base_path`,
+    /// whether through `..` or a symlink
+    pub fn resolve_within(base_path: &Path, file_path: &str) -> Result<PathBuf, BrionError> {
+        let outside = || BrionError::PathOutsideBase(file_path.to_string());
+
+        let mut relative = PathBuf::new();
+        for component in Path::new(file_path).components() {
+            match component {
+                Component::Normal(part) => relative.push(part),
+                Component::CurDir => {}
+                Component::ParentDir => {
+                    if !relative.pop() {
+                        return Err(outside());
+                    }
+                }
+                Component::RootDir | Component::Prefix(_) => return Err(outside()),
+            }
+        }
+
+        // Resolve symlinks on the part that exists; the rest is created fresh
+        let base = fs::canonicalize(base_path).map_err(|e| BrionError::io_at(base_path, e))?;
+        let joined = base.join(&relative);
+        let mut existing = joined.as_path();
+        while fs::symlink_metadata(existing).is_err() {
+            existing = existing.parent().ok_or_else(outside)?;
+        }
+        let resolved = fs::canonicalize(existing).map_err(|e| BrionError::io_at(existing, e))?;
+        if !resolved.starts_with(&base) {
+            return Err(outside());
+        }
+        Ok(joined)
+    }
+
     pub fn create_change(
         agent_id: &str,
         agent_type: &str,
@@ -50,58 +579,63 @@ impl FileOperations {
             after,
             metadata: std::collections::HashMap::new(),
             evaluation_score: None,
+            signature: None,
+            notes: Vec::new(),
+            protected: false,
+            sequence: 0,
         }
     }
 
-    pub fn apply_change(change: &Change, base_path: &PathBuf) -> Result<(), String> {
-        let file_path = base_path.join(&change.file_path);
+    #[cfg_attr(feature = "otel", tracing::instrument(name = "apply", skip_all,
+        fields(change_id = %change.id, file = %change.file_path)))]
+    pub fn apply_change(change: &Change, base_path: &Path, policy: DurabilityPolicy) -> Result<(), BrionError> {
+        let file_path = Self::resolve_within(base_path, &change.file_path)?;
+        Self::backup(&file_path)?;
         
         match change.change_type {
             ChangeType::Create | ChangeType::Modify | ChangeType::Optimize | 
             ChangeType::AddFeature | ChangeType::UpdateContent | ChangeType::UpdateStyle => {
-                Self::write_file(&file_path, &change.after)?;
+                Self::write_file(&file_path, &change.after, policy)?;
             }
             ChangeType::Delete => {
                 if file_path.exists() {
-                    fs::remove_file(&file_path)
-                        .map_err(|e| format!("Failed to delete file {}: {}", file_path.display(), e))?;
+                    fs::remove_file(&file_path).map_err(|e| BrionError::io_at(&file_path, e))?;
                 }
             }
             ChangeType::AddImage | ChangeType::AddModule => {
                 // For images and modules, we'd handle them differently
                 // This is a placeholder
-                Self::write_file(&file_path, &change.after)?;
+                Self::write_file(&file_path, &change.after, policy)?;
             }
         }
         
-        Ok(())
+        // A backup is only left behind by an apply that didn't finish
+        Self::discard_backup(&file_path)
     }
 
-    pub fn rollback_change(change: &Change, base_path: &PathBuf) -> Result<(), String> {
-        let file_path = base_path.join(&change.file_path);
+    pub fn rollback_change(change: &Change, base_path: &Path, policy: DurabilityPolicy) -> Result<(), BrionError> {
+        let file_path = Self::resolve_within(base_path, &change.file_path)?;
         
         match change.change_type {
             ChangeType::Create => {
                 // Delete the file that was created
                 if file_path.exists() {
-                    fs::remove_file(&file_path)
-                        .map_err(|e| format!("Failed to delete file {}: {}", file_path.display(), e))?;
+                    fs::remove_file(&file_path).map_err(|e| BrionError::io_at(&file_path, e))?;
                 }
             }
             ChangeType::Modify | ChangeType::Optimize | ChangeType::AddFeature |
             ChangeType::UpdateContent | ChangeType::UpdateStyle => {
                 // Restore the previous content
-                Self::write_file(&file_path, &change.before)?;
+                Self::write_file(&file_path, &change.before, policy)?;
             }
             ChangeType::Delete => {
                 // Restore the deleted file
-                Self::write_file(&file_path, &change.before)?;
+                Self::write_file(&file_path, &change.before, policy)?;
             }
             ChangeType::AddImage | ChangeType::AddModule => {
                 // Remove the added image/module
                 if file_path.exists() {
-                    fs::remove_file(&file_path)
-                        .map_err(|e| format!("Failed to remove file {}: {}", file_path.display(), e))?;
+                    fs::remove_file(&file_path).map_err(|e| BrionError::io_at(&file_path, e))?;
                 }
             }
         }
@@ -110,3 +644,204 @@ impl FileOperations {
     }
 }
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use parking_lot::Mutex;
+
+    /// Writes to the real disk and notes every sync it's asked for
+    #[derive(Default)]
+    struct RecordingDisk {
+        syncs: Mutex<Vec<String>>,
+    }
+
+    impl DiskIo for RecordingDisk {
+        fn write(&self, path: &Path, content: &[u8]) -> io::Result<()> {
+            OsDisk.write(path, content)
+        }
+
+        fn sync_file(&self, path: &Path) -> io::Result<()> {
+            self.syncs.lock().push(format!("file {}", path.display()));
+            OsDisk.sync_file(path)
+        }
+
+        fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+            OsDisk.rename(from, to)
+        }
+
+        fn sync_dir(&self, dir: &Path) -> io::Result<()> {
+            self.syncs.lock().push(format!("dir {}", dir.display()));
+            OsDisk.sync_dir(dir)
+        }
+    }
+
+    fn scratch_dir() -> PathBuf {
+        std::env::temp_dir().join(format!("brion-files-{}", Uuid::new_v4()))
+    }
+
+    #[test]
+    fn strict_writes_sync_the_file_and_its_directory() {
+        let dir = scratch_dir();
+        let disk = RecordingDisk::default();
+        FileOperations::write_file_on(&disk, &dir.join("index.html"), "<p>hi</p>", DurabilityPolicy::Strict).unwrap();
+
+        let syncs = disk.syncs.lock().clone();
+        assert_eq!(syncs.len(), 2);
+        assert!(syncs[0].starts_with("file "));
+        assert_eq!(syncs[1], format!("dir {}", dir.display()));
+        assert_eq!(FileOperations::read_file(&dir.join("index.html")).unwrap(), "<p>hi</p>");
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn fast_writes_never_sync() {
+        let dir = scratch_dir();
+        let disk = RecordingDisk::default();
+        FileOperations::write_file_on(&disk, &dir.join("index.html"), "<p>hi</p>", DurabilityPolicy::Fast).unwrap();
+
+        assert!(disk.syncs.lock().is_empty());
+        assert_eq!(FileOperations::read_file(&dir.join("index.html")).unwrap(), "<p>hi</p>");
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn concurrent_writes_to_one_file_never_mix() {
+        let dir = scratch_dir();
+        let path = dir.join("tasks.json");
+        let contents: Vec<String> = (0..4).map(|i| i.to_string().repeat(10_000)).collect();
+
+        std::thread::scope(|scope| {
+            for content in &contents {
+                let path = &path;
+                scope.spawn(move || {
+                    for _ in 0..20 {
+                        FileOperations::write_file(path, content, DurabilityPolicy::Fast).unwrap();
+                    }
+                });
+            }
+        });
+
+        assert!(contents.contains(&FileOperations::read_file(&path).unwrap()));
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1, "temp files left behind");
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[tokio::test]
+    async fn a_locked_file_waits_for_its_guard_without_blocking_the_runtime() {
+        let dir = scratch_dir();
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("index.html");
+        let locks = FileLocks::new();
+        let held = locks.lock(&path).await;
+
+        let waiter = tokio::spawn({
+            let locks = locks.clone();
+            let path = dir.join(".").join("index.html");
+            async move { locks.lock(&path).await.path().to_path_buf() }
+        });
+        tokio::task::yield_now().await;
+        assert!(!waiter.is_finished(), "a different spelling of the same file got a second lock");
+
+        // Another table's locks are independent
+        drop(FileLocks::new().lock_blocking(&path).unwrap());
+
+        let key = held.path().to_path_buf();
+        drop(held);
+        assert_eq!(waiter.await.unwrap(), key);
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn an_unreadable_file_is_skipped_and_the_rest_still_discovered() {
+        let dir = scratch_dir();
+        fs::create_dir_all(dir.join("styles")).unwrap();
+        fs::write(dir.join("index.html"), "<p>hi</p>").unwrap();
+        fs::write(dir.join("styles/main.css"), "body {}").unwrap();
+        std::os::unix::fs::symlink(dir.join("missing.html"), dir.join("broken.html")).unwrap();
+
+        let discovery = FileOperations::discover_files(&dir, UnreadablePolicy::SkipAndLog).unwrap();
+        assert_eq!(discovery.files, vec!["index.html".to_string(), "styles/main.css".to_string()]);
+        assert!(discovery.errors.is_empty());
+
+        let discovery = FileOperations::discover_files(&dir, UnreadablePolicy::Collect).unwrap();
+        assert_eq!(discovery.files.len(), 2);
+        assert_eq!(discovery.errors.len(), 1);
+        assert!(FileOperations::discover_files(&dir, UnreadablePolicy::Fail).is_err());
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn applied_changes_leave_no_backups_in_the_site_tree() {
+        let dir = scratch_dir();
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("index.html"), "<p>hi</p>").unwrap();
+        fs::write(dir.join("old.css"), "body {}").unwrap();
+        let base = dir.clone();
+
+        let modify = FileOperations::create_change("test-agent", "UIAgent", "index.html".to_string(),
+            ChangeType::Modify, "<p>hi</p>".to_string(), "<p>hello</p>".to_string());
+        let delete = FileOperations::create_change("test-agent", "UIAgent", "old.css".to_string(),
+            ChangeType::Delete, "body {}".to_string(), String::new());
+        FileOperations::apply_change(&modify, &base, DurabilityPolicy::Fast).unwrap();
+        FileOperations::apply_change(&delete, &base, DurabilityPolicy::Fast).unwrap();
+
+        let left: Vec<String> = fs::read_dir(&dir).unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+            .collect();
+        assert_eq!(left, vec!["index.html".to_string()]);
+        assert!(FileOperations::restore_from_backup(&dir.join("index.html"), DurabilityPolicy::Fast).is_err());
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    fn edit(file_path: &str) -> Change {
+        FileOperations::create_change("test-agent", "UIAgent", file_path.to_string(),
+            ChangeType::Modify, "before".to_string(), "after".to_string())
+    }
+
+    fn outside_base(result: Result<(), BrionError>) -> bool {
+        matches!(result, Err(BrionError::PathOutsideBase(_)))
+    }
+
+    #[test]
+    fn changes_cannot_reach_outside_the_site_root() {
+        let root = scratch_dir();
+        let (site, outside) = (root.join("site"), root.join("outside"));
+        fs::create_dir_all(site.join("a")).unwrap();
+        fs::create_dir_all(&outside).unwrap();
+        fs::write(root.join("x"), "keep").unwrap();
+        fs::write(outside.join("x"), "keep").unwrap();
+
+        let absolute = outside.join("x").to_string_lossy().to_string();
+        let mut escapes = vec!["../x".to_string(), "a/../../x".to_string(), absolute];
+        #[cfg(unix)]
+        {
+            std::os::unix::fs::symlink(&outside, site.join("link")).unwrap();
+            escapes.push("link/x".to_string());
+        }
+        for file_path in &escapes {
+            let change = edit(file_path);
+            assert!(outside_base(FileOperations::apply_change(&change, &site, DurabilityPolicy::Fast)), "apply {}", file_path);
+            assert!(outside_base(FileOperations::rollback_change(&change, &site, DurabilityPolicy::Fast)), "rollback {}", file_path);
+        }
+        assert_eq!(FileOperations::read_file(&root.join("x")).unwrap(), "keep");
+        assert_eq!(FileOperations::read_file(&outside.join("x")).unwrap(), "keep");
+        let _ = fs::remove_dir_all(root);
+    }
+
+    #[test]
+    fn dot_segments_that_stay_inside_the_site_root_are_accepted() {
+        let site = scratch_dir();
+        fs::create_dir_all(site.join("a")).unwrap();
+
+        for (file_path, lands_at) in [("a/./b", "a/b"), ("a/../b", "b")] {
+            let change = edit(file_path);
+            FileOperations::apply_change(&change, &site, DurabilityPolicy::Fast).unwrap();
+            assert_eq!(FileOperations::read_file(&site.join(lands_at)).unwrap(), "after", "apply {}", file_path);
+            FileOperations::rollback_change(&change, &site, DurabilityPolicy::Fast).unwrap();
+            assert_eq!(FileOperations::read_file(&site.join(lands_at)).unwrap(), "before", "rollback {}", file_path);
+        }
+        let _ = fs::remove_dir_all(site);
+    }
+}
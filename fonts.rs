@@ -0,0 +1,115 @@
+// Font Loading
+// Keeps web fonts from blocking text rendering: display=swap on Google Fonts links and @font-face rules
+
+use crate::agents::css_editor::CssEditor;
+use crate::agents::html_head::HtmlHead;
+use sha2::{Digest, Sha256};
+use std::process::Command;
+
+/// Stylesheet endpoints of Google Fonts (`/css` and `/css2`)
+const GOOGLE_FONTS_CSS: &str = "fonts.googleapis.com/css";
+
+/// Hex characters of the `href` hash naming a self-hosted stylesheet
+const LOCAL_NAME_HASH_LEN: usize = 8;
+
+pub struct FontLoading;
+
+impl FontLoading {
+    /// `href`s of every `<link>` pointing at a Google Fonts stylesheet,
+    /// as written in the page
+    pub fn google_fonts_links(html: &str) -> Vec<String> {
+        let lower = html.to_ascii_lowercase();
+        let mut links = Vec::new();
+        for (start, _) in lower.match_indices("<link") {
+            let end = match lower[start..].find('>') {
+                Some(close) => start + close + 1,
+                None => break,
+            };
+            let href = HtmlHead::attributes(&html[start..end])
+                .into_iter()
+                .find(|(name, _)| name == "href")
+                .map(|(_, value)| value);
+            if let Some(href) = href {
+                if href.contains(GOOGLE_FONTS_CSS) && !links.contains(&href) {
+                    links.push(href);
+                }
+            }
+        }
+        links
+    }
+
+    /// Where a self-hosted copy of the stylesheet at `href` is kept, relative
+    /// to the site root
+    pub fn local_stylesheet(href: &str) -> String {
+        let digest = Sha256::digest(href.as_bytes());
+        let hash: String = digest.iter().map(|b| format!("{:02x}", b)).collect();
+        format!("fonts/{}.css", &hash[..LOCAL_NAME_HASH_LEN])
+    }
+
+    /// `url` with `display=swap` added, unless it already sets a display
+    pub fn with_display_swap(url: &str) -> String {
+        let (without_fragment, fragment) = match url.split_once('#') {
+            Some((base, fragment)) => (base, format!("#{}", fragment)),
+            None => (url, String::new()),
+        };
+        let query = without_fragment.split_once('?').map(|(_, query)| query).unwrap_or("");
+        let has_display = query.split('&')
+            .any(|param| param.trim_start_matches("amp;").starts_with("display="));
+        if has_display {
+            return url.to_string();
+        }
+
+        let separator = match (without_fragment.contains('?'), without_fragment.contains("&amp;")) {
+            (false, _) => "?",
+            (true, true) => "&amp;",
+            (true, false) => "&",
+        };
+        format!("{}{}display=swap{}", without_fragment, separator, fragment)
+    }
+
+    /// Add `display=swap` to every Google Fonts link in the page
+    pub fn swap_links(html: &str) -> String {
+        let mut updated = html.to_string();
+        for href in Self::google_fonts_links(html) {
+            let swapped = Self::with_display_swap(&href);
+            if swapped != href {
+                for quote in ['"', '\''] {
+                    updated = updated.replace(&format!("{}{}{}", quote, href, quote), &format!("{}{}{}", quote, swapped, quote));
+                }
+            }
+        }
+        updated
+    }
+
+    /// Add `font-display: swap` to every `@font-face` rule without a
+    /// `font-display` of its own
+    pub fn add_font_display(css: &str) -> String {
+        let rules: Vec<_> = CssEditor::parse_rules(css)
+            .into_iter()
+            .filter(|rule| rule.selector.eq_ignore_ascii_case("@font-face"))
+            .filter(|rule| !css[rule.body_start..rule.body_end].to_ascii_lowercase().contains("font-display"))
+            .collect();
+
+        // Later rules first so earlier offsets stay valid
+        let mut updated = css.to_string();
+        for rule in rules.iter().rev() {
+            updated = CssEditor::set_property_in(&updated, rule, "font-display", "swap");
+        }
+        updated
+    }
+
+    /// Fetch a stylesheet with `curl`, so fonts CSS can be served from the
+    /// site itself
+    pub fn download(url: &str) -> Result<String, String> {
+        let url = if url.starts_with("//") { format!("https:{}", url) } else { url.to_string() };
+        let output = Command::new("curl")
+            .args(["-fsSL", "--max-time", "30", url.as_str()])
+            .output()
+            .map_err(|e| format!("Failed to start curl for {}: {}", url, e))?;
+        if !output.status.success() {
+            return Err(format!("Downloading {} failed: {}", url, String::from_utf8_lossy(&output.stderr).trim()));
+        }
+        String::from_utf8(output.stdout)
+            .map_err(|e| format!("{} is not valid UTF-8: {}", url, e))
+    }
+}
@@ -0,0 +1,205 @@
+// HTML Head Tag Handling
+// Attribute-aware checks and de-duplication for <meta> and <link> tags so agents don't stack duplicates
+
+pub struct HtmlHead;
+
+impl HtmlHead {
+    /// Byte range of the head's contents, between `<head ...>` and `</head>`
+    fn head_range(html: &str) -> Option<(usize, usize)> {
+        let lower = html.to_ascii_lowercase();
+        let open = lower.find("<head")?;
+        let start = open + lower[open..].find('>')? + 1;
+        let end = start + lower[start..].find("</head>")?;
+        Some((start, end))
+    }
+
+    /// Byte ranges of every `<meta>` and `<link>` tag in the head
+    fn head_tags(html: &str) -> Vec<(usize, usize)> {
+        let (start, end) = match Self::head_range(html) {
+            Some(range) => range,
+            None => return Vec::new(),
+        };
+
+        let lower = html[..end].to_ascii_lowercase();
+        let mut tags = Vec::new();
+        let mut i = start;
+        while let Some(offset) = lower[i..].find('<') {
+            let tag_start = i + offset;
+            let is_head_tag = ["<meta", "<link"].iter().any(|name| {
+                lower[tag_start..].starts_with(name)
+                    && lower[tag_start + name.len()..].starts_with(|c: char| c.is_whitespace() || c == '>' || c == '/')
+            });
+            let tag_end = match lower[tag_start..].find('>') {
+                Some(close) => tag_start + close + 1,
+                None => break,
+            };
+            if is_head_tag {
+                tags.push((tag_start, tag_end));
+            }
+            i = tag_end;
+        }
+        tags
+    }
+
+    /// Lowercased attribute names with their values, sorted, so tags that
+    /// differ only in attribute order, quoting or spacing compare equal
+    pub fn attributes(tag: &str) -> Vec<(String, String)> {
+        let inner = tag.trim_start_matches('<').trim_end_matches('>').trim_end_matches('/');
+        let inner = inner.split_once(char::is_whitespace).map(|(_, rest)| rest).unwrap_or("");
+        let chars: Vec<char> = inner.chars().collect();
+
+        let mut attributes = Vec::new();
+        let mut i = 0;
+        while i < chars.len() {
+            while i < chars.len() && (chars[i].is_whitespace() || chars[i] == '/') {
+                i += 1;
+            }
+            let name_start = i;
+            while i < chars.len() && !chars[i].is_whitespace() && chars[i] != '=' {
+                i += 1;
+            }
+            let name: String = chars[name_start..i].iter().collect::<String>().to_ascii_lowercase();
+            if name.is_empty() {
+                break;
+            }
+
+            let mut value = String::new();
+            if i < chars.len() && chars[i] == '=' {
+                i += 1;
+                if i < chars.len() && (chars[i] == '"' || chars[i] == '\'') {
+                    let quote = chars[i];
+                    i += 1;
+                    while i < chars.len() && chars[i] != quote {
+                        value.push(chars[i]);
+                        i += 1;
+                    }
+                    i += 1;
+                } else {
+                    while i < chars.len() && !chars[i].is_whitespace() {
+                        value.push(chars[i]);
+                        i += 1;
+                    }
+                }
+            }
+            attributes.push((name, value));
+        }
+
+        attributes.sort();
+        attributes
+    }
+
+    fn attribute<'a>(attributes: &'a [(String, String)], name: &str) -> Option<&'a str> {
+        attributes.iter().find(|(n, _)| n == name).map(|(_, v)| v.as_str())
+    }
+
+    /// Whether the head already preconnects to `origin`
+    pub fn has_preconnect(html: &str, origin: &str) -> bool {
+        let origin = origin.trim_end_matches('/');
+        Self::head_tags(html).into_iter().any(|(start, end)| {
+            let attributes = Self::attributes(&html[start..end]);
+            let is_preconnect = Self::attribute(&attributes, "rel")
+                .map(|rel| rel.split_whitespace().any(|r| r.eq_ignore_ascii_case("preconnect")))
+                .unwrap_or(false);
+            is_preconnect && Self::attribute(&attributes, "href")
+                .map(|href| href.trim_end_matches('/').eq_ignore_ascii_case(origin))
+                .unwrap_or(false)
+        })
+    }
+
+    /// `content` of the first head `<meta>` whose `attr` (`name`,
+    /// `property`, ...) is `value`, compared case-insensitively
+    pub fn meta_content(html: &str, attr: &str, value: &str) -> Option<String> {
+        Self::head_tags(html).into_iter()
+            .filter(|(start, _)| html[*start..].get(1..5).map(|t| t.eq_ignore_ascii_case("meta")).unwrap_or(false))
+            .map(|(start, end)| Self::attributes(&html[start..end]))
+            .find(|attributes| Self::attribute(attributes, attr).map(|v| v.eq_ignore_ascii_case(value)).unwrap_or(false))
+            .map(|attributes| Self::attribute(&attributes, "content").unwrap_or("").to_string())
+    }
+
+    /// Whether the head has a `<link>` with `rel` among its rel values
+    pub fn has_link_rel(html: &str, rel: &str) -> bool {
+        Self::head_tags(html).into_iter().any(|(start, end)| {
+            let attributes = Self::attributes(&html[start..end]);
+            html[start..].get(1..5).map(|t| t.eq_ignore_ascii_case("link")).unwrap_or(false)
+                && Self::attribute(&attributes, "rel")
+                    .map(|value| value.split_whitespace().any(|r| r.eq_ignore_ascii_case(rel)))
+                    .unwrap_or(false)
+        })
+    }
+
+    /// Byte ranges of every `<title>...</title>` element in the head
+    pub fn titles(html: &str) -> Vec<(usize, usize)> {
+        let (start, end) = match Self::head_range(html) {
+            Some(range) => range,
+            None => return Vec::new(),
+        };
+
+        let lower = html[..end].to_ascii_lowercase();
+        let mut titles = Vec::new();
+        let mut i = start;
+        while let Some(offset) = lower[i..].find("<title") {
+            let title_start = i + offset;
+            let is_title = lower[title_start + "<title".len()..].starts_with(|c: char| c.is_whitespace() || c == '>');
+            let title_end = match lower[title_start..].find("</title>") {
+                Some(close) => title_start + close + "</title>".len(),
+                None => break,
+            };
+            if is_title {
+                titles.push((title_start, title_end));
+            }
+            i = title_end;
+        }
+        titles
+    }
+
+    /// Remove every `<title>` in the head after the first
+    pub fn dedupe_titles(html: &str) -> String {
+        Self::remove_lines(html, Self::titles(html).into_iter().skip(1).collect())
+    }
+
+    /// Insert `markup` right after the opening `<head>` tag
+    pub fn insert_at_start(html: &str, markup: &str) -> Option<String> {
+        let (start, _) = Self::head_range(html)?;
+        let mut updated = html.to_string();
+        updated.insert_str(start, markup);
+        Some(updated)
+    }
+
+    /// Insert `markup` after the last thing in the head, before the
+    /// whitespace leading up to `</head>`
+    pub fn insert_at_end(html: &str, markup: &str) -> Option<String> {
+        let (_, end) = Self::head_range(html)?;
+        let position = html[..end].trim_end().len();
+        let mut updated = html.to_string();
+        updated.insert_str(position, markup);
+        Some(updated)
+    }
+
+    /// Remove `<meta>`/`<link>` tags in the head that repeat an earlier tag
+    /// attribute for attribute, along with their line's leading whitespace
+    pub fn dedupe(html: &str) -> String {
+        let mut seen = Vec::new();
+        let mut duplicates = Vec::new();
+        for (start, end) in Self::head_tags(html) {
+            let tag = &html[start..end];
+            let key = (tag[1..5].to_ascii_lowercase(), Self::attributes(tag));
+            if seen.contains(&key) {
+                duplicates.push((start, end));
+            } else {
+                seen.push(key);
+            }
+        }
+        Self::remove_lines(html, duplicates)
+    }
+
+    /// Remove each byte range along with its line's leading whitespace
+    fn remove_lines(html: &str, ranges: Vec<(usize, usize)>) -> String {
+        let mut updated = html.to_string();
+        for (start, end) in ranges.into_iter().rev() {
+            let line_start = html[..start].trim_end_matches([' ', '\t']).len();
+            let line_start = if html[..line_start].ends_with('\n') { line_start - 1 } else { start };
+            updated.replace_range(line_start..end, "");
+        }
+        updated
+    }
+}
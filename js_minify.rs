@@ -0,0 +1,329 @@
+// JavaScript Minification
+// Conservative comment and whitespace stripping that never touches string, template or regex contents
+
+/// Characters after which a `/` starts a regex literal rather than a division
+const REGEX_PRECEDERS: &str = "(,=:[!&|?{};+-*%<>~^";
+
+/// Keywords after which a `/` starts a regex literal
+const REGEX_KEYWORDS: &[&str] = &[
+    "return", "typeof", "case", "do", "else", "in", "of", "new", "delete", "void", "throw", "yield", "await", "instanceof",
+];
+
+/// A line break after one of these, or before one of `NO_BREAK_BEFORE`,
+/// can never end a statement, so it can go
+const NO_BREAK_AFTER: &str = "{;,([";
+const NO_BREAK_BEFORE: &str = "});,]";
+
+struct Scan {
+    output: String,
+    brackets: String, // Every bracket outside literals and comments, in order
+}
+
+pub struct JsMinifier;
+
+impl JsMinifier {
+    /// `source` with comments removed (except `/*! ... */` notices) and
+    /// whitespace collapsed, or `None` when it can't be done safely: an
+    /// unterminated literal or comment, unbalanced brackets, or brackets
+    /// that come out differently after minifying. Line breaks are kept
+    /// wherever they might end a statement.
+    pub fn minify(source: &str) -> Option<String> {
+        let first = Self::scan(source)?;
+        let second = Self::scan(&first.output)?;
+        if first.brackets != second.brackets {
+            return None;
+        }
+        Some(first.output)
+    }
+
+    fn scan(source: &str) -> Option<Scan> {
+        let chars: Vec<char> = source.chars().collect();
+        let mut output = String::with_capacity(source.len());
+        let mut brackets = String::new();
+        let mut open = Vec::new();
+        let mut gap: Option<bool> = None; // Whitespace or comments since the last token; true if they held a line break
+        let mut last_word = String::new(); // The previous token, when it was an identifier, keyword or number
+        let mut i = 0;
+
+        if source.starts_with("#!") {
+            while i < chars.len() && chars[i] != '\n' {
+                output.push(chars[i]);
+                i += 1;
+            }
+        }
+
+        while i < chars.len() {
+            let c = chars[i];
+            let next = chars.get(i + 1).copied();
+
+            if c.is_whitespace() {
+                gap = Some(gap.unwrap_or(false) || Self::is_line_break(c));
+                i += 1;
+                continue;
+            }
+            if c == '/' && next == Some('/') {
+                while i < chars.len() && !Self::is_line_break(chars[i]) {
+                    i += 1;
+                }
+                gap = Some(gap.unwrap_or(false));
+                continue;
+            }
+            if c == '/' && next == Some('*') {
+                let end = (i + 2..chars.len().saturating_sub(1)).find(|&j| chars[j] == '*' && chars[j + 1] == '/')? + 2;
+                if chars.get(i + 2) == Some(&'!') {
+                    Self::separate(&mut output, gap.take(), c, &last_word);
+                    output.extend(&chars[i..end]);
+                    last_word.clear();
+                } else {
+                    let line_break = chars[i..end].iter().any(|c| Self::is_line_break(*c));
+                    gap = Some(gap.unwrap_or(false) || line_break);
+                }
+                i = end;
+                continue;
+            }
+
+            Self::separate(&mut output, gap.take(), c, &last_word);
+            let word = std::mem::take(&mut last_word);
+            match c {
+                '\'' | '"' => i = Self::copy_string(&chars, i, &mut output)?,
+                '`' => i = Self::copy_template(&chars, i, &mut output)?,
+                '/' if Self::starts_regex(&output, &word) => i = Self::copy_regex(&chars, i, &mut output)?,
+                '(' | '[' | '{' => {
+                    open.push(c);
+                    brackets.push(c);
+                    output.push(c);
+                    i += 1;
+                }
+                ')' | ']' | '}' => {
+                    let expected = match open.pop()? {
+                        '(' => ')',
+                        '[' => ']',
+                        _ => '}',
+                    };
+                    if c != expected {
+                        return None;
+                    }
+                    brackets.push(c);
+                    output.push(c);
+                    i += 1;
+                }
+                c if Self::is_word_char(c) => {
+                    let start = i;
+                    while i < chars.len() && Self::is_word_char(chars[i]) {
+                        i += 1;
+                    }
+                    last_word = chars[start..i].iter().collect();
+                    output.push_str(&last_word);
+                }
+                _ => {
+                    output.push(c);
+                    i += 1;
+                }
+            }
+        }
+
+        if !open.is_empty() {
+            return None;
+        }
+        Some(Scan { output, brackets })
+    }
+
+    /// Write what the skipped whitespace and comments before `next` still
+    /// need: a line break where one could end a statement, a space where
+    /// the tokens would otherwise run together, or nothing
+    fn separate(output: &mut String, gap: Option<bool>, next: char, last_word: &str) {
+        let line_break = match gap {
+            Some(line_break) => line_break,
+            None => return,
+        };
+        let prev = match output.chars().last() {
+            Some(prev) => prev,
+            None => return,
+        };
+
+        if line_break && !NO_BREAK_AFTER.contains(prev) && !NO_BREAK_BEFORE.contains(next) {
+            output.push('\n');
+        } else if (Self::is_word_char(prev) && Self::is_word_char(next))
+            || (prev == next && "+-/".contains(prev))
+            // `1 .toString()` isn't `1.toString()`
+            || (next == '.' && !last_word.is_empty() && last_word.chars().all(|c| c.is_ascii_digit()))
+        {
+            output.push(' ');
+        }
+    }
+
+    fn starts_regex(output: &str, last_word: &str) -> bool {
+        if !last_word.is_empty() {
+            return REGEX_KEYWORDS.contains(&last_word);
+        }
+        if output.ends_with("++") || output.ends_with("--") {
+            return false;
+        }
+        output.chars().last().map(|prev| REGEX_PRECEDERS.contains(prev)).unwrap_or(true)
+    }
+
+    fn copy_string(chars: &[char], start: usize, output: &mut String) -> Option<usize> {
+        let quote = chars[start];
+        output.push(quote);
+        let mut i = start + 1;
+        loop {
+            let c = *chars.get(i)?;
+            output.push(c);
+            i += 1;
+            if c == '\\' {
+                output.push(*chars.get(i)?);
+                i += 1;
+            } else if c == quote {
+                return Some(i);
+            } else if c == '\n' {
+                return None;
+            }
+        }
+    }
+
+    /// Copy a template literal, `${...}` expressions included, as written
+    fn copy_template(chars: &[char], start: usize, output: &mut String) -> Option<usize> {
+        output.push('`');
+        let mut i = start + 1;
+        loop {
+            let c = *chars.get(i)?;
+            if c == '\\' {
+                output.push(c);
+                output.push(*chars.get(i + 1)?);
+                i += 2;
+            } else if c == '`' {
+                output.push(c);
+                return Some(i + 1);
+            } else if c == '$' && chars.get(i + 1) == Some(&'{') {
+                output.push_str("${");
+                i = Self::copy_expression(chars, i + 2, output)?;
+            } else {
+                output.push(c);
+                i += 1;
+            }
+        }
+    }
+
+    /// Copy a template's `${...}` expression up to its closing brace
+    fn copy_expression(chars: &[char], start: usize, output: &mut String) -> Option<usize> {
+        let mut depth = 1;
+        let mut i = start;
+        loop {
+            let c = *chars.get(i)?;
+            match c {
+                '\'' | '"' => i = Self::copy_string(chars, i, output)?,
+                '`' => i = Self::copy_template(chars, i, output)?,
+                _ => {
+                    output.push(c);
+                    i += 1;
+                    if c == '{' {
+                        depth += 1;
+                    } else if c == '}' {
+                        depth -= 1;
+                        if depth == 0 {
+                            return Some(i);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    fn copy_regex(chars: &[char], start: usize, output: &mut String) -> Option<usize> {
+        output.push('/');
+        let mut i = start + 1;
+        let mut in_class = false;
+        loop {
+            let c = *chars.get(i)?;
+            if Self::is_line_break(c) {
+                return None;
+            }
+            output.push(c);
+            i += 1;
+            match c {
+                '\\' => {
+                    output.push(*chars.get(i)?);
+                    i += 1;
+                }
+                '[' => in_class = true,
+                ']' => in_class = false,
+                '/' if !in_class => return Some(i),
+                _ => {}
+            }
+        }
+    }
+
+    fn is_word_char(c: char) -> bool {
+        c.is_alphanumeric() || c == '_' || c == '$' || c == '\\'
+    }
+
+    fn is_line_break(c: char) -> bool {
+        matches!(c, '\n' | '\r' | '\u{2028}' | '\u{2029}')
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn minify(source: &str) -> String {
+        JsMinifier::minify(source).unwrap_or_else(|| panic!("could not minify {:?}", source))
+    }
+
+    #[test]
+    fn line_breaks_that_could_end_a_statement_are_kept() {
+        assert_eq!(minify("a\n(b)"), "a\n(b)");
+        assert_eq!(minify("a\n++b"), "a\n++b");
+        assert_eq!(minify("let a = 1;\n\n  let b = [\n  2,\n  3\n];\n"), "let a=1;let b=[2,3];");
+    }
+
+    #[test]
+    fn a_slash_after_a_value_divides_and_after_an_operator_or_keyword_starts_a_regex() {
+        assert_eq!(minify("(a) / 2 / b"), "(a)/2/b");
+        assert_eq!(minify("x[0] / 2 // half"), "x[0]/2");
+        assert_eq!(minify("total / count"), "total/count");
+        assert_eq!(minify("return / a  b /g.test(s)"), "return/ a  b /g.test(s)");
+        assert_eq!(minify("if (typeof / x /.source) {}"), "if(typeof/ x /.source){}");
+        assert_eq!(minify("s.split( / , /)"), "s.split(/ , /)");
+        assert_eq!(minify("x = [ / [/] / ]"), "x=[/ [/] /]");
+    }
+
+    #[test]
+    fn template_expressions_are_copied_as_written_however_deeply_nested() {
+        let source = "let s = `x ${ y ? `${ z }  w` : '}' }  v`;";
+        assert_eq!(minify(source), "let s=`x ${ y ? `${ z }  w` : '}' }  v`;");
+        assert_eq!(minify("f( `${ { a: 1 }.a }` )"), "f(`${ { a: 1 }.a }`)");
+    }
+
+    #[test]
+    fn bang_comments_are_kept_and_other_comments_dropped() {
+        let source = "/*! License MIT */\nvar a = 1; /* drop */ var b = 2; // and this\n";
+        assert_eq!(minify(source), "/*! License MIT */\nvar a=1;var b=2;");
+    }
+
+    #[test]
+    fn a_space_is_kept_where_tokens_would_otherwise_merge() {
+        assert_eq!(minify("1 .toString()"), "1 .toString()");
+        assert_eq!(minify("a .b"), "a.b");
+        assert_eq!(minify("a + +b; c - -d"), "a+ +b;c- -d");
+        assert_eq!(minify("return  x"), "return x");
+    }
+
+    #[test]
+    fn unbalanced_or_unterminated_input_is_left_alone() {
+        for source in [
+            "function f( {",
+            "a)",
+            "[1, 2)",
+            "var s = 'abc",
+            "var s = 'a\nb'",
+            "var t = `abc",
+            "var t = `${ a `",
+            "/* open",
+            "var r = /abc",
+            "var r = /ab\nc/",
+        ] {
+            assert_eq!(JsMinifier::minify(source), None, "{:?}", source);
+        }
+    }
+}
@@ -0,0 +1,246 @@
+// Line Diffs
+// Shortest edit scripts between two texts (Myers) and unified diff hunks
+
+/// Edit distance past which the middle of a diff is reported as one
+/// replaced block instead of searched further. Bounds time and memory
+/// on files rewritten wholesale.
+const MAX_EDIT_DISTANCE: usize = 2_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiffOp {
+    Equal,  // One line kept from both sides
+    Delete, // One line only in the old text
+    Insert, // One line only in the new text
+}
+
+pub struct LineDiff;
+
+impl LineDiff {
+    /// Edit script turning `before` into `after`, one op per line, with
+    /// as few deletes and inserts as possible
+    pub fn ops(before: &[&str], after: &[&str]) -> Vec<DiffOp> {
+        let prefix = before.iter().zip(after).take_while(|(a, b)| a == b).count();
+        let suffix = before[prefix..].iter().rev()
+            .zip(after[prefix..].iter().rev())
+            .take_while(|(a, b)| a == b)
+            .count();
+        let old = &before[prefix..before.len() - suffix];
+        let new = &after[prefix..after.len() - suffix];
+
+        let mut ops = vec![DiffOp::Equal; prefix];
+        match Self::myers(old, new) {
+            Some(middle) => ops.extend(middle),
+            None => {
+                ops.extend(std::iter::repeat_n(DiffOp::Delete, old.len()));
+                ops.extend(std::iter::repeat_n(DiffOp::Insert, new.len()));
+            }
+        }
+        ops.extend(std::iter::repeat_n(DiffOp::Equal, suffix));
+        ops
+    }
+
+    /// Lines added and removed
+    pub fn stat(before: &[&str], after: &[&str]) -> (usize, usize) {
+        let ops = Self::ops(before, after);
+        let added = ops.iter().filter(|op| **op == DiffOp::Insert).count();
+        let removed = ops.iter().filter(|op| **op == DiffOp::Delete).count();
+        (added, removed)
+    }
+
+    /// Unified diff hunks from `before` to `after` with `context` unchanged
+    /// lines around each change, without file headers. Changes closer than
+    /// twice the context share a hunk.
+    pub fn hunks(before: &[&str], after: &[&str], context: usize) -> String {
+        let ops = Self::ops(before, after);
+
+        // Line index on each side at the start of every op
+        let mut positions = Vec::with_capacity(ops.len() + 1);
+        let (mut old_line, mut new_line) = (0, 0);
+        for op in &ops {
+            positions.push((old_line, new_line));
+            match op {
+                DiffOp::Equal => {
+                    old_line += 1;
+                    new_line += 1;
+                }
+                DiffOp::Delete => old_line += 1,
+                DiffOp::Insert => new_line += 1,
+            }
+        }
+        positions.push((old_line, new_line));
+
+        let changed: Vec<usize> = ops.iter().enumerate()
+            .filter(|(_, op)| **op != DiffOp::Equal)
+            .map(|(i, _)| i)
+            .collect();
+
+        let mut diff = String::new();
+        let mut group_start = 0;
+        while group_start < changed.len() {
+            let mut group_end = group_start;
+            while group_end + 1 < changed.len() && changed[group_end + 1] - changed[group_end] - 1 <= 2 * context {
+                group_end += 1;
+            }
+            let start = changed[group_start].saturating_sub(context);
+            let end = (changed[group_end] + context + 1).min(ops.len());
+
+            let (old_start, new_start) = positions[start];
+            let (old_end, new_end) = positions[end];
+            // An empty range is numbered by the line before it, so `-0,0` for a new file
+            let range = |start: usize, len: usize| if len == 0 { format!("{},0", start) } else { format!("{},{}", start + 1, len) };
+            diff.push_str(&format!("@@ -{} +{} @@\n", range(old_start, old_end - old_start), range(new_start, new_end - new_start)));
+
+            for (op, &(old_line, new_line)) in ops[start..end].iter().zip(&positions[start..end]) {
+                match op {
+                    DiffOp::Equal => diff.push_str(&format!(" {}\n", before[old_line])),
+                    DiffOp::Delete => diff.push_str(&format!("-{}\n", before[old_line])),
+                    DiffOp::Insert => diff.push_str(&format!("+{}\n", after[new_line])),
+                }
+            }
+            group_start = group_end + 1;
+        }
+        diff
+    }
+
+    /// Myers' greedy shortest edit script, or `None` once the edit distance
+    /// passes `MAX_EDIT_DISTANCE`
+    fn myers(old: &[&str], new: &[&str]) -> Option<Vec<DiffOp>> {
+        let (n, m) = (old.len() as isize, new.len() as isize);
+        if n == 0 || m == 0 {
+            let mut ops = vec![DiffOp::Delete; old.len()];
+            ops.extend(std::iter::repeat_n(DiffOp::Insert, new.len()));
+            return Some(ops);
+        }
+
+        // v[k + max] is the furthest x reached on diagonal k = x - y.
+        // trace[d] keeps diagonals -d..=d as they were before step d.
+        let max = n + m;
+        let at = |k: isize| (k + max) as usize;
+        let mut v = vec![0isize; 2 * max as usize + 2];
+        let mut trace: Vec<Vec<isize>> = Vec::new();
+
+        'search: for d in 0..=max {
+            if d as usize > MAX_EDIT_DISTANCE {
+                return None;
+            }
+            trace.push(v[at(-d)..=at(d)].to_vec());
+            for k in (-d..=d).step_by(2) {
+                let mut x = if k == -d || (k != d && v[at(k - 1)] < v[at(k + 1)]) {
+                    v[at(k + 1)]
+                } else {
+                    v[at(k - 1)] + 1
+                };
+                let mut y = x - k;
+                while x < n && y < m && old[x as usize] == new[y as usize] {
+                    x += 1;
+                    y += 1;
+                }
+                v[at(k)] = x;
+                if x >= n && y >= m {
+                    break 'search;
+                }
+            }
+        }
+
+        // Walk back from the end, one edit per step plus the snake before it
+        let mut ops = Vec::new();
+        let (mut x, mut y) = (n, m);
+        for (d, v) in trace.iter().enumerate().skip(1).rev() {
+            let d = d as isize;
+            let at = |k: isize| (k + d) as usize;
+            let k = x - y;
+            let prev_k = if k == -d || (k != d && v[at(k - 1)] < v[at(k + 1)]) { k + 1 } else { k - 1 };
+            let prev_x = v[at(prev_k)];
+            let prev_y = prev_x - prev_k;
+            while x > prev_x && y > prev_y {
+                ops.push(DiffOp::Equal);
+                x -= 1;
+                y -= 1;
+            }
+            ops.push(if x == prev_x { DiffOp::Insert } else { DiffOp::Delete });
+            x = prev_x;
+            y = prev_y;
+        }
+        // Whatever is left is the snake leaving the origin on diagonal 0
+        ops.extend(std::iter::repeat_n(DiffOp::Equal, x as usize));
+        ops.reverse();
+        Some(ops)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(text: &str) -> Vec<&str> {
+        text.lines().collect()
+    }
+
+    /// Apply `ops` to `before`, taking inserted lines from `after`
+    fn replay<'a>(ops: &[DiffOp], before: &[&'a str], after: &[&'a str]) -> (Vec<&'a str>, Vec<&'a str>) {
+        let (mut old, mut new) = (Vec::new(), Vec::new());
+        let (mut i, mut j) = (0, 0);
+        for op in ops {
+            match op {
+                DiffOp::Equal => {
+                    assert_eq!(before[i], after[j]);
+                    old.push(before[i]);
+                    new.push(after[j]);
+                    i += 1;
+                    j += 1;
+                }
+                DiffOp::Delete => {
+                    old.push(before[i]);
+                    i += 1;
+                }
+                DiffOp::Insert => {
+                    new.push(after[j]);
+                    j += 1;
+                }
+            }
+        }
+        (old, new)
+    }
+
+    #[test]
+    fn edits_far_apart_get_their_own_hunks() {
+        let before: Vec<String> = (0..1000).map(|i| format!("line {}", i)).collect();
+        let mut after = before.clone();
+        after[10] = "changed 10".to_string();
+        after[990] = "changed 990".to_string();
+        let before: Vec<&str> = before.iter().map(|l| l.as_str()).collect();
+        let after: Vec<&str> = after.iter().map(|l| l.as_str()).collect();
+
+        assert_eq!(LineDiff::stat(&before, &after), (2, 2));
+        let hunks = LineDiff::hunks(&before, &after, 3);
+        assert_eq!(hunks.matches("@@ -").count(), 2);
+        assert!(hunks.starts_with("@@ -8,7 +8,7 @@\n line 7\n line 8\n line 9\n-line 10\n+changed 10\n"));
+        assert!(hunks.contains("@@ -988,7 +988,7 @@\n"));
+    }
+
+    #[test]
+    fn nearby_edits_share_a_hunk() {
+        let before = lines("a\nb\nc\nd\ne\nf\ng\nh");
+        let after = lines("a\nB\nc\nd\ne\nf\nG\nh");
+
+        let hunks = LineDiff::hunks(&before, &after, 3);
+        assert_eq!(hunks, "@@ -1,8 +1,8 @@\n a\n-b\n+B\n c\n d\n e\n f\n-g\n+G\n h\n");
+    }
+
+    #[test]
+    fn ops_are_a_shortest_valid_edit_script() {
+        let before = lines("a\nb\nc\na\nb\nb\na");
+        let after = lines("c\nb\na\nb\na\nc");
+
+        let ops = LineDiff::ops(&before, &after);
+        assert_eq!(replay(&ops, &before, &after), (before.clone(), after.clone()));
+        // The classic Myers example needs five edits
+        assert_eq!(ops.iter().filter(|op| **op != DiffOp::Equal).count(), 5);
+    }
+
+    #[test]
+    fn a_new_file_is_all_inserts() {
+        let after = lines("one\ntwo");
+        assert_eq!(LineDiff::hunks(&[], &after, 3), "@@ -0,0 +1,2 @@\n+one\n+two\n");
+    }
+}
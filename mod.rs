@@ -4,15 +4,50 @@
 pub mod orchestrator;
 pub mod evaluator;
 pub mod version_control;
+#[allow(clippy::module_inception)] // `agents::agents` holds the Agent trait and task types; renaming would break every import
 pub mod agents;
 pub mod task_queue;
 pub mod file_ops;
 pub mod agent_impl;
+pub mod css_editor;
+pub mod js_minify;
+pub mod html_head;
+pub mod fonts;
+pub mod ring_buffer;
+pub mod experiments;
+pub mod external_tools;
+pub mod rpc;
+pub mod config;
+pub mod consistency;
+pub mod pipeline;
+pub mod line_diff;
+pub mod error;
+#[cfg(feature = "otel")]
+pub mod telemetry;
 
-pub use orchestrator::AgentOrchestrator;
-pub use evaluator::ChangeEvaluator;
-pub use version_control::VersionControl;
-pub use agents::{Agent, AgentType, AgentTask, AgentResult};
-pub use task_queue::TaskQueue;
-pub use file_ops::FileOperations;
+pub use orchestrator::{
+    AgentOrchestrator, FileLimitPolicy, OrchestratorEvent, SelectionStrategy,
+    CYCLE_SNAPSHOT_TEMPLATE, DEFAULT_SHUTDOWN_TIMEOUT, DEFAULT_SNAPSHOT_TEMPLATE,
+    RecoveryReport, RegressionDiff, RollbackMode, RollbackOutcome, RollbackReport, RollbackPreview, Suggestion,
+};
+pub use evaluator::{
+    ChangeEvaluator, EvaluatorConfig, Recommendation, RuleOutcome, ScoreKind, ScoringRule, SimulationReport,
+};
+pub use version_control::{VersionControl, ChangeNote, ChangeQuery, ChangeTransform, ChangeTypeAllowlist, FileDiff};
+pub use agents::{Agent, AgentType, AgentTask, AgentResult, MetricDef, MetricKind};
+pub use task_queue::{TaskQueue, TaskStatus};
+pub use file_ops::{DiskIo, DurabilityPolicy, FileDiscovery, FileKind, FileLockGuard, FileLocks, FileOperations, OsDisk, UnreadablePolicy};
+pub use css_editor::CssEditor;
+pub use js_minify::JsMinifier;
+pub use html_head::HtmlHead;
+pub use fonts::FontLoading;
+pub use ring_buffer::BoundedRingBuffer;
+pub use experiments::Experiments;
+pub use external_tools::{CommandTool, ExternalTool, ToolOutcome};
+pub use rpc::RpcHandler;
+pub use config::{AgentConfig, AgentProfile, Environment};
+pub use consistency::{ConsistencyChecker, Inconsistency, InconsistencyKind};
+pub use error::BrionError;
+pub use pipeline::Pipeline;
+pub use line_diff::{DiffOp, LineDiff};
 
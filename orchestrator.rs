@@ -2,43 +2,314 @@
 // Manages all agents and coordinates continuous improvement
 
 use crate::agents::{
-    agents::{Agent, AgentType, AgentTask, AgentResult},
-    evaluator::ChangeEvaluator,
-    version_control::{VersionControl, Change, ChangeType},
-    task_queue::TaskQueue,
+    agents::{Agent, AgentType, AgentTask, AgentResult, MetricDef, undeclared_metrics},
+    evaluator::{ChangeEvaluator, EvaluationResult, Recommendation},
+    version_control::{VersionControl, Change, ChangeType, CHANGE_GROUP_METADATA_KEY, RESET_METADATA_KEY, REVERTS_METADATA_KEY},
+    task_queue::{TaskQueue, TaskStatus},
+    config::{AgentConfig, AgentProfile, Environment},
+    ring_buffer::BoundedRingBuffer,
+    consistency::{ConsistencyChecker, Inconsistency},
+    file_ops::{DurabilityPolicy, FileLocks},
+    error::BrionError,
+    pipeline::Pipeline,
 };
+use serde::Serialize;
 use std::path::PathBuf;
 use std::sync::Arc;
 use parking_lot::RwLock;
+use tokio::sync::{broadcast, watch, Notify, Semaphore};
+use tokio::task::JoinHandle;
 use tokio::time::{interval, Duration};
 use chrono::Utc;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
 use uuid::Uuid;
-use log::{info, warn, error};
+use log::{debug, info, warn, error};
+
+type SharedAgent = Arc<dyn Agent + Send + Sync>;
+type EventListener = Box<dyn Fn(&OrchestratorEvent) -> bool + Send + Sync>; // false once it wants no more events
 
 pub struct AgentOrchestrator {
-    agents: Arc<RwLock<HashMap<AgentType, Vec<Box<dyn Agent + Send + Sync>>>>>,
+    agents: Arc<RwLock<HashMap<AgentType, Vec<SharedAgent>>>>,
     version_control: Arc<VersionControl>,
     evaluator: Arc<ChangeEvaluator>,
     task_queue: Arc<TaskQueue>,
     base_path: PathBuf,
     is_running: Arc<RwLock<bool>>,
     stats: Arc<RwLock<OrchestratorStats>>,
+    fairness_mode: bool,
+    profile: AgentProfile,
+    quarantine: Arc<RwLock<HashMap<String, chrono::DateTime<Utc>>>>, // fingerprint -> expiry
+    quarantine_ttl: chrono::Duration,
+    diagnostics_interval: Option<Duration>,
+    listeners: Arc<RwLock<Vec<EventListener>>>,
+    selection_strategy: SelectionStrategy,
+    selection_weights: Arc<RwLock<HashMap<String, f64>>>, // agent id -> smooth weighted round-robin state
+    round_robin: Arc<RwLock<HashMap<AgentType, usize>>>, // agent type -> index to try next
+    suggest_mode: bool,
+    suggestions: Arc<RwLock<Vec<Suggestion>>>,
+    scopes: Arc<RwLock<HashMap<String, PathBuf>>>, // agent id -> subtree relative to base_path
+    evaluation_parallelism: usize,
+    snapshot_template: Option<String>, // Auto-snapshot after each task with kept changes when set
+    recent_results: Arc<RwLock<BoundedRingBuffer<AgentResult>>>,
+    evaluate_before_apply: bool,
+    trusted_agents: Arc<RwLock<HashSet<String>>>,
+    default_targets: HashMap<AgentType, Vec<String>>,
+    target_globs: HashMap<AgentType, String>, // Generated tasks target the files matching these
+    design_tokens: Option<String>, // Token file generated design tasks sync from
+    tick_history: Arc<RwLock<BoundedRingBuffer<usize>>>,
+    pause_on_plateau: bool,
+    min_confidence: Option<f64>,
+    max_concurrency: usize,
+    files_in_flight: Arc<RwLock<HashSet<String>>>,
+    file_locks: FileLocks, // Shared with every registered agent
+    tick_interval: Arc<RwLock<Duration>>,
+    max_files_per_task: Option<(usize, FileLimitPolicy)>,
+    auto_remediate: Option<usize>, // Most remediation tasks chained behind one original task
+    cycle_changes: Arc<RwLock<Vec<String>>>, // Changes kept since the last cycle snapshot
+    wake: Arc<Notify>, // Cuts the improvement loop's sleep short when stopping
+    loop_running: Arc<watch::Sender<bool>>,
+    shutdown_timeout: Duration,
+    auto_disable: Option<(usize, f64)>, // Window of recent outcomes, and rollback rate that disables an agent
+    recent_outcomes: Arc<RwLock<HashMap<String, BoundedRingBuffer<bool>>>>, // agent id -> kept?
+    disabled_agents: Arc<RwLock<HashSet<String>>>,
+}
+
+pub const DEFAULT_RECENT_RESULTS_CAPACITY: usize = 100;
+
+pub const DEFAULT_SNAPSHOT_TEMPLATE: &str = "{agent_type}: {change_count} changes to {file_count} files";
+
+/// Description of the snapshot taken after each improvement tick that kept changes
+pub const CYCLE_SNAPSHOT_TEMPLATE: &str = "Improvement cycle: {change_count} changes to {file_count} files by {agent_type}";
+
+/// Ticks averaged for `improvement_rate` and required before a plateau is declared
+pub const DEFAULT_PLATEAU_TICKS: usize = 10;
+
+/// Kept changes per tick at or below which improvement counts as stalled
+const PLATEAU_RATE: f64 = 0.1;
+
+/// Most follow-up tasks queued from a single agent result
+const MAX_FOLLOW_UPS_PER_RESULT: usize = 10;
+
+/// Time between improvement ticks unless changed with `set_interval`
+pub const DEFAULT_TICK_INTERVAL: Duration = Duration::from_secs(30);
+
+/// How long `shutdown` waits for in-flight tasks unless changed with
+/// `with_shutdown_timeout`
+pub const DEFAULT_SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Idle loop ticks between "no agents registered" warnings
+const NO_AGENTS_WARNING_TICKS: u32 = 10;
+
+/// What `recover` did to reconcile the journal with disk
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct RecoveryReport {
+    pub applied: usize,   // Recorded changes whose write was interrupted
+    pub restored: usize,  // Files put back from the backup an interrupted apply left
+    pub evaluated: usize, // Changes that had never been scored
+    pub kept: usize,
+    pub external_changes: Vec<String>, // IDs of changes recorded for edits made outside the agents
+    pub snapshot: Option<String>,
+}
+
+/// What review decided for an evaluated change
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Verdict {
+    Keep,
+    Suggest,
+    Reject,
+}
+
+/// An evaluated change waiting for its verdict to be carried out
+struct Reviewed {
+    change: Change,
+    scored: Change, // `change` with its evaluation score, as recorded
+    fingerprint: String,
+    evaluation: EvaluationResult,
+    verdict: Verdict,
+}
+
+/// Files a running task holds in `files_in_flight`. Dropping the claim
+/// gives them back, so a task that fails or panics can't keep them.
+struct FileClaim {
+    files: Vec<String>,
+    in_flight: Arc<RwLock<HashSet<String>>>,
+}
+
+impl Drop for FileClaim {
+    fn drop(&mut self) {
+        let mut in_flight = self.in_flight.write();
+        for file in &self.files {
+            in_flight.remove(file);
+        }
+    }
+}
+
+/// A change an agent proposed while the orchestrator was in suggest mode.
+/// The file is left untouched until the suggestion is applied.
+#[derive(Debug, Clone, Serialize)]
+pub struct Suggestion {
+    pub change: Change,
+    pub score: f64,
+    pub issues: Vec<String>,
+}
+
+/// Where replaying an agent on a recorded change's `before` content didn't
+/// reproduce the recorded `after`
+#[derive(Debug, Clone, Serialize)]
+pub struct RegressionDiff {
+    pub change_id: String,
+    pub file_path: String,
+    pub expected: String,       // The recorded `after`
+    pub actual: Option<String>, // What the agent produced now; `None` if it failed
+    pub diff: String,           // Unified diff from expected to actual
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub enum RollbackOutcome {
+    Reverted,
+    Skipped(String),
+    Conflict(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RollbackMode {
+    StopOnConflict,
+    ContinueOnConflict,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct RollbackReport {
+    pub entries: Vec<(String, RollbackOutcome)>, // (change id, outcome) in rollback order
+    pub halted: bool, // True when StopOnConflict ended the batch early
 }
 
-#[derive(Debug, Clone, Default)]
+#[derive(Debug, Clone, Serialize)]
+pub struct RollbackPreviewEntry {
+    pub change_id: String,
+    pub file_path: Option<String>,
+    pub outcome: RollbackOutcome, // What an actual rollback would report
+    pub lines_changed: usize,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct RollbackPreview {
+    pub entries: Vec<RollbackPreviewEntry>,
+    pub files_affected: usize,
+    pub lines_affected: usize,
+    pub conflicts: usize,
+}
+
+/// What happens to a task that targets more files than `max_files_per_task`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileLimitPolicy {
+    Fail,  // Refuse the task
+    Split, // Queue it again as tasks covering at most the limit each
+}
+
+/// Task parameter listing the targets of a task without a `target_file`,
+/// comma separated. Split tasks now carry `target_files` instead; this is
+/// still read for tasks queued before that.
+pub const TARGETS_PARAM: &str = "targets";
+
+/// Task parameter counting how many remediation tasks led to this one
+pub const REMEDIATION_DEPTH_PARAM: &str = "remediation_depth";
+
+/// Task parameter listing the recommendation codes a remediation task
+/// addresses, comma separated
+pub const RECOMMENDATIONS_PARAM: &str = "recommendations";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SelectionStrategy {
+    First,               // Always use the first registered agent of a type
+    RoundRobin,          // Cycle through every registered agent of a type
+    PerformanceWeighted, // Favor agents whose changes are kept more often
+}
+
+/// Agents with fewer evaluated changes than this are treated optimistically
+/// so new agents still get explored
+const EXPLORATION_EVALUATIONS: usize = 5;
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct AgentScore {
+    pub kept: usize,
+    pub rolled_back: usize,
+    pub tasks_attempted: usize,
+    pub tasks_failed: usize, // The agent returned an error or broke a task limit
+    pub evaluated: usize,
+    pub average_score: f64,  // Mean overall score of its evaluated changes
+}
+
+impl AgentScore {
+    fn record_score(&mut self, score: f64) {
+        self.evaluated += 1;
+        self.average_score += (score - self.average_score) / self.evaluated as f64;
+    }
+
+    pub fn keep_rate(&self) -> f64 {
+        let total = self.kept + self.rolled_back;
+        if total == 0 {
+            1.0
+        } else {
+            self.kept as f64 / total as f64
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub enum OrchestratorEvent {
+    Drift { file: String, summary: String },
+    Inconsistency(Inconsistency),
+    Unreadable { error: String },
+    TaskStarted { task_id: String, agent_type: AgentType, agent_id: String },
+    ChangeApplied { change_id: String, file: String, score: Option<f64> },    // Kept after review; no score for protected changes
+    ChangeRolledBack { change_id: String, file: String, score: Option<f64> }, // Undone, or held back from disk under evaluate-before-apply
+    CycleCompleted { kept: usize, snapshot: Option<String> },
+    AgentFailed { task_id: String, agent_id: String, error: String },
+    AgentDisabled { agent_id: String, rollback_rate: f64 },
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
 pub struct OrchestratorStats {
     pub total_tasks_executed: usize,
     pub successful_changes: usize,
     pub rolled_back_changes: usize,
     pub agents_active: usize,
     pub last_activity: Option<chrono::DateTime<Utc>>,
+    pub tasks_served: HashMap<AgentType, usize>,
+    pub quarantined_changes: usize,
+    pub agent_scores: HashMap<String, AgentScore>,
+    pub improvement_rate: f64, // Mean kept changes per tick over the plateau window
+    pub plateau_detected: bool,
 }
 
 impl AgentOrchestrator {
     pub fn new(base_path: PathBuf) -> Self {
-        let version_control = Arc::new(VersionControl::new(base_path.clone()));
-        let evaluator = Arc::new(ChangeEvaluator::new());
+        Self::with_profile(base_path, AgentProfile::default())
+    }
+
+    /// Build an orchestrator using the profile selected for `environment`
+    pub fn from_config(base_path: PathBuf, config: &AgentConfig, environment: Environment) -> Result<Self, BrionError> {
+        Ok(Self::with_profile(base_path, config.profile(environment)?))
+    }
+
+    fn with_profile(base_path: PathBuf, profile: AgentProfile) -> Self {
+        let mut version_control = VersionControl::new(base_path.clone());
+        if let Some(max_lines) = profile.max_diff_lines {
+            version_control = version_control.with_max_diff_lines(max_lines);
+        }
+        if let Some(max) = profile.max_snapshots {
+            version_control = version_control.with_max_snapshots(max);
+        }
+        match version_control.load_from_disk() {
+            Ok(0) => {}
+            Ok(count) => info!("Loaded {} changes from the journal", count),
+            Err(e) => error!("Failed to load the change journal: {}", e),
+        }
+        let version_control = Arc::new(version_control);
+        let evaluator = Arc::new(ChangeEvaluator::new().with_threshold(profile.min_score_threshold));
         let task_queue = Arc::new(TaskQueue::new());
 
         Self {
@@ -49,48 +320,489 @@ impl AgentOrchestrator {
             base_path,
             is_running: Arc::new(RwLock::new(false)),
             stats: Arc::new(RwLock::new(OrchestratorStats::default())),
+            fairness_mode: false,
+            profile,
+            quarantine: Arc::new(RwLock::new(HashMap::new())),
+            quarantine_ttl: chrono::Duration::hours(1),
+            diagnostics_interval: None,
+            listeners: Arc::new(RwLock::new(Vec::new())),
+            selection_strategy: SelectionStrategy::RoundRobin,
+            selection_weights: Arc::new(RwLock::new(HashMap::new())),
+            round_robin: Arc::new(RwLock::new(HashMap::new())),
+            suggest_mode: false,
+            suggestions: Arc::new(RwLock::new(Vec::new())),
+            scopes: Arc::new(RwLock::new(HashMap::new())),
+            evaluation_parallelism: 1,
+            snapshot_template: None,
+            recent_results: Arc::new(RwLock::new(BoundedRingBuffer::new(DEFAULT_RECENT_RESULTS_CAPACITY))),
+            evaluate_before_apply: false,
+            trusted_agents: Arc::new(RwLock::new(HashSet::new())),
+            default_targets: HashMap::from([
+                (AgentType::UIAgent, vec!["styles/main.css".to_string()]),
+                (AgentType::PerformanceAgent, vec!["scripts/main.js".to_string(), "index.html".to_string()]),
+                (AgentType::SecurityAgent, vec!["index.html".to_string(), "scripts/main.js".to_string()]),
+                (AgentType::SEOAgent, vec!["index.html".to_string()]),
+                (AgentType::DesignAgent, vec!["styles/main.css".to_string()]),
+            ]),
+            target_globs: HashMap::new(),
+            design_tokens: None,
+            tick_history: Arc::new(RwLock::new(BoundedRingBuffer::new(DEFAULT_PLATEAU_TICKS))),
+            pause_on_plateau: false,
+            min_confidence: None,
+            max_concurrency: 1,
+            files_in_flight: Arc::new(RwLock::new(HashSet::new())),
+            file_locks: FileLocks::new(),
+            tick_interval: Arc::new(RwLock::new(DEFAULT_TICK_INTERVAL)),
+            max_files_per_task: None,
+            auto_remediate: None,
+            cycle_changes: Arc::new(RwLock::new(Vec::new())),
+            wake: Arc::new(Notify::new()),
+            loop_running: Arc::new(watch::channel(false).0),
+            shutdown_timeout: DEFAULT_SHUTDOWN_TIMEOUT,
+            auto_disable: None,
+            recent_outcomes: Arc::new(RwLock::new(HashMap::new())),
+            disabled_agents: Arc::new(RwLock::new(HashSet::new())),
+        }
+    }
+
+    /// Stop selecting an agent once at least `max_rollback_rate` of its last
+    /// `window` reviewed changes were rolled back; `reenable_agent` brings
+    /// it back. A rate of zero or less would disable every agent as soon as
+    /// its window fills, so it's refused.
+    pub fn with_auto_disable(mut self, window: usize, max_rollback_rate: f64) -> Result<Self, BrionError> {
+        if max_rollback_rate.is_nan() || max_rollback_rate <= 0.0 {
+            return Err(BrionError::Other(format!(
+                "Auto-disable rollback rate must be above 0.0, got {}", max_rollback_rate)));
         }
+        self.auto_disable = Some((window.max(1), max_rollback_rate.min(1.0)));
+        Ok(self)
     }
 
-    pub fn register_agent(&self, agent: Box<dyn Agent + Send + Sync>) {
+    /// Longest `shutdown` waits for in-flight tasks before giving up on them
+    pub fn with_shutdown_timeout(mut self, timeout: Duration) -> Self {
+        self.shutdown_timeout = timeout;
+        self
+    }
+
+    /// Score changes with `evaluator` instead of the default one built from
+    /// the profile's threshold
+    pub fn with_evaluator(mut self, evaluator: ChangeEvaluator) -> Self {
+        self.evaluator = Arc::new(evaluator);
+        self
+    }
+
+    /// Files an agent type works on when a task has no targets; the task
+    /// runs once per file
+    pub fn with_default_targets(mut self, agent_type: AgentType, targets: Vec<String>) -> Self {
+        self.default_targets.insert(agent_type, targets);
+        self
+    }
+
+    /// Generate tasks for `agent_type` targeting every file matching
+    /// `pattern` (such as `pages/**/*.html`), found afresh each cycle. When
+    /// nothing matches, the default targets are used.
+    pub fn with_target_glob(mut self, agent_type: AgentType, pattern: &str) -> Self {
+        self.target_globs.insert(agent_type, pattern.to_string());
+        self
+    }
+
+    /// Generate design tasks syncing stylesheets with the token file at
+    /// `path` (relative to the base path). Without one no design tasks are
+    /// generated, as there would be nothing to sync.
+    pub fn with_design_tokens(mut self, path: &str) -> Self {
+        self.design_tokens = Some(path.to_string());
+        self
+    }
+
+    /// Have agents only propose changes; the orchestrator writes a change to
+    /// disk once it passes evaluation. Agents that can't defer are refused.
+    pub fn with_evaluate_before_apply(mut self, enabled: bool) -> Self {
+        self.evaluate_before_apply = enabled;
+        self
+    }
+
+    /// Run up to `tasks` tasks of different agent types at once per tick.
+    /// Tasks writing the same file never run together.
+    pub fn with_max_concurrency(mut self, tasks: usize) -> Self {
+        self.max_concurrency = tasks.max(1);
+        self
+    }
+
+    /// Cap the files one task may target or modify. Tasks targeting more
+    /// are refused or split per `policy`; a task whose agent modified more
+    /// files anyway has its changes rolled back and fails.
+    pub fn with_max_files_per_task(mut self, max_files: usize, policy: FileLimitPolicy) -> Self {
+        self.max_files_per_task = Some((max_files.max(1), policy));
+        self
+    }
+
+    /// Keep the task queue in `path` (relative to the site root unless
+    /// absolute): queued tasks are loaded from it now, along with tasks that
+    /// were running when it was last saved, and the queue is saved back as
+    /// it changes, after every tick and on `stop`
+    pub fn with_task_queue_file(mut self, path: PathBuf) -> Self {
+        let path = self.base_path.join(path);
+        let task_queue = TaskQueue::new().with_persistence(path.clone());
+        task_queue.set_durability_policy(self.durability());
+        match task_queue.load_from(&path) {
+            Ok(0) => {}
+            Ok(count) => info!("Loaded {} queued tasks from {}", count, path.display()),
+            Err(e) => error!("Failed to load the task queue from {}: {}", path.display(), e),
+        }
+        self.task_queue = Arc::new(task_queue);
+        self
+    }
+
+    /// Choose between fsyncing every write (`Strict`) and leaving it to the
+    /// OS (`Fast`), for site files, the journal and the task queue file.
+    /// Agents pick it up from the version control they record into.
+    pub fn with_durability_policy(self, policy: DurabilityPolicy) -> Self {
+        self.version_control.set_durability_policy(policy);
+        self.task_queue.set_durability_policy(policy);
+        self
+    }
+
+    fn durability(&self) -> DurabilityPolicy {
+        self.version_control.durability_policy()
+    }
+
+    /// Only auto-apply changes from results whose reported confidence
+    /// exceeds `min_confidence`; the rest become suggestions awaiting
+    /// approval. Results without a confidence are treated as confident.
+    pub fn with_min_confidence(mut self, min_confidence: f64) -> Self {
+        self.min_confidence = Some(min_confidence);
+        self
+    }
+
+    /// When a kept change carries recommendations for another agent type,
+    /// queue a task for that agent on the same file. Remediation tasks can
+    /// queue their own, up to `max_depth` tasks behind the original.
+    pub fn with_auto_remediate(mut self, max_depth: usize) -> Self {
+        self.auto_remediate = Some(max_depth);
+        self
+    }
+
+    /// Declare a plateau once the kept-changes rate has stayed near zero for
+    /// `ticks` ticks, optionally stopping the improvement loop when it does
+    pub fn with_plateau_detection(mut self, ticks: usize, pause_on_plateau: bool) -> Self {
+        self.tick_history = Arc::new(RwLock::new(BoundedRingBuffer::new(ticks.max(1))));
+        self.pause_on_plateau = pause_on_plateau;
+        self
+    }
+
+    /// How many task results to retain for `get_recent_results`
+    pub fn with_recent_results_capacity(mut self, capacity: usize) -> Self {
+        self.recent_results = Arc::new(RwLock::new(BoundedRingBuffer::new(capacity)));
+        self
+    }
+
+    /// Create a snapshot after every task that keeps changes, described by
+    /// `template` (see `VersionControl::create_templated_snapshot`)
+    pub fn with_snapshot_template(mut self, template: String) -> Self {
+        self.snapshot_template = Some(template);
+        self
+    }
+
+    /// Evaluate up to `threads` changes from one task concurrently
+    pub fn with_evaluation_parallelism(mut self, threads: usize) -> Self {
+        self.evaluation_parallelism = threads.max(1);
+        self
+    }
+
+    /// Evaluate agent changes but revert them and hold them as suggestions
+    /// for review instead of keeping them on disk
+    pub fn with_suggest_mode(mut self, enabled: bool) -> Self {
+        self.suggest_mode = enabled;
+        self
+    }
+
+    pub fn with_selection_strategy(mut self, strategy: SelectionStrategy) -> Self {
+        self.selection_strategy = strategy;
+        self
+    }
+
+    /// Periodically check tracked files on disk against the journal
+    pub fn with_diagnostics_interval(mut self, period: Duration) -> Self {
+        self.diagnostics_interval = Some(period);
+        self
+    }
+
+    pub fn on_event<F>(&self, listener: F)
+    where
+        F: Fn(&OrchestratorEvent) + Send + Sync + 'static,
+    {
+        self.listeners.write().push(Box::new(move |event| {
+            listener(event);
+            true
+        }));
+    }
+
+    /// Receive every event on a channel instead of through a callback.
+    /// Receivers that fall more than `capacity` events behind miss the
+    /// oldest ones.
+    pub fn subscribe(&self, capacity: usize) -> broadcast::Receiver<OrchestratorEvent> {
+        let (sender, receiver) = broadcast::channel(capacity.max(1));
+        // Once every receiver is dropped the send fails and the listener
+        // is removed
+        self.listeners.write().push(Box::new(move |event| sender.send(event.clone()).is_ok()));
+        receiver
+    }
+
+    fn emit(&self, event: OrchestratorEvent) {
+        self.listeners.write().retain(|listener| listener(&event));
+    }
+
+    /// How long a rejected change stays quarantined before it may be
+    /// evaluated again
+    pub fn with_quarantine_ttl(mut self, ttl: chrono::Duration) -> Self {
+        self.quarantine_ttl = ttl;
+        self
+    }
+
+    /// Hand each tick's slots, one per agent type, to whichever type has
+    /// been served least so far instead of one to each type, so types that
+    /// fell behind get more than one until they catch up
+    pub fn with_fairness_mode(mut self, enabled: bool) -> Self {
+        self.fairness_mode = enabled;
+        self
+    }
+
+    pub fn register_agent(&self, mut agent: Box<dyn Agent + Send + Sync>) {
         let agent_type = agent.get_type();
-        self.agents.write()
-            .entry(agent_type)
-            .or_insert_with(Vec::new)
-            .push(agent);
-        
-        let mut stats = self.stats.write();
-        stats.agents_active = self.agents.read().values().map(|v| v.len()).sum();
+        if !self.profile.is_enabled(&agent_type) {
+            warn!("Agent {} not registered: {:?} is disabled by profile", agent.get_id(), agent_type);
+            return;
+        }
+        if self.evaluate_before_apply && !agent.set_deferred_apply(true) {
+            warn!("Agent {} not registered: it can't defer applying changes until evaluation", agent.get_id());
+            return;
+        }
+        // A scoped agent would record paths relative to its subtree, so it
+        // gets no journal and the orchestrator records its changes instead
+        if !self.scopes.read().contains_key(agent.get_id()) {
+            agent.set_version_control(self.version_control.clone());
+        }
+        agent.set_file_locks(self.file_locks.clone());
+
+        // Publish the count while still holding the registry lock so
+        // concurrent registrations can't overwrite it with a stale total
+        let mut agents = self.agents.write();
+        agents.entry(agent_type)
+            .or_default()
+            .push(Arc::from(agent));
+
+        self.stats.write().agents_active = agents.values().map(|v| v.len()).sum();
+    }
+
+    /// Register an agent that may only work inside `scope` (relative to the
+    /// base path). The agent sees the scope as its root directory.
+    pub fn register_scoped_agent(&self, agent: Box<dyn Agent + Send + Sync>, scope: PathBuf) {
+        self.scopes.write().insert(agent.get_id().to_string(), scope);
+        self.register_agent(agent);
+    }
+
+    /// Register an agent whose changes skip the heuristic evaluator and are
+    /// kept unless a hard safety guard trips
+    pub fn register_trusted_agent(&self, agent: Box<dyn Agent + Send + Sync>) {
+        self.trusted_agents.write().insert(agent.get_id().to_string());
+        self.register_agent(agent);
+    }
+
+    fn scope_of(&self, agent: &dyn Agent) -> Option<PathBuf> {
+        self.scopes.read().get(agent.get_id()).cloned()
     }
 
-    pub fn start_continuous_improvement(self: Arc<Self>) {
+    /// Whether every file `task` works on lies inside the agent's scope. A
+    /// task with no files at all could touch anything, so it isn't.
+    fn in_scope(&self, agent: &dyn Agent, task: &AgentTask) -> bool {
+        match self.scope_of(agent) {
+            Some(scope) => {
+                let targets = self.task_files(task);
+                !targets.is_empty() && targets.iter().all(|target| {
+                    // `..` could climb back out of the scope after matching its prefix
+                    let target = std::path::Path::new(target);
+                    target.starts_with(&scope)
+                        && !target.components().any(|c| matches!(c, std::path::Component::ParentDir))
+                })
+            }
+            None => true,
+        }
+    }
+
+    /// Clone the agent references so a processing pass doesn't hold the
+    /// registry lock while tasks execute.
+    fn snapshot_agents(&self) -> Vec<(AgentType, Vec<SharedAgent>)> {
+        self.agents.read()
+            .iter()
+            .map(|(agent_type, agent_list)| (agent_type.clone(), agent_list.clone()))
+            .collect()
+    }
+
+    fn select_agent(&self, agent_list: &[SharedAgent], task: &AgentTask) -> Option<SharedAgent> {
+        let disabled = self.disabled_agents.read().clone();
+        let eligible = |agent: &SharedAgent| {
+            !disabled.contains(agent.get_id()) && self.in_scope(agent.as_ref(), task) && agent.can_handle(task)
+        };
+
+        match self.selection_strategy {
+            SelectionStrategy::First => agent_list.iter().find(|agent| eligible(agent)).cloned(),
+            SelectionStrategy::RoundRobin => {
+                // Walk the full registered list from where the last pick left
+                // off, so the rotation stays stable as eligibility changes
+                let mut next = self.round_robin.write();
+                let start = next.get(&task.agent_type).copied().unwrap_or(0);
+                let chosen = (0..agent_list.len())
+                    .map(|offset| (start + offset) % agent_list.len())
+                    .find(|&index| eligible(&agent_list[index]))?;
+                next.insert(task.agent_type.clone(), chosen + 1);
+                Some(agent_list[chosen].clone())
+            }
+            SelectionStrategy::PerformanceWeighted => {
+                let agent_list: Vec<SharedAgent> = agent_list.iter()
+                    .filter(|agent| eligible(agent))
+                    .cloned()
+                    .collect();
+                let weights: Vec<f64> = {
+                    let stats = self.stats.read();
+                    agent_list.iter()
+                        .map(|agent| {
+                            let score = stats.agent_scores.get(agent.get_id()).cloned().unwrap_or_default();
+                            if score.kept + score.rolled_back < EXPLORATION_EVALUATIONS {
+                                1.0
+                            } else {
+                                score.keep_rate().max(0.05)
+                            }
+                        })
+                        .collect()
+                };
+
+                // Smooth weighted round-robin: picks agents in proportion to
+                // their weight without needing a random source
+                let total: f64 = weights.iter().sum();
+                let mut current = self.selection_weights.write();
+                let mut best: Option<(usize, f64)> = None;
+                for (index, agent) in agent_list.iter().enumerate() {
+                    let value = current.entry(agent.get_id().to_string()).or_insert(0.0);
+                    *value += weights[index];
+                    if best.map(|(_, v)| *value > v).unwrap_or(true) {
+                        best = Some((index, *value));
+                    }
+                }
+
+                let (index, _) = best?;
+                if let Some(value) = current.get_mut(agent_list[index].get_id()) {
+                    *value -= total;
+                }
+                Some(agent_list[index].clone())
+            }
+        }
+    }
+
+    /// Run improvement ticks in the background until `stop` or `shutdown`.
+    /// The handle resolves once the loop has exited, after the tick in
+    /// progress finishes.
+    pub fn start_continuous_improvement(self: Arc<Self>) -> JoinHandle<()> {
+        if let Err(e) = self.recover() {
+            error!("Recovery failed: {}", e);
+        }
+
         *self.is_running.write() = true;
         let orchestrator = Arc::clone(&self);
+
+        if let Some(period) = self.diagnostics_interval {
+            let diagnostics = Arc::clone(&self);
+            tokio::spawn(async move {
+                let mut interval = interval(period);
+                loop {
+                    interval.tick().await;
+                    if !*diagnostics.is_running.read() {
+                        break;
+                    }
+                    diagnostics.run_diagnostics();
+                }
+            });
+        }
         
+        orchestrator.loop_running.send_replace(true);
         tokio::spawn(async move {
-            let mut interval = interval(Duration::from_secs(30)); // Check every 30 seconds
+            let mut idle_ticks = 0u32;
+            let mut delay = Duration::ZERO; // First tick runs immediately
             
             loop {
-                interval.tick().await;
+                // Re-read every tick so set_interval takes effect while running
+                tokio::select! {
+                    _ = tokio::time::sleep(delay) => {}
+                    _ = orchestrator.wake.notified() => {}
+                }
+                delay = *orchestrator.tick_interval.read();
                 
                 if !*orchestrator.is_running.read() {
                     break;
                 }
 
+                // With nothing to run the tasks, generating more would only
+                // grow the queue; pause and remind the operator now and then
+                if !orchestrator.has_agents() {
+                    if idle_ticks.is_multiple_of(NO_AGENTS_WARNING_TICKS) {
+                        warn!("No agents registered; task generation is paused until one is");
+                    }
+                    idle_ticks += 1;
+                    continue;
+                }
+                idle_ticks = 0;
+
                 // Generate tasks automatically
                 orchestrator.generate_improvement_tasks().await;
                 
                 // Process tasks
                 orchestrator.process_task_queue().await;
             }
-        });
+            orchestrator.loop_running.send_replace(false);
+        })
+    }
+
+    fn has_agents(&self) -> bool {
+        self.agents.read().values().any(|agent_list| !agent_list.is_empty())
+    }
+
+    /// Change the time between improvement ticks; a running loop picks it
+    /// up after its current wait
+    pub fn set_interval(&self, period: Duration) -> Result<(), BrionError> {
+        if period.is_zero() {
+            return Err(BrionError::Other("Tick interval must be greater than zero".to_string()));
+        }
+        *self.tick_interval.write() = period;
+        Ok(())
     }
 
     pub fn stop(&self) {
         *self.is_running.write() = false;
+        self.wake.notify_one();
+        self.task_queue.flush();
+    }
+
+    /// Stop starting tasks and wait for the ones already running to finish,
+    /// up to the shutdown timeout. Returns false if the timeout ran out
+    /// with tasks still in flight.
+    pub async fn shutdown(&self) -> bool {
+        self.stop();
+        let mut running = self.loop_running.subscribe();
+        let waited = tokio::time::timeout(self.shutdown_timeout, running.wait_for(|running| !running)).await;
+        match waited {
+            Ok(_) => {
+                info!("Continuous improvement shut down cleanly");
+                true
+            }
+            Err(_) => {
+                warn!("Tasks still running after {:?}, shutting down anyway", self.shutdown_timeout);
+                false
+            }
+        }
     }
 
     async fn generate_improvement_tasks(&self) {
+        use crate::agents::file_ops::FileOperations;
+
         // Automatically generate tasks for continuous improvement
         let task_types = vec![
             (AgentType::PerformanceAgent, "Optimize page load performance", 7),
@@ -99,121 +811,3360 @@ impl AgentOrchestrator {
             (AgentType::FeatureAgent, "Add new interactive features", 8),
             (AgentType::AccessibilityAgent, "Enhance accessibility", 6),
             (AgentType::SEOAgent, "Improve SEO optimization", 5),
+            (AgentType::SecurityAgent, "Harden security", 7),
+            (AgentType::DesignAgent, "Sync design tokens", 5),
         ];
 
+        let disabled = self.disabled_agents.read().clone();
+        let registered: HashSet<AgentType> = self.agents.read()
+            .iter()
+            .filter(|(_, agent_list)| agent_list.iter().any(|agent| !disabled.contains(agent.get_id())))
+            .map(|(agent_type, _)| agent_type.clone())
+            .collect();
+
         for (agent_type, description, priority) in task_types {
+            // Tasks for types nobody handles would never leave the queue
+            if !registered.contains(&agent_type) {
+                continue;
+            }
+
+            let mut parameters = HashMap::new();
+            if agent_type == AgentType::DesignAgent {
+                match &self.design_tokens {
+                    Some(path) => {
+                        parameters.insert("design_tokens".to_string(), path.clone());
+                    }
+                    None => continue,
+                }
+            }
+
             let task = AgentTask {
                 id: Uuid::new_v4().to_string(),
                 agent_type: agent_type.clone(),
                 priority,
                 description: description.to_string(),
                 target_file: None,
-                parameters: HashMap::new(),
+                target_files: self.target_globs.get(&agent_type)
+                    .map(|pattern| FileOperations::glob(&self.base_path, pattern))
+                    .unwrap_or_default(),
+                parameters,
                 created_at: Utc::now(),
+                depends_on: Vec::new(),
             };
 
-            self.task_queue.add_task(task);
+            // Skip work that's already waiting, so a slow queue doesn't fill with repeats
+            self.task_queue.add_task_dedup(task);
         }
     }
 
-    async fn process_task_queue(&self) {
-        let agents = self.agents.read();
-        
-        for (agent_type, agent_list) in agents.iter() {
-            if agent_list.is_empty() {
-                continue;
+    async fn process_task_queue(self: &Arc<Self>) {
+        let kept_before = self.total_kept();
+        let mut agents = self.snapshot_agents();
+        agents.retain(|(_, agent_list)| !agent_list.is_empty());
+        let mut served = if self.fairness_mode { self.stats.read().tasks_served.clone() } else { HashMap::new() };
+
+        // One slot per agent type, its tasks run concurrently up to the cap.
+        // Normally each type gets one slot; under fairness mode each slot
+        // goes to the least-served type that still has work. Tasks whose
+        // files are already claimed wait for the next tick.
+        let permits = Arc::new(Semaphore::new(self.max_concurrency));
+        let mut running = Vec::new();
+        let mut slots = agents.len();
+        while slots > 0 && !agents.is_empty() {
+            // A stop mid-tick lets started tasks finish but starts no more
+            if !*self.is_running.read() {
+                break;
             }
+            let index = if self.fairness_mode {
+                (0..agents.len())
+                    .min_by_key(|&i| (served.get(&agents[i].0).copied().unwrap_or(0), format!("{:?}", agents[i].0)))
+                    .unwrap_or(0)
+            } else {
+                0
+            };
+            let agent_type = agents[index].0.clone();
 
-            // Get next task for this agent type
-            if let Some(task) = self.task_queue.get_next_task(Some(agent_type.clone())) {
-                // Select an agent (round-robin or based on availability)
-                if let Some(agent) = agent_list.first() {
-                    match self.execute_task_with_agent(agent.as_ref(), &task).await {
-                        Ok(result) => {
-                            info!("Task {} completed by agent {}", task.id, result.agent_id);
-                            self.task_queue.mark_completed(task);
-                            
-                            let mut stats = self.stats.write();
-                            stats.total_tasks_executed += 1;
-                            if result.success {
-                                stats.successful_changes += result.changes.len();
-                            }
-                            stats.last_activity = Some(Utc::now());
-                        }
-                        Err(e) => {
-                            error!("Task {} failed: {}", task.id, e);
-                        }
-                    }
+            // Get next task for this agent type; a type without one is done for the tick
+            let task = match self.task_queue.get_next_task(Some(agent_type.clone())) {
+                Some(task) => task,
+                None => {
+                    agents.remove(index);
+                    continue;
+                }
+            };
+            slots -= 1;
+            let agent_list = if self.fairness_mode { agents[index].1.clone() } else { agents.remove(index).1 };
+
+            // Select an agent according to the configured strategy
+            let agent = match self.select_agent(&agent_list, &task) {
+                Some(agent) => agent,
+                None => {
+                    warn!("No enabled {:?} agent is scoped for task {}, requeueing", task.agent_type, task.id);
+                    self.task_queue.add_task(task);
+                    agents.retain(|(other, _)| *other != agent_type);
+                    continue;
+                }
+            };
+
+            let claim = match self.claim_files(&task) {
+                Some(claim) => claim,
+                None => {
+                    info!("Task {} targets a file another task is writing, deferring", task.id);
+                    self.task_queue.add_task(task);
+                    agents.retain(|(other, _)| *other != agent_type);
+                    continue;
+                }
+            };
+            *served.entry(agent_type).or_insert(0) += 1;
+
+            let orchestrator = Arc::clone(self);
+            let permits = Arc::clone(&permits);
+            running.push(tokio::spawn(async move {
+                let _claim = claim;
+                let _permit = permits.acquire_owned().await;
+                let task_id = task.id.clone();
+                if let Err(e) = orchestrator.dispatch_task(&agent, task).await {
+                    error!("Task {} failed: {}", task_id, e);
                 }
+            }));
+        }
+
+        for handle in running {
+            if let Err(e) = handle.await {
+                error!("Task panicked: {}", e);
             }
         }
+
+        let kept = self.total_kept().saturating_sub(kept_before);
+        self.record_tick(kept);
+        self.task_queue.flush();
+        let snapshot = self.snapshot_cycle();
+        self.emit(OrchestratorEvent::CycleCompleted { kept, snapshot });
     }
 
-    async fn execute_task_with_agent(
-        &self,
-        agent: &dyn Agent,
-        task: &AgentTask,
-    ) -> Result<AgentResult, String> {
-        let result = agent.execute_task(task, &self.base_path)?;
+    /// Snapshot the changes kept since the last cycle snapshot, so every
+    /// productive tick or drain leaves a restore point. With a snapshot
+    /// template each task already left one, so none is made. Returns the
+    /// snapshot's version ID.
+    fn snapshot_cycle(&self) -> Option<String> {
+        let kept = std::mem::take(&mut *self.cycle_changes.write());
+        if kept.is_empty() || self.snapshot_template.is_some() {
+            return None;
+        }
+        let version_id = self.version_control.create_templated_snapshot(CYCLE_SNAPSHOT_TEMPLATE, &kept);
+        info!("Created snapshot {} for improvement cycle ({} changes)", version_id, kept.len());
+        Some(version_id)
+    }
 
-        // Record and evaluate changes
-        for change_id in &result.changes {
-            // Get the change from the agent (agents should store changes temporarily)
-            // For now, we'll create a placeholder evaluation
-            // In a full implementation, agents would return full Change objects
-            
-            // The change should already be recorded by the agent via version control
-            // We just need to evaluate it
-            if let Some(change) = self.version_control.get_change(change_id) {
-                // Evaluate the change
-                let evaluation = self.evaluator.evaluate_change(&change);
-                
-                // Update change with evaluation score
-                let mut updated_change = change.clone();
-                updated_change.evaluation_score = Some(evaluation.overall_score);
-                self.version_control.record_change(updated_change.clone());
-
-                // Decide whether to keep or rollback
-                if !evaluation.should_keep {
-                    warn!("Change {} scored below threshold ({:.2}), rolling back", 
-                        change_id, evaluation.overall_score);
-                    self.rollback_change(change_id)?;
-                    
-                    let mut stats = self.stats.write();
-                    stats.rolled_back_changes += 1;
-                } else {
-                    info!("Change {} approved with score {:.2}", 
-                        change_id, evaluation.overall_score);
+    /// Files a task works on: its targets, the targets it was split with,
+    /// or the default targets for its agent type
+    fn task_files(&self, task: &AgentTask) -> Vec<String> {
+        let targets = task.targets();
+        if !targets.is_empty() {
+            return targets;
+        }
+        match task.get_param(TARGETS_PARAM) {
+            Some(targets) => targets.split(',').map(|t| t.trim().to_string()).filter(|t| !t.is_empty()).collect(),
+            None => self.default_targets.get(&task.agent_type).cloned().unwrap_or_default(),
+        }
+    }
+
+    /// Every file a task may write: its own, plus the pages a cache-busting
+    /// rename rewrites, the stylesheets font self-hosting creates and the
+    /// pages a content update scans
+    fn written_files(&self, task: &AgentTask) -> Vec<String> {
+        use crate::agents::file_ops::{FileKind, FileOperations};
+        use crate::agents::fonts::FontLoading;
+
+        let mut files = self.task_files(task);
+        let flag = |key: &str| task.get_bool(key).ok().flatten().unwrap_or(false);
+        match task.agent_type {
+            AgentType::PerformanceAgent => {
+                if flag("self_host_fonts") {
+                    for page in files.clone() {
+                        if let Ok(content) = FileOperations::read_file(&self.base_path.join(&page)) {
+                            files.extend(FontLoading::google_fonts_links(&content).iter()
+                                .map(|href| FontLoading::local_stylesheet(href)));
+                        }
+                    }
                 }
+                if flag("cache_bust") {
+                    files.extend(FileOperations::possible_pages(&self.base_path));
+                }
+            }
+            AgentType::ContentAgent if task.targets().is_empty() => {
+                files.extend(FileOperations::list_files(&self.base_path).into_iter()
+                    .filter(|file| FileKind::from_extension(std::path::Path::new(file)) == FileKind::Html));
             }
+            _ => {}
         }
+        files.sort();
+        files.dedup();
+        files
+    }
 
-        Ok(result)
+    /// Mark the files the task may write as being written, or return
+    /// `None` if another running task already holds one of them
+    fn claim_files(&self, task: &AgentTask) -> Option<FileClaim> {
+        let files = self.written_files(task);
+        let mut in_flight = self.files_in_flight.write();
+        if files.iter().any(|file| in_flight.contains(file)) {
+            return None;
+        }
+        in_flight.extend(files.iter().cloned());
+        Some(FileClaim { files, in_flight: Arc::clone(&self.files_in_flight) })
     }
 
-    pub fn rollback_change(&self, change_id: &str) -> Result<(), String> {
-        let change = self.version_control.rollback_change(change_id)?;
-        
-        // Restore the file to its previous state
-        use crate::agents::file_ops::FileOperations;
-        FileOperations::rollback_change(&change, &self.base_path)?;
-        
-        info!("Rolled back change {} in file {}", change_id, change.file_path);
-        
-        Ok(())
+    fn total_kept(&self) -> usize {
+        self.stats.read().agent_scores.values().map(|score| score.kept).sum()
     }
 
-    pub fn get_stats(&self) -> OrchestratorStats {
-        self.stats.read().clone()
+    /// Fold one tick's kept-change count into the improvement rate and
+    /// check for a plateau
+    fn record_tick(&self, kept: usize) {
+        let (rate, window_full) = {
+            let mut history = self.tick_history.write();
+            history.push(kept);
+            let rate = history.iter().sum::<usize>() as f64 / history.len() as f64;
+            (rate, history.len() == history.capacity())
+        };
+
+        let plateau = window_full && rate <= PLATEAU_RATE;
+        let newly_detected = {
+            let mut stats = self.stats.write();
+            let newly_detected = plateau && !stats.plateau_detected;
+            stats.improvement_rate = rate;
+            stats.plateau_detected = plateau;
+            newly_detected
+        };
+
+        if newly_detected {
+            warn!("Improvement has plateaued ({:.2} kept changes per tick)", rate);
+            if self.pause_on_plateau {
+                info!("Pausing continuous improvement after plateau");
+                self.stop();
+            }
+        }
     }
 
-    pub fn get_version_control(&self) -> Arc<VersionControl> {
-        self.version_control.clone()
+    /// Run the next queued task for `agent_type` on an agent picked by the
+    /// selection strategy, snapshotting what it kept. Returns `Ok(None)`
+    /// when nothing is queued for that type.
+    pub async fn run_agent_once(&self, agent_type: AgentType) -> Result<Option<AgentResult>, BrionError> {
+        let result = self.run_next_task(agent_type).await;
+        self.task_queue.flush();
+        self.snapshot_cycle();
+        result
     }
 
-    pub fn get_task_queue(&self) -> Arc<TaskQueue> {
-        self.task_queue.clone()
+    /// `run_agent_once` without the snapshot
+    async fn run_next_task(&self, agent_type: AgentType) -> Result<Option<AgentResult>, BrionError> {
+        let agent_list = self.agents.read().get(&agent_type).cloned().unwrap_or_default();
+        if agent_list.is_empty() {
+            return Err(BrionError::Other(format!("No agent registered for {:?}", agent_type)));
+        }
+
+        let task = match self.task_queue.get_next_task(Some(agent_type.clone())) {
+            Some(task) => task,
+            None => return Ok(None),
+        };
+
+        let agent = match self.select_agent(&agent_list, &task) {
+            Some(agent) => agent,
+            None => {
+                let task_id = task.id.clone();
+                self.task_queue.add_task(task);
+                return Err(BrionError::Other(format!("No enabled {:?} agent is scoped for task {}", agent_type, task_id)));
+            }
+        };
+
+        let _claim = match self.claim_files(&task) {
+            Some(claim) => claim,
+            None => {
+                let task_id = task.id.clone();
+                self.task_queue.add_task(task);
+                return Err(BrionError::Other(format!("Task {} targets a file another task is writing", task_id)));
+            }
+        };
+        self.dispatch_task(&agent, task).await.map(Some)
     }
-}
 
+    /// Process queued tasks until the queue is empty or a full pass over
+    /// the registered agent types makes no progress, e.g. because the
+    /// remaining tasks have no agent or wait on dependencies that can't
+    /// finish. What the tasks kept is snapshotted once at the end. Returns
+    /// every result in the order tasks completed.
+    pub async fn drain_queue(&self) -> Vec<AgentResult> {
+        let mut results = Vec::new();
+        loop {
+            let agent_types: Vec<AgentType> = self.snapshot_agents()
+                .into_iter()
+                .filter(|(_, agent_list)| !agent_list.is_empty())
+                .map(|(agent_type, _)| agent_type)
+                .collect();
+
+            // A failed task still leaves the queue, so that counts as progress
+            let queued_before = self.task_queue.get_queue_size();
+            let mut progressed = false;
+            for agent_type in agent_types {
+                match self.run_next_task(agent_type).await {
+                    Ok(Some(result)) => {
+                        results.push(result);
+                        progressed = true;
+                    }
+                    Ok(None) => {}
+                    Err(e) => warn!("Drain: {}", e),
+                }
+            }
+            progressed |= self.task_queue.get_queue_size() < queued_before;
+
+            if !progressed || self.task_queue.get_queue_size() == 0 {
+                break;
+            }
+        }
+
+        let remaining = self.task_queue.get_queue_size();
+        if remaining > 0 {
+            warn!("Drain stopped with {} tasks that can't be processed", remaining);
+        }
+        self.task_queue.flush();
+        self.snapshot_cycle();
+        results
+    }
 
+    #[cfg_attr(feature = "otel", tracing::instrument(name = "task", skip_all,
+        fields(task_id = %task.id, agent_type = ?task.agent_type)))]
+    async fn dispatch_task(&self, agent: &SharedAgent, task: AgentTask) -> Result<AgentResult, BrionError> {
+        let task_id = task.id.clone();
+        self.task_queue.set_status(&task_id, TaskStatus::Running);
+        self.emit(OrchestratorEvent::TaskStarted {
+            task_id: task_id.clone(),
+            agent_type: agent.get_type(),
+            agent_id: agent.get_id().to_string(),
+        });
+        let result = self.run_task(agent, task).await;
+        if let Err(ref e) = result {
+            self.task_queue.set_status(&task_id, TaskStatus::Failed(e.to_string()));
+            self.emit(OrchestratorEvent::AgentFailed {
+                task_id,
+                agent_id: agent.get_id().to_string(),
+                error: e.to_string(),
+            });
+        }
+        result
+    }
+
+    async fn run_task(&self, agent: &SharedAgent, task: AgentTask) -> Result<AgentResult, BrionError> {
+        let targets = match (&task.target_file, task.target_files.is_empty()) {
+            (Some(_), true) => Vec::new(),
+            _ => self.task_files(&task),
+        };
+        if let Some((max_files, policy)) = self.max_files_per_task {
+            if targets.len() > max_files {
+                return Err(self.enforce_file_limit(&task, targets, max_files, policy));
+            }
+        }
+        let result = if targets.is_empty() {
+            self.execute_task_with_agent(agent.as_ref(), &task).await?
+        } else {
+            let mut results = Vec::new();
+            for target in targets {
+                results.push(self.execute_task_with_agent(agent.as_ref(), &task.for_target(target)).await?);
+            }
+            AgentResult::merge(&task, &agent.metrics_schema(), results)
+        };
+
+        let undeclared = undeclared_metrics(&agent.metrics_schema(), &result.metrics);
+        if !undeclared.is_empty() {
+            warn!("Agent {} emitted undeclared metrics: {}", result.agent_id, undeclared.join(", "));
+        }
+
+        info!("Task {} completed by agent {}", task.id, result.agent_id);
+        self.enqueue_follow_ups(&result);
+        self.task_queue.mark_completed(task);
+        self.recent_results.write().push(result.clone());
+        
+        let mut stats = self.stats.write();
+        stats.total_tasks_executed += 1;
+        *stats.tasks_served.entry(agent.get_type()).or_insert(0) += 1;
+        if result.success {
+            stats.successful_changes += result.changes.len();
+        }
+        stats.last_activity = Some(Utc::now());
+
+        Ok(result)
+    }
+
+    /// Refuse a task targeting more than `max_files`, or under `Split`
+    /// queue chunks of its targets as new tasks. Returns the error the
+    /// original task ends with either way.
+    fn enforce_file_limit(&self, task: &AgentTask, targets: Vec<String>, max_files: usize, policy: FileLimitPolicy) -> BrionError {
+        let message = format!("Task {} targets {} files, more than the limit of {}", task.id, targets.len(), max_files);
+        if policy == FileLimitPolicy::Split {
+            for chunk in targets.chunks(max_files) {
+                let mut part = task.clone();
+                part.id = Uuid::new_v4().to_string();
+                part.created_at = Utc::now();
+                part.target_file = None;
+                part.target_files = chunk.to_vec();
+                part.parameters.remove(TARGETS_PARAM);
+                info!("Splitting task {} into task {} for {}", task.id, part.id, chunk.join(", "));
+                self.task_queue.add_task(part);
+            }
+        }
+        warn!("{}{}", message, if policy == FileLimitPolicy::Split { ", split" } else { "" });
+        BrionError::Other(message)
+    }
+
+    /// Queue the follow-up tasks an agent discovered, up to a per-result cap
+    /// so a misbehaving agent can't flood the queue
+    fn enqueue_follow_ups(&self, result: &AgentResult) {
+        if result.follow_up_tasks.len() > MAX_FOLLOW_UPS_PER_RESULT {
+            warn!("Agent {} returned {} follow-up tasks, queueing the first {}",
+                result.agent_id, result.follow_up_tasks.len(), MAX_FOLLOW_UPS_PER_RESULT);
+        }
+        for follow_up in result.follow_up_tasks.iter().take(MAX_FOLLOW_UPS_PER_RESULT) {
+            if self.task_queue.add_task_dedup(follow_up.clone()) {
+                info!("Queueing follow-up task {} ({:?}) from agent {}", follow_up.id, follow_up.agent_type, result.agent_id);
+            }
+        }
+    }
+
+    /// Under auto-remediation, queue one task on the change's file for each
+    /// other registered agent type its recommendations point at, unless
+    /// `task` is already at the depth cap
+    fn queue_remediation(&self, task: &AgentTask, change: &Change, recommendations: &[Recommendation]) {
+        let max_depth = match self.auto_remediate {
+            Some(max_depth) => max_depth,
+            None => return,
+        };
+
+        let mut codes_by_type: HashMap<AgentType, Vec<String>> = HashMap::new();
+        {
+            let agents = self.agents.read();
+            for recommendation in recommendations {
+                if let Some(agent_type) = &recommendation.suggested_agent {
+                    if *agent_type != task.agent_type && agents.contains_key(agent_type) {
+                        codes_by_type.entry(agent_type.clone()).or_default().push(recommendation.code.clone());
+                    }
+                }
+            }
+        }
+        if codes_by_type.is_empty() {
+            return;
+        }
+
+        let depth = task.get_u64(REMEDIATION_DEPTH_PARAM).ok().flatten().unwrap_or(0) as usize;
+        if depth >= max_depth {
+            info!("Not remediating change {}: task {} is {} remediations deep", change.id, task.id, depth);
+            return;
+        }
+
+        for (agent_type, codes) in codes_by_type {
+            let remediation = AgentTask {
+                id: Uuid::new_v4().to_string(),
+                agent_type: agent_type.clone(),
+                priority: task.priority,
+                description: "Address evaluator recommendations".to_string(),
+                target_file: Some(change.file_path.clone()),
+                target_files: Vec::new(),
+                parameters: HashMap::from([
+                    (REMEDIATION_DEPTH_PARAM.to_string(), (depth + 1).to_string()),
+                    (RECOMMENDATIONS_PARAM.to_string(), codes.join(",")),
+                ]),
+                created_at: Utc::now(),
+                depends_on: Vec::new(),
+            };
+            if self.task_queue.add_task_dedup(remediation) {
+                info!("Queueing {:?} remediation of {} for change {} ({})",
+                    agent_type, change.file_path, change.id, codes.join(", "));
+            }
+        }
+    }
+
+    #[cfg_attr(feature = "otel", tracing::instrument(name = "execute", skip_all,
+        fields(task_id = %task.id, agent_id = %agent.get_id())))]
+    async fn execute_task_with_agent(
+        &self,
+        agent: &(dyn Agent + Send + Sync),
+        task: &AgentTask,
+    ) -> Result<AgentResult, BrionError> {
+        self.stats.write().agent_scores.entry(agent.get_id().to_string()).or_default().tasks_attempted += 1;
+        let result = match self.run_and_record(agent, task).await {
+            Ok(result) => result,
+            Err(e) => {
+                self.stats.write().agent_scores.entry(agent.get_id().to_string()).or_default().tasks_failed += 1;
+                return Err(e);
+            }
+        };
+
+        if let Some((max_files, _)) = self.max_files_per_task {
+            let files: HashSet<&String> = result.records.iter()
+                .map(|change| &change.file_path)
+                .collect();
+            if files.len() > max_files {
+                for change_id in result.changes.iter().rev() {
+                    self.reject_change(change_id)?;
+                }
+                {
+                    let mut stats = self.stats.write();
+                    stats.rolled_back_changes += result.changes.len();
+                    let score = stats.agent_scores.entry(agent.get_id().to_string()).or_default();
+                    score.rolled_back += result.changes.len();
+                    score.tasks_failed += 1;
+                }
+                for _ in &result.changes {
+                    self.record_outcome(agent.get_id(), false);
+                }
+                return Err(BrionError::Other(format!(
+                    "Agent {} modified {} files for task {}, more than the limit of {}; rolled back",
+                    result.agent_id, files.len(), task.id, max_files
+                )));
+            }
+        }
+
+        let kept = self.review_changes(&result.records, self.needs_approval(&result), Some(task))?;
+        self.cycle_changes.write().extend(kept.iter().cloned());
+
+        if let (Some(template), false) = (&self.snapshot_template, kept.is_empty()) {
+            let version_id = self.version_control.create_templated_snapshot(template, &kept);
+            info!("Created snapshot {} for task {}", version_id, task.id);
+        }
+
+        Ok(result)
+    }
+
+    /// Whether changes from `result` wait for approval because the agent
+    /// isn't confident enough in them
+    fn needs_approval(&self, result: &AgentResult) -> bool {
+        let confident = match (self.min_confidence, result.confidence) {
+            (Some(min), Some(confidence)) => confidence > min,
+            _ => true,
+        };
+        if !confident {
+            info!("Agent {} reported confidence {:.2}, routing its changes to approval",
+                result.agent_id, result.confidence.unwrap_or_default());
+        }
+        !confident
+    }
+
+    /// Run `task` on `agent` and make sure every change it made is in the
+    /// journal, without reviewing them
+    async fn run_and_record(&self, agent: &(dyn Agent + Send + Sync), task: &AgentTask) -> Result<AgentResult, BrionError> {
+        // Scoped agents run rooted at their subtree, so targets are made
+        // relative to the scope and change paths re-prefixed afterwards
+        let scope = self.scope_of(agent);
+        let mut result = match &scope {
+            Some(scope) => {
+                let unscoped = |target: &String| std::path::Path::new(target).strip_prefix(scope)
+                    .map(|p| p.to_string_lossy().to_string())
+                    .unwrap_or_else(|_| target.clone());
+                let mut scoped_task = task.clone();
+                scoped_task.target_file = task.target_file.as_ref().map(unscoped);
+                scoped_task.target_files = task.target_files.iter().map(unscoped).collect();
+                let mut result = agent.execute_task(&scoped_task, &self.base_path.join(scope)).await?;
+                for change in result.records.iter_mut() {
+                    change.file_path = scope.join(&change.file_path).to_string_lossy().to_string();
+                }
+                result
+            }
+            None => agent.execute_task(task, &self.base_path).await?,
+        };
+
+        // From here on the result carries every change as recorded, after
+        // transforms and scoping. Agents without version control, scoped
+        // agents among them, never recorded theirs, so those are recorded now.
+        for change in result.records.iter_mut() {
+            *change = if self.version_control.get_change(&change.id).is_none() {
+                self.version_control.record(change.clone())?
+            } else {
+                self.version_control.apply_transforms(change.clone())?
+            };
+        }
+
+        Ok(result)
+    }
+
+    /// Re-run an agent on the `before` content of each change and report
+    /// the changes whose recorded `after` it no longer reproduces. Each
+    /// replay gets a fresh agent from `new_agent`, wired to a scratch
+    /// directory holding only that file, so the site, the journal and any
+    /// registered agent are never touched.
+    pub async fn regression_test_agent<F>(&self, new_agent: F, changes: &[Change]) -> Vec<RegressionDiff>
+    where
+        F: Fn() -> Box<dyn Agent + Send + Sync>,
+    {
+        let mut diffs = Vec::new();
+        for change in changes {
+            if change.change_type == ChangeType::Delete {
+                continue;
+            }
+
+            let scratch = std::env::temp_dir().join(format!("brion-replay-{}", Uuid::new_v4()));
+            let scratch_vc = Arc::new(VersionControl::new(scratch.clone()));
+            let mut agent = new_agent();
+            agent.set_deferred_apply(false);
+            agent.set_version_control(scratch_vc.clone());
+
+            let replayed = Self::replay_change(agent.as_ref(), change, &scratch, &scratch_vc).await;
+            let _ = std::fs::remove_dir_all(&scratch);
+
+            let (actual, error) = match replayed {
+                Ok(actual) if actual == change.after => continue,
+                Ok(actual) => (Some(actual), None),
+                Err(e) => (None, Some(e.to_string())),
+            };
+            let mut comparison = change.clone();
+            comparison.before = change.after.clone();
+            comparison.after = actual.clone().unwrap_or_default();
+            diffs.push(RegressionDiff {
+                change_id: change.id.clone(),
+                file_path: change.file_path.clone(),
+                expected: change.after.clone(),
+                actual,
+                diff: comparison.unified_diff(),
+                error,
+            });
+        }
+        diffs
+    }
+
+    /// Run `agent` on `change.before` in `scratch` and return what it made of it
+    async fn replay_change(
+        agent: &(dyn Agent + Send + Sync),
+        change: &Change,
+        scratch: &std::path::Path,
+        scratch_vc: &VersionControl,
+    ) -> Result<String, BrionError> {
+        use crate::agents::file_ops::FileOperations;
+
+        std::fs::create_dir_all(scratch).map_err(|e| BrionError::io_at(scratch, e))?;
+        let path = FileOperations::resolve_within(scratch, &change.file_path)?;
+        FileOperations::write_file(&path, &change.before, scratch_vc.durability_policy())?;
+        let task = AgentTask {
+            id: Uuid::new_v4().to_string(),
+            agent_type: agent.get_type(),
+            priority: 5,
+            description: format!("Replay change {}", change.id),
+            target_file: Some(change.file_path.clone()),
+            target_files: Vec::new(),
+            parameters: HashMap::new(),
+            created_at: Utc::now(),
+            depends_on: Vec::new(),
+        };
+        let result = agent.execute_task(&task, scratch).await?;
+
+        // Deferred or unrecorded output only shows up in the changes
+        let recorded = result.changes.iter()
+            .filter_map(|id| scratch_vc.get_change(id))
+            .chain(result.records)
+            .rfind(|c| c.file_path == change.file_path);
+        match recorded {
+            Some(recorded) => Ok(recorded.after),
+            None => FileOperations::read_file(&path),
+        }
+    }
+
+    /// Run each stage of `pipeline` on `target` in order. Every stage's
+    /// changes are written before the next stage runs, so it works on the
+    /// previous stage's output, and only the combined change from the
+    /// original content to the final one is reviewed, like any other
+    /// change. If it's rejected, held as a suggestion, or a stage fails,
+    /// every stage's changes are rolled back.
+    pub async fn run_pipeline(&self, pipeline: &Pipeline, target: &str) -> Result<AgentResult, BrionError> {
+        use crate::agents::file_ops::FileOperations;
+
+        let path = FileOperations::resolve_within(&self.base_path, target)?;
+        let pipeline_task = AgentTask {
+            id: Uuid::new_v4().to_string(),
+            agent_type: pipeline.stages.first().cloned().unwrap_or(AgentType::UIAgent),
+            priority: 5,
+            description: format!("Pipeline {}", pipeline.name),
+            target_file: Some(target.to_string()),
+            target_files: Vec::new(),
+            parameters: HashMap::new(),
+            created_at: Utc::now(),
+            depends_on: Vec::new(),
+        };
+
+        let _claim = self.claim_files(&pipeline_task).ok_or_else(|| {
+            BrionError::Other(format!("Pipeline {} targets a file another task is writing", pipeline.name))
+        })?;
+        self.run_pipeline_stages(pipeline, &pipeline_task, &path).await
+    }
+
+    async fn run_pipeline_stages(
+        &self,
+        pipeline: &Pipeline,
+        pipeline_task: &AgentTask,
+        path: &std::path::Path,
+    ) -> Result<AgentResult, BrionError> {
+        use crate::agents::file_ops::FileOperations;
+
+        let target = pipeline_task.target_file.clone().unwrap_or_default();
+        let original = if path.exists() { Some(FileOperations::read_file(path)?) } else { None };
+        let mut results = Vec::new();
+        let mut schema = Vec::new();
+        let mut change_ids: Vec<String> = Vec::new();
+        for agent_type in &pipeline.stages {
+            let mut task = pipeline_task.clone();
+            task.id = Uuid::new_v4().to_string();
+            task.agent_type = agent_type.clone();
+            task.description = format!("Pipeline {} stage {:?}", pipeline.name, agent_type);
+
+            match self.run_pipeline_stage(&task).await {
+                Ok((result, stage_schema)) => {
+                    change_ids.extend(result.changes.iter().cloned());
+                    results.push(result);
+                    schema.extend(stage_schema);
+                }
+                Err(e) => {
+                    warn!("Pipeline {} failed at {:?}: {}", pipeline.name, agent_type, e);
+                    self.rollback_pipeline(&change_ids);
+                    return Err(e);
+                }
+            }
+        }
+
+        let trusted = {
+            let trusted_agents = self.trusted_agents.read();
+            results.iter().all(|result| trusted_agents.contains(&result.agent_id))
+        };
+        let mut result = AgentResult::merge(pipeline_task, &schema, results);
+        if change_ids.is_empty() {
+            return Ok(result);
+        }
+
+        let current = if path.exists() { Some(FileOperations::read_file(path)?) } else { None };
+        let change_type = match (&original, &current) {
+            (None, Some(_)) => ChangeType::Create,
+            (Some(_), None) => ChangeType::Delete,
+            _ => ChangeType::Modify,
+        };
+        let combined = FileOperations::create_change(
+            &result.agent_id,
+            "Pipeline",
+            target.clone(),
+            change_type,
+            original.unwrap_or_default(),
+            current.unwrap_or_default(),
+        );
+        // Only a pipeline made entirely of trusted agents skips the heuristics
+        let evaluation = if trusted {
+            self.evaluator.evaluate_trusted(&combined)
+        } else {
+            self.evaluator.evaluate_change(&combined)
+        };
+
+        match self.verdict(&evaluation, self.needs_approval(&result)) {
+            Verdict::Keep => {}
+            Verdict::Suggest => {
+                self.rollback_pipeline(&change_ids);
+                info!("Pipeline {} held as a suggestion for {} with score {:.2}",
+                    pipeline.name, target, evaluation.overall_score);
+                self.suggestions.write().push(Suggestion {
+                    change: combined,
+                    score: evaluation.overall_score,
+                    issues: evaluation.issues,
+                });
+                result.message = format!("Pipeline {} held as a suggestion with score {:.2}", pipeline.name, evaluation.overall_score);
+                return Ok(result);
+            }
+            Verdict::Reject => {
+                warn!("Pipeline {} scored {:.2} on {}, rolling back {} changes",
+                    pipeline.name, evaluation.overall_score, target, change_ids.len());
+                self.rollback_pipeline(&change_ids);
+                {
+                    let mut stats = self.stats.write();
+                    stats.rolled_back_changes += change_ids.len();
+                    for change in change_ids.iter().filter_map(|id| self.version_control.get_change(id)) {
+                        stats.agent_scores.entry(change.agent_id).or_default().rolled_back += 1;
+                    }
+                }
+                for change in change_ids.iter().filter_map(|id| self.version_control.get_change(id)) {
+                    self.record_outcome(&change.agent_id, false);
+                }
+                result.success = false;
+                result.message = format!("Pipeline {} rejected with score {:.2}", pipeline.name, evaluation.overall_score);
+                return Ok(result);
+            }
+        }
+
+        // Every stage change is kept on the combined change's evaluation
+        for change_id in &change_ids {
+            if let Some(mut change) = self.version_control.get_change(change_id) {
+                change.evaluation_score = Some(evaluation.overall_score);
+                let agent_id = change.agent_id.clone();
+                let file = change.file_path.clone();
+                self.version_control.record_change(change)?;
+                let stage_evaluation = EvaluationResult { change_id: change_id.clone(), ..evaluation.clone() };
+                if let Err(e) = self.version_control.record_evaluation(stage_evaluation) {
+                    warn!("Failed to store the evaluation of change {}: {}", change_id, e);
+                }
+                {
+                    let mut stats = self.stats.write();
+                    let score = stats.agent_scores.entry(agent_id.clone()).or_default();
+                    score.kept += 1;
+                    score.record_score(evaluation.overall_score);
+                }
+                self.record_outcome(&agent_id, true);
+                self.emit(OrchestratorEvent::ChangeApplied {
+                    change_id: change_id.clone(),
+                    file,
+                    score: Some(evaluation.overall_score),
+                });
+            }
+        }
+        self.stats.write().successful_changes += change_ids.len();
+        self.cycle_changes.write().extend(change_ids.iter().cloned());
+        info!("Pipeline {} kept {} changes on {} with score {:.2}",
+            pipeline.name, change_ids.len(), target, evaluation.overall_score);
+
+        if let Some(template) = &self.snapshot_template {
+            let version_id = self.version_control.create_templated_snapshot(template, &change_ids);
+            info!("Created snapshot {} for pipeline {}", version_id, pipeline.name);
+        }
+        Ok(result)
+    }
+
+    /// Run one pipeline stage, returning its result and the stage agent's
+    /// metrics schema
+    async fn run_pipeline_stage(&self, task: &AgentTask) -> Result<(AgentResult, Vec<MetricDef>), BrionError> {
+        use crate::agents::file_ops::FileOperations;
+
+        let agent_list = self.agents.read().get(&task.agent_type).cloned().unwrap_or_default();
+        let agent = self.select_agent(&agent_list, task)
+            .ok_or_else(|| BrionError::Other(format!("No {:?} agent available for {}", task.agent_type, task.description)))?;
+        let result = self.run_and_record(agent.as_ref(), task).await?;
+
+        // Deferred agents leave the file alone; the next stage needs to see their output
+        if self.evaluate_before_apply {
+            for change_id in &result.changes {
+                let change = self.version_control.full_change(change_id)?;
+                let _lock = self.file_locks.lock_blocking(&self.base_path.join(&change.file_path))?;
+                FileOperations::apply_change(&change, &self.base_path, self.durability())?;
+            }
+        }
+        Ok((result, agent.metrics_schema()))
+    }
+
+    /// Undo pipeline changes newest first
+    fn rollback_pipeline(&self, change_ids: &[String]) {
+        for change_id in change_ids.iter().rev() {
+            if let Err(e) = self.rollback_change(change_id) {
+                error!("Failed to roll back pipeline change {}: {}", change_id, e);
+            }
+        }
+    }
+
+    /// Evaluate recorded changes and keep or roll back each one, returning
+    /// the IDs of the changes that were kept. `source_task` is the task
+    /// that produced them, if any, for auto-remediation.
+    fn review_changes(
+        &self,
+        changes: &[Change],
+        needs_approval: bool,
+        source_task: Option<&AgentTask>,
+    ) -> Result<Vec<String>, BrionError> {
+        // Gather recorded changes, skipping those already rejected recently.
+        // Protected changes are kept as they are.
+        let group_of = |change: &Change| change.metadata.get(CHANGE_GROUP_METADATA_KEY).cloned();
+        let mut pending: Vec<(Change, String)> = Vec::new();
+        let mut kept = Vec::new();
+        for change in changes {
+            let change_id = &change.id;
+            if change.protected {
+                info!("Change {} is protected, keeping it without evaluation", change_id);
+                if self.evaluate_before_apply {
+                    use crate::agents::file_ops::FileOperations;
+                    let _lock = self.file_locks.lock_blocking(&self.base_path.join(&change.file_path))?;
+                    FileOperations::apply_change(&self.version_control.full_change(change_id)?, &self.base_path, self.durability())?;
+                }
+                self.emit(OrchestratorEvent::ChangeApplied {
+                    change_id: change_id.clone(),
+                    file: change.file_path.clone(),
+                    score: None,
+                });
+                kept.push(change_id.clone());
+                continue;
+            }
+            let fingerprint = Self::fingerprint(change);
+            if self.is_quarantined(&fingerprint) {
+                warn!("Change {} matches a quarantined change, rolling back without evaluation", change_id);
+                // Grouped changes are undone with the rest of their group below
+                if group_of(change).is_none() {
+                    self.reject_change(change_id)?;
+                }
+                self.stats.write().quarantined_changes += 1;
+                continue;
+            }
+            pending.push((change.clone(), fingerprint));
+        }
+
+        let to_evaluate: Vec<Change> = pending.iter().map(|(change, _)| change.clone()).collect();
+        let evaluations = self.evaluate_batch(&to_evaluate)?;
+
+        // Changes in a group build on each other, so none is kept or undone
+        // until the whole group has been judged
+        let mut grouped: HashMap<String, Reviewed> = HashMap::new();
+        for ((change, fingerprint), evaluation) in pending.into_iter().zip(evaluations) {
+            let change_id = &change.id;
+
+            // Update change with evaluation score
+            let mut scored = change.clone();
+            scored.evaluation_score = Some(evaluation.overall_score);
+            self.version_control.record_change(scored.clone())?;
+            if let Err(e) = self.version_control.record_evaluation(evaluation.clone()) {
+                warn!("Failed to store the evaluation of change {}: {}", change_id, e);
+            }
+            self.stats.write().agent_scores.entry(change.agent_id.clone()).or_default().record_score(evaluation.overall_score);
+
+            let verdict = self.verdict(&evaluation, needs_approval);
+            let in_group = group_of(&change).is_some();
+            let reviewed = Reviewed { change, scored, fingerprint, evaluation, verdict };
+            if in_group {
+                grouped.insert(reviewed.change.id.clone(), reviewed);
+                continue;
+            }
+            match verdict {
+                Verdict::Keep => {
+                    kept.push(reviewed.change.id.clone());
+                    self.keep_reviewed(reviewed, source_task)?;
+                }
+                Verdict::Suggest => {
+                    self.reject_change(&reviewed.change.id)?;
+                    self.hold_suggestion(reviewed);
+                }
+                Verdict::Reject => {
+                    self.reject_change(&reviewed.change.id)?;
+                    self.note_rejection(&reviewed);
+                }
+            }
+        }
+
+        let mut groups: Vec<String> = Vec::new();
+        for change in changes.iter().filter(|change| !change.protected) {
+            if let Some(group) = group_of(change) {
+                if !groups.contains(&group) {
+                    groups.push(group);
+                }
+            }
+        }
+        for group in groups {
+            let members: Vec<&Change> = changes.iter()
+                .filter(|change| !change.protected && group_of(change).as_ref() == Some(&group))
+                .collect();
+            let all_kept = members.iter()
+                .all(|change| grouped.get(&change.id).map(|member| member.verdict == Verdict::Keep).unwrap_or(false));
+            if all_kept {
+                for change in members {
+                    if let Some(member) = grouped.remove(&change.id) {
+                        kept.push(change.id.clone());
+                        self.keep_reviewed(member, source_task)?;
+                    }
+                }
+                continue;
+            }
+
+            // Undoing every member newest first walks each file back to the
+            // group's earliest `before`, whichever member was rejected
+            warn!("Rolling back change group {}: not every change in it was kept", group);
+            for change in members.iter().rev() {
+                self.reject_change(&change.id)?;
+            }
+            // Quarantined members have no review and were counted above
+            for member in members.iter().filter_map(|change| grouped.remove(&change.id)) {
+                match member.verdict {
+                    Verdict::Keep => {
+                        warn!("Rolled back change {} because another change in its group wasn't kept", member.change.id);
+                        {
+                            let mut stats = self.stats.write();
+                            stats.rolled_back_changes += 1;
+                            stats.agent_scores.entry(member.change.agent_id.clone()).or_default().rolled_back += 1;
+                        }
+                        self.record_outcome(&member.change.agent_id, false);
+                    }
+                    Verdict::Suggest => self.hold_suggestion(member),
+                    Verdict::Reject => self.note_rejection(&member),
+                }
+            }
+        }
+
+        Ok(kept)
+    }
+
+    /// Apply a kept change if it waited for evaluation, and queue any
+    /// remediation it calls for
+    fn keep_reviewed(&self, reviewed: Reviewed, source_task: Option<&AgentTask>) -> Result<(), BrionError> {
+        let Reviewed { change, scored, evaluation, .. } = reviewed;
+        if self.evaluate_before_apply {
+            use crate::agents::file_ops::FileOperations;
+            let _lock = self.file_locks.lock_blocking(&self.base_path.join(&scored.file_path))?;
+            FileOperations::apply_change(&self.version_control.full_change(&scored.id)?, &self.base_path, self.durability())?;
+        }
+        self.stats.write().agent_scores.entry(change.agent_id.clone()).or_default().kept += 1;
+        self.record_outcome(&change.agent_id, true);
+        info!("Change {} approved with score {:.2}", change.id, evaluation.overall_score);
+        if let Some(task) = source_task {
+            self.queue_remediation(task, &change, &evaluation.recommendations);
+        }
+        self.emit(OrchestratorEvent::ChangeApplied {
+            change_id: change.id.clone(),
+            file: change.file_path.clone(),
+            score: Some(evaluation.overall_score),
+        });
+        Ok(())
+    }
+
+    /// Hold an already undone change as a suggestion awaiting approval
+    fn hold_suggestion(&self, reviewed: Reviewed) {
+        info!("Change {} held as a suggestion with score {:.2}", reviewed.change.id, reviewed.evaluation.overall_score);
+        self.suggestions.write().push(Suggestion {
+            change: reviewed.scored,
+            score: reviewed.evaluation.overall_score,
+            issues: reviewed.evaluation.issues,
+        });
+    }
+
+    /// Quarantine and count an already undone change that failed review
+    fn note_rejection(&self, reviewed: &Reviewed) {
+        let change = &reviewed.change;
+        warn!("Change {} scored below threshold ({:.2}), rolled back", change.id, reviewed.evaluation.overall_score);
+        debug!("Rejected change {}:\n{}", change.id, change.unified_diff());
+        self.quarantine.write().insert(reviewed.fingerprint.clone(), Utc::now() + self.quarantine_ttl);
+        {
+            let mut stats = self.stats.write();
+            stats.rolled_back_changes += 1;
+            stats.agent_scores.entry(change.agent_id.clone()).or_default().rolled_back += 1;
+        }
+        self.record_outcome(&change.agent_id, false);
+    }
+
+    /// Whether to keep an evaluated change, hold it as a suggestion, or
+    /// reject it. Safe mode also rejects anything the evaluator flagged.
+    /// Suggest mode holds every change; otherwise low-confidence changes
+    /// are held if they would pass.
+    fn verdict(&self, evaluation: &EvaluationResult, needs_approval: bool) -> Verdict {
+        let flagged = self.profile.safe_mode && !evaluation.issues.is_empty();
+        let passes = evaluation.should_keep && !flagged;
+        if self.suggest_mode || (needs_approval && passes) {
+            Verdict::Suggest
+        } else if passes {
+            Verdict::Keep
+        } else {
+            Verdict::Reject
+        }
+    }
+
+    /// Evaluate every recorded change that hasn't been scored yet, so
+    /// nothing escapes review. Returns the IDs of the changes kept.
+    pub fn evaluate_pending(&self) -> Result<Vec<String>, BrionError> {
+        self.review_changes(&self.version_control.unevaluated_changes(), false, None)
+    }
+
+    /// Undo a change that failed evaluation. When changes are only applied
+    /// after evaluation the file was never touched, so only the journal is updated.
+    fn reject_change(&self, change_id: &str) -> Result<(), BrionError> {
+        if self.evaluate_before_apply {
+            let change = self.version_control.rollback_change(change_id)?;
+            self.emit_rolled_back(&change);
+            Ok(())
+        } else {
+            self.rollback_change(change_id)
+        }
+    }
+
+    /// Note whether a reviewed change from `agent_id` was kept, and disable
+    /// the agent once too many of its recent changes were rolled back
+    fn record_outcome(&self, agent_id: &str, kept: bool) {
+        let (window, max_rate) = match self.auto_disable {
+            Some(limits) => limits,
+            None => return,
+        };
+        let rate = {
+            let mut outcomes = self.recent_outcomes.write();
+            let history = outcomes.entry(agent_id.to_string())
+                .or_insert_with(|| BoundedRingBuffer::new(window));
+            history.push(kept);
+            if history.len() < history.capacity() {
+                return;
+            }
+            history.iter().filter(|kept| !**kept).count() as f64 / history.len() as f64
+        };
+
+        if rate >= max_rate && self.disabled_agents.write().insert(agent_id.to_string()) {
+            warn!("Disabling agent {}: {:.0}% of its last {} changes were rolled back", agent_id, rate * 100.0, window);
+            self.emit(OrchestratorEvent::AgentDisabled { agent_id: agent_id.to_string(), rollback_rate: rate });
+        }
+    }
+
+    /// Let a disabled agent be selected again, with a clean outcome history.
+    /// Returns false if it wasn't disabled.
+    pub fn reenable_agent(&self, agent_id: &str) -> bool {
+        self.recent_outcomes.write().remove(agent_id);
+        let reenabled = self.disabled_agents.write().remove(agent_id);
+        if reenabled {
+            info!("Re-enabled agent {}", agent_id);
+        }
+        reenabled
+    }
+
+    pub fn disabled_agents(&self) -> Vec<String> {
+        let mut disabled: Vec<String> = self.disabled_agents.read().iter().cloned().collect();
+        disabled.sort();
+        disabled
+    }
+
+    fn emit_rolled_back(&self, change: &Change) {
+        self.emit(OrchestratorEvent::ChangeRolledBack {
+            change_id: change.id.clone(),
+            file: change.file_path.clone(),
+            score: self.version_control.get_evaluation(&change.id).map(|e| e.overall_score),
+        });
+    }
+
+    fn fingerprint(change: &Change) -> String {
+        let mut hasher = DefaultHasher::new();
+        change.after.hash(&mut hasher);
+        format!("{}:{:x}", change.file_path, hasher.finish())
+    }
+
+    fn is_quarantined(&self, fingerprint: &str) -> bool {
+        let now = Utc::now();
+        let mut quarantine = self.quarantine.write();
+        quarantine.retain(|_, expiry| *expiry > now);
+        quarantine.contains_key(fingerprint)
+    }
+
+    /// Evaluate changes across up to `evaluation_parallelism` threads,
+    /// returning results in input order
+    fn evaluate_batch(&self, changes: &[Change]) -> Result<Vec<EvaluationResult>, BrionError> {
+        if self.evaluation_parallelism <= 1 || changes.len() <= 1 {
+            return Ok(changes.iter().map(|change| self.evaluate(change)).collect());
+        }
+
+        let chunk_size = changes.len().div_ceil(self.evaluation_parallelism);
+        std::thread::scope(|scope| {
+            let handles: Vec<_> = changes.chunks(chunk_size)
+                .map(|chunk| scope.spawn(move || {
+                    chunk.iter().map(|change| self.evaluate(change)).collect::<Vec<_>>()
+                }))
+                .collect();
+
+            // Join every worker before looking at any, so one panic can't
+            // leave another unjoined to re-panic the scope
+            let joined: Vec<_> = handles.into_iter().map(|handle| handle.join()).collect();
+            let mut evaluations = Vec::with_capacity(changes.len());
+            for chunk in joined {
+                let chunk = chunk.map_err(|panic| {
+                    let message = panic.downcast_ref::<&str>().map(|s| s.to_string())
+                        .or_else(|| panic.downcast_ref::<String>().cloned())
+                        .unwrap_or_else(|| "unknown panic".to_string());
+                    BrionError::EvaluationFailed(format!("evaluation worker panicked: {}", message))
+                })?;
+                evaluations.extend(chunk);
+            }
+            Ok(evaluations)
+        })
+    }
+
+    #[cfg_attr(feature = "otel", tracing::instrument(name = "evaluate", skip_all,
+        fields(change_id = %change.id, score = tracing::field::Empty)))]
+    fn evaluate(&self, change: &Change) -> EvaluationResult {
+        let evaluation = if self.trusted_agents.read().contains(&change.agent_id) {
+            self.evaluator.evaluate_trusted(change)
+        } else {
+            self.evaluator.evaluate_change(change)
+        };
+        #[cfg(feature = "otel")]
+        tracing::Span::current().record("score", evaluation.overall_score);
+        evaluation
+    }
+
+    #[cfg_attr(feature = "otel", tracing::instrument(name = "rollback", skip(self)))]
+    pub fn rollback_change(&self, change_id: &str) -> Result<(), BrionError> {
+        use crate::agents::file_ops::FileOperations;
+
+        // A second rollback would revert the revert
+        if self.version_control.is_rolled_back(change_id) {
+            debug!("Change {} is already rolled back", change_id);
+            return Ok(());
+        }
+
+        // Restore the file through a recorded inverse change, so the
+        // history shows the rollback rather than the file silently going back.
+        // The revert is applied with its full content, however it's recorded.
+        let revert = self.version_control.create_revert(change_id)?;
+        let (revert_id, applied) = {
+            // Locked first, so a file that can't be locked leaves no revert behind
+            let _lock = self.file_locks.lock_blocking(&self.base_path.join(&revert.file_path))?;
+            let revert_id = self.version_control.record_change(revert.clone())?;
+            (revert_id, FileOperations::apply_change(&revert, &self.base_path, self.durability()))
+        };
+        if let Err(e) = applied {
+            self.version_control.rollback_change(&revert_id)?;
+            return Err(e);
+        }
+        let change = self.version_control.rollback_change(change_id)?;
+        self.emit_rolled_back(&change);
+        
+        info!("Rolled back change {} in file {} with revert {}", change_id, revert.file_path, revert_id);
+        
+        Ok(())
+    }
+
+    pub fn get_suggestions(&self) -> Vec<Suggestion> {
+        self.suggestions.read().clone()
+    }
+
+    /// Apply every pending suggestion accepted by `filter` as one batch.
+    /// If any file no longer matches the suggestion's starting content, or a
+    /// write fails, nothing is kept. Returns the IDs of the recorded changes.
+    pub fn apply_suggestions<F>(&self, filter: F) -> Result<Vec<String>, BrionError>
+    where
+        F: Fn(&Suggestion) -> bool,
+    {
+        use crate::agents::file_ops::FileOperations;
+
+        let mut suggestions = self.suggestions.write();
+        let (accepted, pending): (Vec<Suggestion>, Vec<Suggestion>) =
+            suggestions.drain(..).partition(|s| filter(s));
+
+        let paths: Vec<PathBuf> = accepted.iter().map(|s| self.base_path.join(&s.change.file_path)).collect();
+        let _locks = self.file_locks.lock_files_blocking(&paths)?;
+        let result = (|| {
+            for suggestion in &accepted {
+                let path = self.base_path.join(&suggestion.change.file_path);
+                let current = if path.exists() { FileOperations::read_file(&path)? } else { String::new() };
+                if current != suggestion.change.before {
+                    return Err(BrionError::Other(format!("{} changed since suggestion {} was made",
+                        suggestion.change.file_path, suggestion.change.id)));
+                }
+            }
+
+            let mut applied: Vec<Change> = Vec::new();
+            for suggestion in &accepted {
+                let change = FileOperations::create_change(
+                    &suggestion.change.agent_id,
+                    &suggestion.change.agent_type,
+                    suggestion.change.file_path.clone(),
+                    suggestion.change.change_type.clone(),
+                    suggestion.change.before.clone(),
+                    suggestion.change.after.clone(),
+                );
+                if let Err(e) = FileOperations::apply_change(&change, &self.base_path, self.durability()) {
+                    for done in applied.iter().rev() {
+                        let _ = FileOperations::rollback_change(done, &self.base_path, self.durability());
+                    }
+                    return Err(e);
+                }
+                applied.push(change);
+            }
+
+            let mut change_ids = Vec::new();
+            for (suggestion, mut change) in accepted.iter().zip(applied) {
+                change.evaluation_score = Some(suggestion.score);
+                change_ids.push(self.version_control.record_change(change)?);
+            }
+            Ok(change_ids)
+        })();
+
+        // On failure the accepted suggestions stay pending for another try
+        match result {
+            Ok(change_ids) => {
+                *suggestions = pending;
+                self.stats.write().successful_changes += change_ids.len();
+                Ok(change_ids)
+            }
+            Err(e) => {
+                *suggestions = accepted.into_iter().chain(pending).collect();
+                Err(e)
+            }
+        }
+    }
+
+    pub fn create_snapshot(&self, description: String) -> String {
+        self.version_control.create_snapshot(description)
+    }
+
+    /// Roll back every change in a version snapshot, newest first
+    pub fn rollback_to_version(&self, version_id: &str, mode: RollbackMode) -> Result<RollbackReport, BrionError> {
+        let mut changes = self.version_control.rollback_to_version(version_id)?;
+        changes.sort_by_key(|c| std::cmp::Reverse(c.timestamp));
+        let change_ids: Vec<String> = changes.into_iter().map(|c| c.id).collect();
+        Ok(self.rollback_changes(&change_ids, mode))
+    }
+
+    /// Roll back changes in the given order, reporting what happened to each
+    pub fn rollback_changes(&self, change_ids: &[String], mode: RollbackMode) -> RollbackReport {
+        use crate::agents::file_ops::FileOperations;
+
+        let mut report = RollbackReport::default();
+        for change_id in change_ids {
+            if self.version_control.is_rolled_back(change_id) {
+                report.entries.push((change_id.clone(), RollbackOutcome::Skipped("already reverted".to_string())));
+                continue;
+            }
+
+            let conflict = match self.version_control.full_change(change_id) {
+                Err(e) => Some(e.to_string()),
+                Ok(change) => {
+                    let path = self.base_path.join(&change.file_path);
+                    let on_disk = if path.exists() { FileOperations::read_file(&path).ok() } else { None };
+                    Self::rollback_conflict(&change, on_disk.as_deref())
+                }
+            };
+
+            let outcome = match conflict {
+                Some(reason) => RollbackOutcome::Conflict(reason),
+                None => match self.rollback_change(change_id) {
+                    Ok(()) => RollbackOutcome::Reverted,
+                    Err(e) => RollbackOutcome::Conflict(e.to_string()),
+                },
+            };
+
+            let is_conflict = matches!(outcome, RollbackOutcome::Conflict(_));
+            report.entries.push((change_id.clone(), outcome));
+            if is_conflict && mode == RollbackMode::StopOnConflict {
+                report.halted = true;
+                break;
+            }
+        }
+
+        report
+    }
+
+    /// Restore every file agents have touched to its content before the
+    /// first recorded change, deleting files agents created. The revert is
+    /// recorded as ordinary changes, which aren't up for evaluation; returns
+    /// their IDs.
+    pub fn reset_to_original(&self) -> Result<Vec<String>, BrionError> {
+        use crate::agents::file_ops::FileOperations;
+
+        let mut originals: Vec<(String, Option<String>)> =
+            self.version_control.restorable_originals()?.into_iter().collect();
+        originals.sort_by(|a, b| a.0.cmp(&b.0));
+
+        let mut change_ids = Vec::new();
+        for (file_path, original) in originals {
+            let path = self.base_path.join(&file_path);
+            let _lock = self.file_locks.lock_blocking(&path)?;
+            let on_disk = if path.exists() { Some(FileOperations::read_file(&path)?) } else { None };
+            if on_disk == original {
+                continue;
+            }
+
+            let (change_type, after) = match original {
+                Some(content) if on_disk.is_some() => (ChangeType::Modify, content),
+                Some(content) => (ChangeType::Create, content),
+                None => (ChangeType::Delete, String::new()),
+            };
+            let mut change = FileOperations::create_change(
+                "orchestrator",
+                "Orchestrator",
+                file_path,
+                change_type,
+                on_disk.unwrap_or_default(),
+                after,
+            );
+            change.metadata.insert(RESET_METADATA_KEY.to_string(), "original".to_string());
+
+            // Record first, like every other writer, so no write lands
+            // untracked. What's written is the full content, however it's recorded.
+            let change_id = self.version_control.record_change(change.clone())?;
+            if let Err(e) = FileOperations::apply_change(&change, &self.base_path, self.durability()) {
+                self.version_control.rollback_change(&change_id)?;
+                return Err(e);
+            }
+            change_ids.push(change_id);
+        }
+
+        info!("Reset {} files to their original content", change_ids.len());
+        Ok(change_ids)
+    }
+
+    fn rollback_conflict(change: &Change, on_disk: Option<&str>) -> Option<String> {
+        match change.change_type {
+            ChangeType::Delete if on_disk.is_some() => {
+                Some(format!("{} was recreated after deletion", change.file_path))
+            }
+            ChangeType::Delete => None,
+            _ if on_disk != Some(change.after.as_str()) => {
+                Some(format!("{} was modified after the change was applied", change.file_path))
+            }
+            _ => None,
+        }
+    }
+
+    /// Work out what rolling back `change_ids` in order would do, without
+    /// touching disk. Later entries see the effect of earlier ones.
+    pub fn rollback_preview(&self, change_ids: &[String]) -> RollbackPreview {
+        use crate::agents::file_ops::FileOperations;
+
+        let mut preview = RollbackPreview::default();
+        let mut simulated: HashMap<String, Option<String>> = HashMap::new();
+        let mut files = HashSet::new();
+
+        for change_id in change_ids {
+            if self.version_control.is_rolled_back(change_id) {
+                preview.entries.push(RollbackPreviewEntry {
+                    change_id: change_id.clone(),
+                    file_path: None,
+                    outcome: RollbackOutcome::Skipped("already reverted".to_string()),
+                    lines_changed: 0,
+                });
+                continue;
+            }
+
+            let change = match self.version_control.full_change(change_id) {
+                Ok(change) => change,
+                Err(e) => {
+                    preview.conflicts += 1;
+                    preview.entries.push(RollbackPreviewEntry {
+                        change_id: change_id.clone(),
+                        file_path: None,
+                        outcome: RollbackOutcome::Conflict(e.to_string()),
+                        lines_changed: 0,
+                    });
+                    continue;
+                }
+            };
+
+            let on_disk = simulated.entry(change.file_path.clone())
+                .or_insert_with(|| {
+                    let path = self.base_path.join(&change.file_path);
+                    if path.exists() { FileOperations::read_file(&path).ok() } else { None }
+                })
+                .clone();
+
+            if let Some(reason) = Self::rollback_conflict(&change, on_disk.as_deref()) {
+                preview.conflicts += 1;
+                preview.entries.push(RollbackPreviewEntry {
+                    change_id: change_id.clone(),
+                    file_path: Some(change.file_path.clone()),
+                    outcome: RollbackOutcome::Conflict(reason),
+                    lines_changed: 0,
+                });
+                continue;
+            }
+
+            // Mirrors FileOperations::rollback_change
+            let restored = match change.change_type {
+                ChangeType::Create | ChangeType::AddImage | ChangeType::AddModule => None,
+                _ => Some(change.before.clone()),
+            };
+            let (added, removed) = VersionControl::line_delta(
+                on_disk.as_deref().unwrap_or(""),
+                restored.as_deref().unwrap_or(""),
+            );
+            let lines_changed = added.len() + removed.len();
+
+            preview.lines_affected += lines_changed;
+            files.insert(change.file_path.clone());
+            simulated.insert(change.file_path.clone(), restored);
+            preview.entries.push(RollbackPreviewEntry {
+                change_id: change_id.clone(),
+                file_path: Some(change.file_path.clone()),
+                outcome: RollbackOutcome::Reverted,
+                lines_changed,
+            });
+        }
+
+        preview.files_affected = files.len();
+        preview
+    }
+
+    /// Compare every tracked file on disk with the content the journal
+    /// reconstructs for it, emitting a drift event for each mismatch
+    pub fn run_diagnostics(&self) -> Vec<OrchestratorEvent> {
+        let mut events = Vec::new();
+        for (file, expected, actual) in self.drifted_files() {
+            let summary = match (&expected, &actual) {
+                (Some(expected), Some(actual)) => format!(
+                    "disk has {} bytes, journal expects {} bytes", actual.len(), expected.len()
+                ),
+                (Some(_), None) => "file missing or unreadable on disk".to_string(),
+                _ => "file exists on disk but journal records it deleted".to_string(),
+            };
+
+            warn!("Drift detected in {}: {}", file, summary);
+            events.push(OrchestratorEvent::Drift { file, summary });
+        }
+
+        match ConsistencyChecker::check_with_policy(&self.base_path, self.profile.unreadable_files) {
+            Ok((inconsistencies, unreadable)) => {
+                for inconsistency in inconsistencies {
+                    warn!("Inconsistency in {}: {:?} {}", inconsistency.file, inconsistency.kind, inconsistency.reference);
+                    events.push(OrchestratorEvent::Inconsistency(inconsistency));
+                }
+                for e in unreadable {
+                    warn!("Unreadable during consistency check: {}", e);
+                    events.push(OrchestratorEvent::Unreadable { error: e.to_string() });
+                }
+            }
+            Err(e) => {
+                error!("Consistency check aborted: {}", e);
+                events.push(OrchestratorEvent::Unreadable { error: e.to_string() });
+            }
+        }
+
+        for event in &events {
+            self.emit(event.clone());
+        }
+        events
+    }
+
+    /// Tracked files whose disk content differs from what the journal
+    /// expects, as (file, expected, actual)
+    fn drifted_files(&self) -> Vec<(String, Option<String>, Option<String>)> {
+        use crate::agents::file_ops::FileOperations;
+
+        self.version_control.tracked_files()
+            .into_iter()
+            .filter_map(|file| {
+                let expected = self.version_control.current_content(&file);
+                let path = self.base_path.join(&file);
+                let actual = if path.exists() {
+                    FileOperations::read_file(&path).ok()
+                } else {
+                    None
+                };
+                (expected != actual).then_some((file, expected, actual))
+            })
+            .collect()
+    }
+
+    /// Record an `external` change for every tracked file edited outside
+    /// the agents, so the journal matches disk again. Returns the change IDs.
+    pub fn detect_external_changes(&self) -> Result<Vec<String>, BrionError> {
+        use crate::agents::file_ops::FileOperations;
+
+        let mut change_ids = Vec::new();
+        for (file, expected, actual) in self.drifted_files() {
+            let change_type = match (&expected, &actual) {
+                (Some(_), Some(_)) => ChangeType::Modify,
+                (None, Some(_)) => ChangeType::Create,
+                _ => ChangeType::Delete,
+            };
+            let mut change = FileOperations::create_change(
+                "external",
+                "External",
+                file,
+                change_type,
+                expected.unwrap_or_default(),
+                actual.unwrap_or_default(),
+            );
+            change.evaluation_score = Some(1.0); // Not ours to judge
+            change_ids.push(self.version_control.record_change(change)?);
+        }
+        Ok(change_ids)
+    }
+
+    /// Bring the journal and disk back in line after a crash mid-tick:
+    /// settle leftover backups, finish applies that were interrupted,
+    /// evaluate changes that were never scored, record external edits, and
+    /// snapshot what that left outside any snapshot
+    pub fn recover(&self) -> Result<RecoveryReport, BrionError> {
+        use crate::agents::file_ops::FileOperations;
+
+        let mut report = RecoveryReport::default();
+
+        // A backup outlives only an apply the crash interrupted. If the file
+        // already holds what the journal says, the write landed; otherwise
+        // the backup puts it back to its content before the apply, which
+        // the steps below know how to finish from.
+        for path in FileOperations::leftover_backups(&self.base_path) {
+            let file = path.strip_prefix(&self.base_path)
+                .map(|p| p.to_string_lossy().replace('\\', "/"))
+                .unwrap_or_default();
+            let on_disk = if path.exists() { FileOperations::read_file(&path).ok() } else { None };
+            if on_disk == self.version_control.current_content(&file) {
+                FileOperations::discard_backup(&path)?;
+            } else {
+                warn!("Restoring {} from the backup an interrupted apply left", file);
+                FileOperations::restore_from_backup(&path, self.durability())?;
+                report.restored += 1;
+            }
+        }
+
+        let pending = self.version_control.unevaluated_changes();
+
+        // Recorded but the write never happened. Under evaluate-before-apply
+        // that's expected, evaluation applies it.
+        if !self.evaluate_before_apply {
+            for change in &pending {
+                let change = match self.version_control.full_change(&change.id) {
+                    Ok(change) => change,
+                    Err(e) => {
+                        warn!("Can't finish applying change {}: {}", change.id, e);
+                        continue;
+                    }
+                };
+                let path = self.base_path.join(&change.file_path);
+                let on_disk = if path.exists() { FileOperations::read_file(&path).ok() } else { None };
+                if on_disk.as_deref().unwrap_or("") == change.before && change.before != change.after {
+                    FileOperations::apply_change(&change, &self.base_path, self.durability())?;
+                    report.applied += 1;
+                }
+            }
+        }
+
+        // Rollbacks that recorded their revert but didn't finish
+        for revert in self.version_control.pending_reverts() {
+            let revert = match self.version_control.full_change(&revert.id) {
+                Ok(revert) => revert,
+                Err(e) => {
+                    warn!("Can't finish revert {}: {}", revert.id, e);
+                    continue;
+                }
+            };
+            let path = self.base_path.join(&revert.file_path);
+            let on_disk = if path.exists() { FileOperations::read_file(&path).ok() } else { None };
+            let on_disk = on_disk.as_deref().unwrap_or("");
+            if on_disk == revert.before && revert.before != revert.after {
+                FileOperations::apply_change(&revert, &self.base_path, self.durability())?;
+                report.applied += 1;
+            } else if on_disk != revert.after {
+                warn!("File {} changed since revert {} was recorded, abandoning it", revert.file_path, revert.id);
+                self.version_control.rollback_change(&revert.id)?;
+                continue;
+            }
+            if let Some(original) = revert.metadata.get(REVERTS_METADATA_KEY) {
+                self.version_control.rollback_change(original)?;
+            }
+        }
+
+        report.evaluated = pending.len();
+        report.kept = self.evaluate_pending()?.len();
+        report.external_changes = self.detect_external_changes()?;
+
+        // Snapshot only when recovery itself changed something; a clean
+        // restart leaves the history alone
+        let unsnapshotted = self.version_control.unsnapshotted_changes();
+        let recovered = report.restored + report.applied + report.kept + report.external_changes.len();
+        if recovered > 0 && !unsnapshotted.is_empty() {
+            report.snapshot = Some(self.version_control.create_snapshot(
+                format!("Recovered after restart: {} changes", unsnapshotted.len())
+            ));
+        }
+
+        info!("Recovery: restored {} backups, applied {} interrupted changes, evaluated {} ({} kept), recorded {} external edits{}",
+            report.restored, report.applied, report.evaluated, report.kept, report.external_changes.len(),
+            report.snapshot.as_ref().map(|v| format!(", snapshot {}", v)).unwrap_or_default());
+        Ok(report)
+    }
+
+    /// Most recent task results, oldest first
+    pub fn get_recent_results(&self) -> Vec<AgentResult> {
+        self.recent_results.read().to_vec()
+    }
+
+    /// Per-agent tasks, kept and rolled back changes, and average score,
+    /// keyed by agent ID
+    pub fn get_agent_stats(&self) -> HashMap<String, AgentScore> {
+        self.stats.read().agent_scores.clone()
+    }
+
+    pub fn get_stats(&self) -> OrchestratorStats {
+        self.stats.read().clone()
+    }
+
+    pub fn get_version_control(&self) -> Arc<VersionControl> {
+        self.version_control.clone()
+    }
+
+    pub fn get_task_queue(&self) -> Arc<TaskQueue> {
+        self.task_queue.clone()
+    }
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+    use crate::agents::agent_impl::EnhancedUIAgent;
+    use crate::agents::evaluator::{RuleOutcome, ScoreKind, ScoringRule};
+    use crate::agents::file_ops::FileOperations;
+    use crate::agents::version_control::ChangeTypeAllowlist;
+
+    const PAGE: &str = "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<title>Home</title>\n</head>\n<body>\n<main>\n<h1>Welcome</h1>\n</main>\n</body>\n</html>\n";
+    const IMPROVED_PAGE: &str = "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">\n<title>Home</title>\n</head>\n<body>\n<main>\n<h1>Welcome</h1>\n</main>\n</body>\n</html>\n";
+
+    fn scratch_site(files: &[(&str, &str)]) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("brion-site-{}", Uuid::new_v4()));
+        for (path, content) in files {
+            let path = dir.join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, content).unwrap();
+        }
+        dir
+    }
+
+    fn read(dir: &Path, file: &str) -> String {
+        std::fs::read_to_string(dir.join(file)).unwrap()
+    }
+
+    /// Appends `<!-- stamp -->` to its target and applies the change itself
+    struct StampAgent {
+        id: String,
+        agent_type: AgentType,
+        stamp: &'static str,
+    }
+
+    impl StampAgent {
+        fn boxed(agent_type: AgentType, stamp: &'static str) -> Box<dyn Agent + Send + Sync> {
+            Box::new(StampAgent { id: format!("{}-agent", stamp), agent_type, stamp })
+        }
+    }
+
+    #[async_trait::async_trait]
+    impl Agent for StampAgent {
+        fn get_type(&self) -> AgentType {
+            self.agent_type.clone()
+        }
+
+        fn get_id(&self) -> &str {
+            &self.id
+        }
+
+        fn can_handle(&self, task: &AgentTask) -> bool {
+            task.agent_type == self.agent_type
+        }
+
+        async fn execute_task(&self, task: &AgentTask, base_path: &Path) -> Result<AgentResult, String> {
+            let target = task.target_file.clone().unwrap_or_default();
+            let before = FileOperations::read_file(&base_path.join(&target))?;
+            let after = format!("{}<!-- {} -->\n", before, self.stamp);
+            let change = FileOperations::create_change(&self.id, "Stamp", target, ChangeType::Modify, before, after);
+            FileOperations::apply_change(&change, base_path, DurabilityPolicy::Fast)?;
+            Ok(AgentResult {
+                task_id: task.id.clone(),
+                agent_id: self.id.clone(),
+                success: true,
+                changes: vec![change.id.clone()],
+                records: vec![change],
+                message: format!("Stamped {}", self.stamp),
+                metrics: HashMap::new(),
+                confidence: None,
+                follow_up_tasks: Vec::new(),
+            })
+        }
+    }
+
+    /// Appends a CSS rule to its target and applies the change itself
+    struct RuleAgent {
+        id: String,
+        agent_type: AgentType,
+        rule: &'static str,
+    }
+
+    impl RuleAgent {
+        fn boxed(agent_type: AgentType, rule: &'static str) -> Box<dyn Agent + Send + Sync> {
+            Box::new(RuleAgent { id: format!("{:?}-agent", agent_type), agent_type, rule })
+        }
+    }
+
+    #[async_trait::async_trait]
+    impl Agent for RuleAgent {
+        fn get_type(&self) -> AgentType {
+            self.agent_type.clone()
+        }
+
+        fn get_id(&self) -> &str {
+            &self.id
+        }
+
+        fn can_handle(&self, task: &AgentTask) -> bool {
+            task.agent_type == self.agent_type
+        }
+
+        async fn execute_task(&self, task: &AgentTask, base_path: &Path) -> Result<AgentResult, String> {
+            let target = task.target_file.clone().unwrap_or_default();
+            let before = FileOperations::read_file(&base_path.join(&target))?;
+            let after = format!("{}{}\n", before, self.rule);
+            let change = FileOperations::create_change(&self.id, "Rule", target, ChangeType::Modify, before, after);
+            FileOperations::apply_change(&change, base_path, DurabilityPolicy::Fast)?;
+            Ok(AgentResult {
+                task_id: task.id.clone(),
+                agent_id: self.id.clone(),
+                success: true,
+                changes: vec![change.id.clone()],
+                records: vec![change],
+                message: format!("Added {}", self.rule),
+                metrics: HashMap::new(),
+                confidence: None,
+                follow_up_tasks: Vec::new(),
+            })
+        }
+    }
+
+    /// Appends each stamp as its own change on top of the previous one, all
+    /// in one change group, the way the SEO and content agents do
+    struct ChainAgent {
+        stamps: &'static [&'static str],
+    }
+
+    #[async_trait::async_trait]
+    impl Agent for ChainAgent {
+        fn get_type(&self) -> AgentType {
+            AgentType::SEOAgent
+        }
+
+        fn get_id(&self) -> &str {
+            "chain-agent"
+        }
+
+        fn can_handle(&self, task: &AgentTask) -> bool {
+            task.agent_type == AgentType::SEOAgent
+        }
+
+        async fn execute_task(&self, task: &AgentTask, base_path: &Path) -> Result<AgentResult, String> {
+            let target = task.target_file.clone().unwrap_or_default();
+            let mut current = FileOperations::read_file(&base_path.join(&target))?;
+            let group = Uuid::new_v4().to_string();
+            let mut records = Vec::new();
+            for stamp in self.stamps {
+                let after = format!("{}<!-- {} -->\n", current, stamp);
+                let mut change = FileOperations::create_change("chain-agent", "SEOAgent", target.clone(),
+                    ChangeType::UpdateContent, current, after.clone());
+                change.metadata.insert(CHANGE_GROUP_METADATA_KEY.to_string(), group.clone());
+                FileOperations::apply_change(&change, base_path, DurabilityPolicy::Fast)?;
+                records.push(change);
+                current = after;
+            }
+            Ok(AgentResult {
+                task_id: task.id.clone(),
+                agent_id: "chain-agent".to_string(),
+                success: true,
+                changes: records.iter().map(|change| change.id.clone()).collect(),
+                records,
+                message: "Chained".to_string(),
+                metrics: HashMap::new(),
+                confidence: None,
+                follow_up_tasks: Vec::new(),
+            })
+        }
+    }
+
+    /// Sinks any change that adds `reject-me`
+    struct RejectMarked;
+
+    impl ScoringRule for RejectMarked {
+        fn name(&self) -> &str {
+            "reject-marked"
+        }
+
+        fn score(&self, change: &Change) -> RuleOutcome {
+            if ChangeEvaluator::added_lines(&change.before, &change.after).contains("reject-me") {
+                RuleOutcome::new(ScoreKind::Functionality, -1.0).with_issue("marked for rejection")
+            } else {
+                RuleOutcome::pass()
+            }
+        }
+    }
+
+    fn task_for(agent_type: AgentType, target: &str) -> AgentTask {
+        AgentTask {
+            id: Uuid::new_v4().to_string(),
+            agent_type,
+            priority: 5,
+            description: "Test task".to_string(),
+            target_file: Some(target.to_string()),
+            target_files: Vec::new(),
+            parameters: HashMap::new(),
+            created_at: Utc::now(),
+            depends_on: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn recover_evaluates_a_change_the_crashed_run_applied() {
+        let dir = scratch_site(&[("index.html", PAGE)]);
+        // The previous run recorded and wrote the change, then died before evaluating it
+        let journal = VersionControl::new(dir.clone());
+        let change = FileOperations::create_change("ui-agent", "UIAgent", "index.html".to_string(),
+            ChangeType::Modify, PAGE.to_string(), IMPROVED_PAGE.to_string());
+        journal.record_change(change.clone()).unwrap();
+        FileOperations::apply_change(&change, &dir, DurabilityPolicy::Fast).unwrap();
+
+        let orchestrator = AgentOrchestrator::new(dir.clone());
+        let report = orchestrator.recover().unwrap();
+
+        assert_eq!(report.applied, 0);
+        assert_eq!(report.evaluated, 1);
+        assert!(report.external_changes.is_empty());
+        assert!(orchestrator.version_control.unevaluated_changes().is_empty());
+        let expected = if report.kept == 1 { IMPROVED_PAGE } else { PAGE };
+        assert_eq!(read(&dir, "index.html"), expected);
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn recover_finishes_a_write_the_crashed_run_never_made() {
+        let dir = scratch_site(&[("index.html", PAGE)]);
+        let journal = VersionControl::new(dir.clone());
+        journal.record_change(FileOperations::create_change("ui-agent", "UIAgent", "index.html".to_string(),
+            ChangeType::Modify, PAGE.to_string(), IMPROVED_PAGE.to_string())).unwrap();
+
+        let orchestrator = AgentOrchestrator::new(dir.clone());
+        let report = orchestrator.recover().unwrap();
+
+        assert_eq!(report.applied, 1);
+        assert_eq!(report.evaluated, 1);
+        assert!(report.external_changes.is_empty());
+        let expected = if report.kept == 1 { IMPROVED_PAGE } else { PAGE };
+        assert_eq!(read(&dir, "index.html"), expected);
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn recover_settles_backups_left_by_interrupted_applies() {
+        let dir = scratch_site(&[("index.html", "<!DOCTYPE ht"), ("style.css", "body { margin: 0; }\n")]);
+        let journal = VersionControl::new(dir.clone());
+        // Died mid-write of the page, and after writing the stylesheet but
+        // before its backup was removed
+        journal.record_change(FileOperations::create_change("ui-agent", "UIAgent", "index.html".to_string(),
+            ChangeType::Modify, PAGE.to_string(), IMPROVED_PAGE.to_string())).unwrap();
+        std::fs::write(FileOperations::backup_path(&dir.join("index.html")), PAGE).unwrap();
+        let mut styled = FileOperations::create_change("ui-agent", "UIAgent", "style.css".to_string(),
+            ChangeType::Modify, "body {}\n".to_string(), "body { margin: 0; }\n".to_string());
+        styled.evaluation_score = Some(0.9);
+        journal.record_change(styled).unwrap();
+        std::fs::write(FileOperations::backup_path(&dir.join("style.css")), "body {}\n").unwrap();
+
+        let orchestrator = AgentOrchestrator::new(dir.clone());
+        let report = orchestrator.recover().unwrap();
+
+        assert_eq!(report.restored, 1);
+        assert_eq!(report.applied, 1);
+        assert!(FileOperations::leftover_backups(&dir).is_empty());
+        assert_eq!(read(&dir, "style.css"), "body { margin: 0; }\n");
+        let expected = if report.kept == 1 { IMPROVED_PAGE } else { PAGE };
+        assert_eq!(read(&dir, "index.html"), expected);
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[tokio::test]
+    async fn pipeline_stages_see_the_previous_stage_output() {
+        let dir = scratch_site(&[("index.html", PAGE)]);
+        let orchestrator = AgentOrchestrator::new(dir.clone());
+        orchestrator.register_trusted_agent(StampAgent::boxed(AgentType::ContentAgent, "content"));
+        orchestrator.register_trusted_agent(StampAgent::boxed(AgentType::SEOAgent, "seo"));
+        let pipeline = Pipeline::new("page").then(AgentType::ContentAgent).then(AgentType::SEOAgent);
+
+        let result = orchestrator.run_pipeline(&pipeline, "index.html").await.unwrap();
+
+        assert!(result.success);
+        assert_eq!(read(&dir, "index.html"), format!("{}<!-- content -->\n<!-- seo -->\n", PAGE));
+        let seo_change = orchestrator.version_control.get_changes_by_agent("seo-agent").pop().unwrap();
+        assert!(seo_change.before.ends_with("<!-- content -->\n"));
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[tokio::test]
+    async fn pipelines_in_suggest_mode_leave_the_file_alone() {
+        let dir = scratch_site(&[("index.html", PAGE)]);
+        let orchestrator = AgentOrchestrator::new(dir.clone()).with_suggest_mode(true);
+        orchestrator.register_trusted_agent(StampAgent::boxed(AgentType::ContentAgent, "content"));
+        orchestrator.register_trusted_agent(StampAgent::boxed(AgentType::SEOAgent, "seo"));
+        let pipeline = Pipeline::new("page").then(AgentType::ContentAgent).then(AgentType::SEOAgent);
+
+        orchestrator.run_pipeline(&pipeline, "index.html").await.unwrap();
+
+        assert_eq!(read(&dir, "index.html"), PAGE);
+        let suggestions = orchestrator.get_suggestions();
+        assert_eq!(suggestions.len(), 1);
+        assert_eq!(suggestions[0].change.before, PAGE);
+        assert_eq!(suggestions[0].change.after, format!("{}<!-- content -->\n<!-- seo -->\n", PAGE));
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[tokio::test]
+    async fn a_rejected_pipeline_counts_against_every_stage_agent() {
+        let dir = scratch_site(&[("index.html", PAGE)]);
+        let mut evaluator = ChangeEvaluator::new().with_threshold(0.3);
+        evaluator.register_rule(Box::new(RejectMarked));
+        let orchestrator = AgentOrchestrator::new(dir.clone()).with_evaluator(evaluator);
+        orchestrator.register_agent(StampAgent::boxed(AgentType::ContentAgent, "content"));
+        orchestrator.register_agent(StampAgent::boxed(AgentType::SEOAgent, "reject-me"));
+        let pipeline = Pipeline::new("page").then(AgentType::ContentAgent).then(AgentType::SEOAgent);
+
+        let result = orchestrator.run_pipeline(&pipeline, "index.html").await.unwrap();
+
+        assert!(!result.success);
+        assert_eq!(read(&dir, "index.html"), PAGE);
+        let stats = orchestrator.get_agent_stats();
+        for agent_id in ["content-agent", "reject-me-agent"] {
+            assert_eq!(stats[agent_id].kept, 0);
+            assert_eq!(stats[agent_id].rolled_back, 1);
+        }
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[tokio::test]
+    async fn rejected_pipelines_count_toward_auto_disable() {
+        let dir = scratch_site(&[("index.html", PAGE)]);
+        assert!(AgentOrchestrator::new(dir.clone()).with_auto_disable(5, 0.0).is_err());
+
+        let mut evaluator = ChangeEvaluator::new().with_threshold(0.3);
+        evaluator.register_rule(Box::new(RejectMarked));
+        let orchestrator = AgentOrchestrator::new(dir.clone())
+            .with_evaluator(evaluator)
+            .with_auto_disable(1, 1.0)
+            .unwrap();
+        orchestrator.register_agent(StampAgent::boxed(AgentType::ContentAgent, "content"));
+        orchestrator.register_agent(StampAgent::boxed(AgentType::SEOAgent, "reject-me"));
+        let pipeline = Pipeline::new("page").then(AgentType::ContentAgent).then(AgentType::SEOAgent);
+
+        orchestrator.run_pipeline(&pipeline, "index.html").await.unwrap();
+
+        assert_eq!(orchestrator.disabled_agents(), vec!["content-agent", "reject-me-agent"]);
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[tokio::test]
+    async fn kept_pipeline_changes_count_toward_auto_disable_too() {
+        let dir = scratch_site(&[("index.html", PAGE)]);
+        let mut evaluator = ChangeEvaluator::new().with_threshold(0.3);
+        evaluator.register_rule(Box::new(RejectMarked));
+        let orchestrator = AgentOrchestrator::new(dir.clone())
+            .with_evaluator(evaluator)
+            .with_auto_disable(2, 1.0)
+            .unwrap();
+        orchestrator.register_agent(StampAgent::boxed(AgentType::ContentAgent, "content"));
+        orchestrator.register_agent(StampAgent::boxed(AgentType::SEOAgent, "reject-me"));
+        let rejected = Pipeline::new("page").then(AgentType::ContentAgent).then(AgentType::SEOAgent);
+        let kept = Pipeline::new("content").then(AgentType::ContentAgent);
+        let mut events = orchestrator.subscribe(16);
+
+        orchestrator.run_pipeline(&rejected, "index.html").await.unwrap();
+        let result = orchestrator.run_pipeline(&kept, "index.html").await.unwrap();
+        orchestrator.run_pipeline(&rejected, "index.html").await.unwrap();
+
+        assert!(result.success);
+        assert_eq!(read(&dir, "index.html"), format!("{}<!-- content -->\n", PAGE));
+        assert_eq!(orchestrator.disabled_agents(), vec!["reject-me-agent"]);
+        let stats = orchestrator.get_agent_stats();
+        assert_eq!((stats["content-agent"].kept, stats["content-agent"].rolled_back), (1, 2));
+        let journal = orchestrator.get_version_control();
+        assert!(journal.get_evaluation(&result.changes[0]).is_some());
+        let mut applied_ids = Vec::new();
+        while let Ok(event) = events.try_recv() {
+            if let OrchestratorEvent::ChangeApplied { change_id, .. } = event {
+                applied_ids.push(change_id);
+            }
+        }
+        assert_eq!(applied_ids, result.changes);
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[tokio::test]
+    async fn regression_test_reports_changes_the_agent_no_longer_reproduces() {
+        let dir = scratch_site(&[]);
+        let orchestrator = AgentOrchestrator::new(dir.clone());
+        let stamp = |after: &str| FileOperations::create_change("content-agent", "Stamp", "index.html".to_string(),
+            ChangeType::Modify, PAGE.to_string(), format!("{}<!-- {} -->\n", PAGE, after));
+        let reproduced = stamp("content");
+        let drifted = stamp("old content");
+
+        let diffs = orchestrator.regression_test_agent(
+            || StampAgent::boxed(AgentType::ContentAgent, "content"),
+            &[reproduced, drifted.clone()],
+        ).await;
+
+        assert_eq!(diffs.len(), 1);
+        assert_eq!(diffs[0].change_id, drifted.id);
+        assert_eq!(diffs[0].expected, drifted.after);
+        assert_eq!(diffs[0].actual.as_deref(), Some(format!("{}<!-- content -->\n", PAGE).as_str()));
+        assert!(diffs[0].diff.contains("-<!-- old content -->"));
+        assert!(diffs[0].diff.contains("+<!-- content -->"));
+        assert!(orchestrator.version_control.get_all_changes().is_empty());
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[tokio::test]
+    async fn recommendations_queue_remediation_up_to_the_depth_cap() {
+        let dir = scratch_site(&[("style.css", "body { color: #222; background: #fff; }\n")]);
+        let orchestrator = AgentOrchestrator::new(dir.clone())
+            .with_evaluator(ChangeEvaluator::new().with_threshold(0.0))
+            .with_auto_remediate(2);
+        // Each agent's rule draws a recommendation for the other, so left alone they'd loop forever
+        orchestrator.register_agent(RuleAgent::boxed(AgentType::UIAgent, ".note { color: #999; background-color: #aaa; }"));
+        orchestrator.register_agent(RuleAgent::boxed(AgentType::AccessibilityAgent, ".banner { width: 1200px; }"));
+        orchestrator.get_task_queue().add_task(task_for(AgentType::UIAgent, "style.css"));
+
+        orchestrator.run_agent_once(AgentType::UIAgent).await.unwrap().unwrap();
+        let remediation = orchestrator.get_task_queue().get_next_task(Some(AgentType::AccessibilityAgent))
+            .expect("the low-contrast rule should queue an accessibility task");
+        assert_eq!(remediation.target_file.as_deref(), Some("style.css"));
+        assert_eq!(remediation.get_param(REMEDIATION_DEPTH_PARAM), Some("1"));
+        assert_eq!(remediation.get_param(RECOMMENDATIONS_PARAM), Some("improve-contrast"));
+        orchestrator.get_task_queue().add_task(remediation);
+
+        // Accessibility at depth 1 queues a UI task at depth 2, which may queue nothing more
+        let results = orchestrator.drain_queue().await;
+        assert_eq!(results.len(), 2);
+        assert_eq!(orchestrator.get_task_queue().get_queue_size(), 0);
+        assert_eq!(read(&dir, "style.css").matches(".note").count(), 2);
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[tokio::test]
+    async fn rejecting_the_first_change_of_a_chain_restores_the_original() {
+        let dir = scratch_site(&[("index.html", PAGE)]);
+        let mut evaluator = ChangeEvaluator::new().with_threshold(0.3);
+        evaluator.register_rule(Box::new(RejectMarked));
+        let orchestrator = AgentOrchestrator::new(dir.clone()).with_evaluator(evaluator);
+        orchestrator.register_agent(Box::new(ChainAgent { stamps: &["reject-me", "fine", "also fine"] }));
+        orchestrator.get_task_queue().add_task(task_for(AgentType::SEOAgent, "index.html"));
+
+        let result = orchestrator.run_agent_once(AgentType::SEOAgent).await.unwrap().unwrap();
+
+        assert_eq!(read(&dir, "index.html"), PAGE);
+        for change_id in &result.changes {
+            assert!(orchestrator.version_control.is_rolled_back(change_id));
+        }
+        let stats = orchestrator.get_agent_stats();
+        assert_eq!(stats["chain-agent"].kept, 0);
+        assert_eq!(stats["chain-agent"].rolled_back, 3);
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[tokio::test]
+    async fn a_rejected_content_region_takes_the_page_back_to_its_original() {
+        let page = "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<title>Home</title>\n</head>\n<body>\n<main>\n<!-- brion:content:intro -->\n<p>Old intro</p>\n<!-- /brion:content:intro -->\n<!-- brion:content:news -->\n<p>Old news</p>\n<!-- /brion:content:news -->\n</main>\n</body>\n</html>\n";
+        // Regions update in name order, so the rejected intro comes first
+        let dir = scratch_site(&[
+            ("index.html", page),
+            ("content.json", "{\"intro\": \"<p>reject-me</p>\", \"news\": \"<p>Fresh news</p>\"}"),
+        ]);
+        let mut evaluator = ChangeEvaluator::new().with_threshold(0.3);
+        evaluator.register_rule(Box::new(RejectMarked));
+        let orchestrator = AgentOrchestrator::new(dir.clone()).with_evaluator(evaluator);
+        orchestrator.register_agent(Box::new(crate::agents::agent_impl::EnhancedContentAgent::new()));
+        let mut task = task_for(AgentType::ContentAgent, "index.html");
+        task.parameters.insert("content_source".to_string(), "content.json".to_string());
+        orchestrator.get_task_queue().add_task(task);
+
+        let result = orchestrator.run_agent_once(AgentType::ContentAgent).await.unwrap().unwrap();
+
+        assert_eq!(result.changes.len(), 2);
+        assert_eq!(read(&dir, "index.html"), page);
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[tokio::test]
+    async fn draining_the_queue_snapshots_what_it_kept_once() {
+        for template in [None, Some("{change_count} changes by {agent_type}")] {
+            let dir = scratch_site(&[("index.html", PAGE), ("about.html", PAGE)]);
+            let mut orchestrator = AgentOrchestrator::new(dir.clone())
+                .with_evaluator(ChangeEvaluator::new().with_threshold(0.0));
+            if let Some(template) = template {
+                orchestrator = orchestrator.with_snapshot_template(template.to_string());
+            }
+            orchestrator.register_agent(StampAgent::boxed(AgentType::UIAgent, "ui"));
+            orchestrator.get_task_queue().add_task(task_for(AgentType::UIAgent, "index.html"));
+            orchestrator.get_task_queue().add_task(task_for(AgentType::UIAgent, "about.html"));
+
+            orchestrator.drain_queue().await;
+
+            // One snapshot per task under a template, otherwise one for the drain
+            let expected = if template.is_some() { 2 } else { 1 };
+            assert_eq!(orchestrator.version_control.get_version_history().len(), expected);
+            assert!(orchestrator.version_control.unsnapshotted_changes().is_empty());
+            let _ = std::fs::remove_dir_all(dir);
+        }
+    }
+
+    /// Panics partway through every task
+    struct PanickingAgent;
+
+    #[async_trait::async_trait]
+    impl Agent for PanickingAgent {
+        fn get_type(&self) -> AgentType {
+            AgentType::SEOAgent
+        }
+
+        fn get_id(&self) -> &str {
+            "panicking-agent"
+        }
+
+        fn can_handle(&self, task: &AgentTask) -> bool {
+            task.agent_type == AgentType::SEOAgent
+        }
+
+        async fn execute_task(&self, _task: &AgentTask, _base_path: &Path) -> Result<AgentResult, String> {
+            panic!("agent crashed mid-task");
+        }
+    }
+
+    #[tokio::test]
+    async fn a_panicking_agent_gives_its_files_back() {
+        let dir = scratch_site(&[("index.html", PAGE)]);
+        let orchestrator = Arc::new(AgentOrchestrator::new(dir.clone()));
+        orchestrator.register_agent(Box::new(PanickingAgent));
+        orchestrator.get_task_queue().add_task(task_for(AgentType::SEOAgent, "index.html"));
+        *orchestrator.is_running.write() = true;
+
+        orchestrator.process_task_queue().await;
+
+        assert!(orchestrator.files_in_flight.read().is_empty());
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn claims_cover_the_pages_a_task_rewrites() {
+        let dir = scratch_site(&[("index.html", PAGE), ("about.html", PAGE), ("styles/main.css", "body {}")]);
+        let orchestrator = AgentOrchestrator::new(dir.clone());
+
+        let mut rename = task_for(AgentType::PerformanceAgent, "styles/main.css");
+        rename.parameters.insert("cache_bust".to_string(), "true".to_string());
+        let claim = orchestrator.claim_files(&rename).unwrap();
+        assert_eq!(claim.files, vec!["about.html", "index.html", "styles/main.css"]);
+
+        // A content update without targets scans every page, so it waits
+        let mut scan = task_for(AgentType::ContentAgent, "");
+        scan.target_file = None;
+        assert!(orchestrator.claim_files(&scan).is_none());
+
+        drop(claim);
+        assert!(orchestrator.files_in_flight.read().is_empty());
+        let claim = orchestrator.claim_files(&scan).unwrap();
+        assert_eq!(claim.files, vec!["about.html", "index.html"]);
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn a_rollback_is_recorded_and_applied_only_once() {
+        let dir = scratch_site(&[("index.html", PAGE)]);
+        let orchestrator = AgentOrchestrator::new(dir.clone());
+        let change = FileOperations::create_change("ui-agent", "UIAgent", "index.html".to_string(),
+            ChangeType::Modify, PAGE.to_string(), IMPROVED_PAGE.to_string());
+        let change_id = orchestrator.version_control.record_change(change.clone()).unwrap();
+        FileOperations::apply_change(&change, &dir, DurabilityPolicy::Fast).unwrap();
+
+        orchestrator.rollback_change(&change_id).unwrap();
+        orchestrator.rollback_change(&change_id).unwrap();
+
+        let reverts: Vec<Change> = orchestrator.version_control.get_all_changes().into_iter()
+            .filter(|change| change.metadata.contains_key(REVERTS_METADATA_KEY))
+            .collect();
+        assert_eq!(reverts.len(), 1);
+        assert_eq!(reverts[0].after, PAGE);
+        assert_eq!(read(&dir, "index.html"), PAGE);
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn a_rollback_of_a_held_file_fails_instead_of_deadlocking_a_current_thread_runtime() {
+        let dir = scratch_site(&[("index.html", PAGE)]);
+        let orchestrator = AgentOrchestrator::new(dir.clone());
+        let change = FileOperations::create_change("ui-agent", "UIAgent", "index.html".to_string(),
+            ChangeType::Modify, PAGE.to_string(), IMPROVED_PAGE.to_string());
+        let change_id = orchestrator.version_control.record_change(change.clone()).unwrap();
+        FileOperations::apply_change(&change, &dir, DurabilityPolicy::Fast).unwrap();
+
+        // An agent task on this thread holds the file, as one suspended mid-edit would
+        let held = orchestrator.file_locks.lock(&dir.join("index.html")).await;
+        assert!(matches!(orchestrator.rollback_change(&change_id), Err(BrionError::FileLocked(_))));
+        assert!(!orchestrator.version_control.is_rolled_back(&change_id));
+        assert_eq!(orchestrator.version_control.get_all_changes().len(), 1);
+
+        drop(held);
+        orchestrator.rollback_change(&change_id).unwrap();
+        assert_eq!(read(&dir, "index.html"), PAGE);
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn dropped_subscriptions_stop_listening() {
+        let dir = scratch_site(&[("index.html", PAGE)]);
+        let orchestrator = AgentOrchestrator::new(dir.clone());
+        orchestrator.on_event(|_| {});
+        let mut kept = orchestrator.subscribe(4);
+        for _ in 0..3 {
+            drop(orchestrator.subscribe(4));
+        }
+        assert_eq!(orchestrator.listeners.read().len(), 5);
+
+        orchestrator.emit(OrchestratorEvent::CycleCompleted { kept: 0, snapshot: None });
+
+        assert_eq!(orchestrator.listeners.read().len(), 2);
+        assert!(kept.try_recv().is_ok());
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    /// Reports that it started, then waits for the gate before finishing
+    /// without changes
+    struct GateAgent {
+        started: Arc<Notify>,
+        gate: Arc<Notify>,
+    }
+
+    #[async_trait::async_trait]
+    impl Agent for GateAgent {
+        fn get_type(&self) -> AgentType {
+            AgentType::UIAgent
+        }
+
+        fn get_id(&self) -> &str {
+            "gate-agent"
+        }
+
+        fn can_handle(&self, task: &AgentTask) -> bool {
+            task.agent_type == AgentType::UIAgent
+        }
+
+        async fn execute_task(&self, task: &AgentTask, _base_path: &Path) -> Result<AgentResult, String> {
+            self.started.notify_one();
+            self.gate.notified().await;
+            Ok(AgentResult {
+                task_id: task.id.clone(),
+                agent_id: "gate-agent".to_string(),
+                success: true,
+                changes: Vec::new(),
+                records: Vec::new(),
+                message: "Passed the gate".to_string(),
+                metrics: HashMap::new(),
+                confidence: None,
+                follow_up_tasks: Vec::new(),
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn agents_registered_mid_tick_are_dispatched_next_tick() {
+        let dir = scratch_site(&[("index.html", PAGE), ("about.html", PAGE)]);
+        let orchestrator = Arc::new(AgentOrchestrator::new(dir.clone())
+            .with_evaluator(ChangeEvaluator::new().with_threshold(0.0)));
+        let (started, gate) = (Arc::new(Notify::new()), Arc::new(Notify::new()));
+        orchestrator.register_agent(Box::new(GateAgent { started: started.clone(), gate: gate.clone() }));
+        orchestrator.get_task_queue().add_task(task_for(AgentType::UIAgent, "index.html"));
+        orchestrator.get_task_queue().add_task(task_for(AgentType::SEOAgent, "about.html"));
+        *orchestrator.is_running.write() = true;
+
+        let tick = tokio::spawn({
+            let orchestrator = Arc::clone(&orchestrator);
+            async move { orchestrator.process_task_queue().await }
+        });
+        started.notified().await;
+
+        // The tick is mid-task; registering must not wait for it
+        let registered = tokio::task::spawn_blocking({
+            let orchestrator = Arc::clone(&orchestrator);
+            move || orchestrator.register_agent(StampAgent::boxed(AgentType::SEOAgent, "seo"))
+        });
+        tokio::time::timeout(Duration::from_secs(5), registered).await
+            .expect("registering an agent blocked on the running tick")
+            .unwrap();
+        gate.notify_one();
+        tick.await.unwrap();
+
+        orchestrator.process_task_queue().await;
+
+        assert_eq!(orchestrator.get_stats().agents_active, 2);
+        assert_eq!(orchestrator.get_agent_stats()["seo-agent"].tasks_attempted, 1);
+        assert_eq!(read(&dir, "about.html"), format!("{}<!-- seo -->\n", PAGE));
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[tokio::test]
+    async fn a_tick_moves_tasks_from_pending_through_running_to_completed_or_failed() {
+        let dir = scratch_site(&[("index.html", PAGE), ("about.html", PAGE), ("contact.html", PAGE)]);
+        let orchestrator = Arc::new(AgentOrchestrator::new(dir.clone())
+            .with_evaluator(ChangeEvaluator::new().with_threshold(0.0))
+            .with_max_files_per_task(1, FileLimitPolicy::Fail));
+        let (started, gate) = (Arc::new(Notify::new()), Arc::new(Notify::new()));
+        orchestrator.register_agent(Box::new(GateAgent { started: started.clone(), gate: gate.clone() }));
+        orchestrator.register_agent(StampAgent::boxed(AgentType::SEOAgent, "seo"));
+        let passing = task_for(AgentType::UIAgent, "index.html");
+        // Two files against a cap of one, so the task fails without touching either
+        let mut failing = task_for(AgentType::SEOAgent, "");
+        failing.target_file = None;
+        failing.target_files = vec!["about.html".to_string(), "contact.html".to_string()];
+        let (passing_id, failing_id) = (passing.id.clone(), failing.id.clone());
+        let queue = orchestrator.get_task_queue();
+        queue.add_task(passing);
+        queue.add_task(failing);
+        assert_eq!(queue.get_task_status(&passing_id), Some(TaskStatus::Pending));
+        assert_eq!(queue.get_task_status(&failing_id), Some(TaskStatus::Pending));
+        *orchestrator.is_running.write() = true;
+
+        let tick = tokio::spawn({
+            let orchestrator = Arc::clone(&orchestrator);
+            async move { orchestrator.process_task_queue().await }
+        });
+        started.notified().await;
+        assert_eq!(queue.get_task_status(&passing_id), Some(TaskStatus::Running));
+
+        gate.notify_one();
+        tick.await.unwrap();
+
+        assert_eq!(queue.get_task_status(&passing_id), Some(TaskStatus::Completed));
+        assert!(matches!(queue.get_task_status(&failing_id), Some(TaskStatus::Failed(_))));
+        assert_eq!(queue.get_tasks_by_status(&TaskStatus::Pending), Vec::<String>::new());
+        assert_eq!(read(&dir, "about.html"), PAGE);
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    /// A running loop whose gated agent has started one of several queued
+    /// tasks, with `started` already consumed
+    async fn loop_with_a_task_in_flight(dir: &Path, timeout: Duration) -> (Arc<AgentOrchestrator>, Arc<Notify>, JoinHandle<()>) {
+        let orchestrator = Arc::new(AgentOrchestrator::new(dir.to_path_buf())
+            .with_max_concurrency(1)
+            .with_shutdown_timeout(timeout));
+        let (started, gate) = (Arc::new(Notify::new()), Arc::new(Notify::new()));
+        orchestrator.register_agent(Box::new(GateAgent { started: started.clone(), gate: gate.clone() }));
+        for page in ["index.html", "about.html"] {
+            orchestrator.get_task_queue().add_task(task_for(AgentType::UIAgent, page));
+        }
+        let handle = Arc::clone(&orchestrator).start_continuous_improvement();
+        started.notified().await;
+        (orchestrator, gate, handle)
+    }
+
+    #[tokio::test]
+    async fn shutdown_waits_for_the_task_in_flight_and_starts_no_other() {
+        let dir = scratch_site(&[("index.html", PAGE), ("about.html", PAGE)]);
+        let (orchestrator, gate, handle) = loop_with_a_task_in_flight(&dir, Duration::from_secs(5)).await;
+
+        let shutdown = tokio::spawn({
+            let orchestrator = Arc::clone(&orchestrator);
+            async move { orchestrator.shutdown().await }
+        });
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(!shutdown.is_finished(), "shutdown returned with a task still running");
+
+        gate.notify_one();
+        // A second task would wait at the gate until the timeout ran out
+        assert!(shutdown.await.unwrap());
+        tokio::time::timeout(Duration::from_secs(5), handle).await
+            .expect("the loop started another task after stop")
+            .unwrap();
+        assert_eq!(orchestrator.get_agent_stats()["gate-agent"].tasks_attempted, 1);
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[tokio::test]
+    async fn shutdown_gives_up_on_a_task_that_outlasts_the_timeout() {
+        let dir = scratch_site(&[("index.html", PAGE), ("about.html", PAGE)]);
+        let (orchestrator, gate, handle) = loop_with_a_task_in_flight(&dir, Duration::from_millis(50)).await;
+
+        assert!(!orchestrator.shutdown().await);
+
+        // Once the straggler finishes, the stopped loop exits without another task
+        gate.notify_one();
+        tokio::time::timeout(Duration::from_secs(5), handle).await
+            .expect("the loop started another task after stop")
+            .unwrap();
+        assert_eq!(orchestrator.get_agent_stats()["gate-agent"].tasks_attempted, 1);
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    /// Tasks served per type when UI work runs alone for three ticks, then
+    /// SEO and content agents join and every type has a backlog for four more
+    async fn served_after_a_late_start(fairness: bool) -> Vec<usize> {
+        let types = [(AgentType::UIAgent, "ui"), (AgentType::SEOAgent, "seo"), (AgentType::ContentAgent, "content")];
+        let files: Vec<String> = types.iter().flat_map(|(_, stamp)| (0..9).map(move |i| format!("{}{}.html", stamp, i))).collect();
+        let dir = scratch_site(&files.iter().map(|file| (file.as_str(), PAGE)).collect::<Vec<_>>());
+        let orchestrator = Arc::new(AgentOrchestrator::new(dir.clone())
+            .with_evaluator(ChangeEvaluator::new().with_threshold(0.0))
+            .with_max_concurrency(1)
+            .with_fairness_mode(fairness));
+        *orchestrator.is_running.write() = true;
+
+        orchestrator.register_agent(StampAgent::boxed(AgentType::UIAgent, "ui"));
+        for i in 0..3 {
+            orchestrator.get_task_queue().add_task(task_for(AgentType::UIAgent, &format!("ui{}.html", i)));
+            orchestrator.process_task_queue().await;
+        }
+        for (agent_type, stamp) in &types[1..] {
+            orchestrator.register_agent(StampAgent::boxed(agent_type.clone(), stamp));
+        }
+        for (agent_type, stamp) in &types {
+            for i in 3..9 {
+                orchestrator.get_task_queue().add_task(task_for(agent_type.clone(), &format!("{}{}.html", stamp, i)));
+            }
+        }
+        for _ in 0..4 {
+            orchestrator.process_task_queue().await;
+        }
+
+        let served = orchestrator.get_stats().tasks_served;
+        let _ = std::fs::remove_dir_all(dir);
+        types.iter().map(|(agent_type, _)| served.get(agent_type).copied().unwrap_or(0)).collect()
+    }
+
+    #[tokio::test]
+    async fn fairness_mode_lets_under_served_agent_types_catch_up() {
+        let served = served_after_a_late_start(true).await;
+        assert_eq!(served.iter().sum::<usize>(), 15, "served {:?}", served);
+        let (min, max) = (*served.iter().min().unwrap(), *served.iter().max().unwrap());
+        assert!(max - min <= 1, "served {:?}", served);
+
+        // Without it every type gets one task a tick and UI keeps its head start
+        assert_eq!(served_after_a_late_start(false).await, vec![7, 4, 4]);
+    }
+
+    #[tokio::test]
+    async fn a_change_rejected_again_is_short_circuited_as_quarantined() {
+        let dir = scratch_site(&[("index.html", PAGE)]);
+        let mut evaluator = ChangeEvaluator::new().with_threshold(0.3);
+        evaluator.register_rule(Box::new(RejectMarked));
+        let orchestrator = AgentOrchestrator::new(dir.clone()).with_evaluator(evaluator);
+        orchestrator.register_agent(StampAgent::boxed(AgentType::SEOAgent, "reject-me"));
+
+        let mut proposed = Vec::new();
+        for _ in 0..2 {
+            orchestrator.get_task_queue().add_task(task_for(AgentType::SEOAgent, "index.html"));
+            let result = orchestrator.run_agent_once(AgentType::SEOAgent).await.unwrap().unwrap();
+            proposed.push(result.changes[0].clone());
+        }
+
+        let stats = orchestrator.get_stats();
+        assert_eq!(stats.rolled_back_changes, 1);
+        assert_eq!(stats.quarantined_changes, 1);
+        let journal = orchestrator.get_version_control();
+        assert!(journal.get_evaluation(&proposed[0]).is_some());
+        assert!(journal.get_evaluation(&proposed[1]).is_none());
+        assert_eq!(read(&dir, "index.html"), PAGE);
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[tokio::test]
+    async fn changes_from_an_agent_without_version_control_are_still_evaluated() {
+        let dir = scratch_site(&[("index.html", PAGE)]);
+        let orchestrator = AgentOrchestrator::new(dir.clone())
+            .with_evaluator(ChangeEvaluator::new().with_threshold(0.0));
+        // StampAgent records nothing itself and only hands back its changes
+        orchestrator.register_agent(StampAgent::boxed(AgentType::UIAgent, "ui"));
+        orchestrator.get_task_queue().add_task(task_for(AgentType::UIAgent, "index.html"));
+
+        let result = orchestrator.run_agent_once(AgentType::UIAgent).await.unwrap().unwrap();
+
+        let journal = orchestrator.get_version_control();
+        let change = journal.get_change(&result.changes[0]).expect("the orchestrator records the change");
+        assert!(change.evaluation_score.is_some());
+        assert!(journal.get_evaluation(&change.id).is_some());
+        assert_eq!(orchestrator.get_agent_stats()["ui-agent"].kept, 1);
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[tokio::test]
+    async fn registered_enhanced_agents_record_into_the_orchestrator_journal() {
+        let dir = scratch_site(&[("styles/main.css", "body {\n    color: #333;\n}\n")]);
+        let orchestrator = AgentOrchestrator::new(dir.clone())
+            .with_evaluator(ChangeEvaluator::new().with_threshold(0.0));
+        // No with_version_control: registration hands the agent the journal
+        orchestrator.register_agent(Box::new(EnhancedUIAgent::new()));
+        orchestrator.get_task_queue().add_task(task_for(AgentType::UIAgent, "styles/main.css"));
+
+        let result = orchestrator.run_agent_once(AgentType::UIAgent).await.unwrap().unwrap();
+
+        assert!(!result.changes.is_empty());
+        let journal = orchestrator.get_version_control();
+        for change_id in &result.changes {
+            let change = journal.get_change(change_id).expect("change missing from the orchestrator journal");
+            assert_eq!(change.file_path, "styles/main.css");
+        }
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn diagnostics_report_drift_for_a_file_edited_out_of_band() {
+        let dir = scratch_site(&[("index.html", PAGE), ("about.html", PAGE)]);
+        let orchestrator = AgentOrchestrator::new(dir.clone());
+        let journal = orchestrator.get_version_control();
+        for file in ["index.html", "about.html"] {
+            let change = FileOperations::create_change("ui-agent", "UIAgent", file.to_string(),
+                ChangeType::Modify, PAGE.to_string(), IMPROVED_PAGE.to_string());
+            journal.record_change(change.clone()).unwrap();
+            FileOperations::apply_change(&change, &dir, DurabilityPolicy::Fast).unwrap();
+        }
+        let mut events = orchestrator.subscribe(16);
+
+        std::fs::write(dir.join("about.html"), "<p>edited by hand</p>\n").unwrap();
+        orchestrator.run_diagnostics();
+
+        let mut drifted = Vec::new();
+        while let Ok(event) = events.try_recv() {
+            if let OrchestratorEvent::Drift { file, .. } = event {
+                drifted.push(file);
+            }
+        }
+        assert_eq!(drifted, vec!["about.html"]);
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn performance_weighting_prefers_the_agent_whose_changes_are_kept() {
+        let dir = scratch_site(&[("index.html", PAGE)]);
+        let orchestrator = AgentOrchestrator::new(dir.clone())
+            .with_selection_strategy(SelectionStrategy::PerformanceWeighted);
+        orchestrator.register_agent(StampAgent::boxed(AgentType::UIAgent, "good"));
+        orchestrator.register_agent(StampAgent::boxed(AgentType::UIAgent, "poor"));
+        {
+            let mut stats = orchestrator.stats.write();
+            stats.agent_scores.insert("good-agent".to_string(), AgentScore { kept: 9, rolled_back: 1, ..Default::default() });
+            stats.agent_scores.insert("poor-agent".to_string(), AgentScore { kept: 1, rolled_back: 9, ..Default::default() });
+        }
+        let agent_list = orchestrator.agents.read()[&AgentType::UIAgent].clone();
+        let task = task_for(AgentType::UIAgent, "index.html");
+
+        let mut picks: HashMap<String, usize> = HashMap::new();
+        for _ in 0..100 {
+            let agent = orchestrator.select_agent(&agent_list, &task).unwrap();
+            *picks.entry(agent.get_id().to_string()).or_default() += 1;
+        }
+
+        let (good, poor) = (picks["good-agent"], picks.get("poor-agent").copied().unwrap_or(0));
+        assert!(good > poor * 3, "picked {:?}", picks);
+        assert!(poor > 0, "the weaker agent should still be tried: {:?}", picks);
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn batch_rollback_reports_each_outcome_and_can_stop_at_a_conflict() {
+        let dir = scratch_site(&[("a.html", PAGE), ("b.html", PAGE), ("c.html", PAGE)]);
+        let orchestrator = AgentOrchestrator::new(dir.clone());
+        let journal = orchestrator.get_version_control();
+        let change_ids: Vec<String> = ["a.html", "b.html", "c.html"].iter()
+            .map(|file| {
+                let change = FileOperations::create_change("ui-agent", "UIAgent", file.to_string(),
+                    ChangeType::Modify, PAGE.to_string(), IMPROVED_PAGE.to_string());
+                journal.record_change(change.clone()).unwrap();
+                FileOperations::apply_change(&change, &dir, DurabilityPolicy::Fast).unwrap();
+                change.id
+            })
+            .collect();
+        orchestrator.rollback_change(&change_ids[0]).unwrap();
+        std::fs::write(dir.join("b.html"), "<p>edited by hand</p>\n").unwrap();
+
+        let report = orchestrator.rollback_changes(&change_ids, RollbackMode::StopOnConflict);
+
+        assert!(report.halted);
+        assert_eq!(report.entries.len(), 2);
+        assert!(matches!(report.entries[0].1, RollbackOutcome::Skipped(_)));
+        assert!(matches!(report.entries[1].1, RollbackOutcome::Conflict(_)));
+        assert_eq!(read(&dir, "c.html"), IMPROVED_PAGE);
+
+        let report = orchestrator.rollback_changes(&change_ids, RollbackMode::ContinueOnConflict);
+
+        assert!(!report.halted);
+        let outcomes: Vec<&RollbackOutcome> = report.entries.iter().map(|(_, outcome)| outcome).collect();
+        assert!(matches!(outcomes[..], [RollbackOutcome::Skipped(_), RollbackOutcome::Conflict(_), RollbackOutcome::Reverted]));
+        assert_eq!(read(&dir, "b.html"), "<p>edited by hand</p>\n");
+        assert_eq!(read(&dir, "c.html"), PAGE);
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn applying_suggestions_lands_only_those_the_filter_accepts() {
+        let dir = scratch_site(&[("a.html", PAGE), ("b.html", PAGE), ("c.html", PAGE)]);
+        let orchestrator = AgentOrchestrator::new(dir.clone()).with_suggest_mode(true);
+        for (file, score) in [("a.html", 0.9), ("b.html", 0.5), ("c.html", 0.8)] {
+            let change = FileOperations::create_change("ui-agent", "UIAgent", file.to_string(),
+                ChangeType::Modify, PAGE.to_string(), IMPROVED_PAGE.to_string());
+            orchestrator.suggestions.write().push(Suggestion { change, score, issues: Vec::new() });
+        }
+
+        let applied = orchestrator.apply_suggestions(|suggestion| suggestion.score >= 0.8).unwrap();
+
+        assert_eq!(applied.len(), 2);
+        assert_eq!(read(&dir, "a.html"), IMPROVED_PAGE);
+        assert_eq!(read(&dir, "b.html"), PAGE);
+        assert_eq!(read(&dir, "c.html"), IMPROVED_PAGE);
+        let journal = orchestrator.get_version_control();
+        let files: Vec<String> = applied.iter().map(|id| journal.get_change(id).unwrap().file_path).collect();
+        assert_eq!(files, vec!["a.html", "c.html"]);
+        let pending = orchestrator.get_suggestions();
+        assert_eq!(pending.len(), 1);
+        assert_eq!(pending[0].change.file_path, "b.html");
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[tokio::test]
+    async fn a_scoped_agent_never_touches_files_outside_its_subtree() {
+        let dir = scratch_site(&[("blog/post.html", PAGE), ("index.html", PAGE), ("blog/../about.html", PAGE)]);
+        let orchestrator = AgentOrchestrator::new(dir.clone())
+            .with_evaluator(ChangeEvaluator::new().with_threshold(0.0));
+        orchestrator.register_scoped_agent(StampAgent::boxed(AgentType::ContentAgent, "blog"), PathBuf::from("blog"));
+        for target in ["blog/post.html", "index.html", "blog/../about.html"] {
+            orchestrator.get_task_queue().add_task(task_for(AgentType::ContentAgent, target));
+        }
+
+        orchestrator.drain_queue().await;
+
+        assert_eq!(read(&dir, "blog/post.html"), format!("{}<!-- blog -->\n", PAGE));
+        assert_eq!(read(&dir, "index.html"), PAGE);
+        assert_eq!(read(&dir, "about.html"), PAGE);
+        let changes = orchestrator.get_version_control().get_all_changes();
+        assert_eq!(changes.len(), 1);
+        assert!(changes.iter().all(|change| change.file_path.starts_with("blog/")));
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[tokio::test]
+    async fn a_scoped_agent_is_journaled_and_reset_under_its_subtree() {
+        let dir = scratch_site(&[("index.html", PAGE), ("blog/index.html", PAGE)]);
+        let orchestrator = AgentOrchestrator::new(dir.clone())
+            .with_evaluator(ChangeEvaluator::new().with_threshold(0.0));
+        orchestrator.register_scoped_agent(Box::new(EnhancedUIAgent::new()), PathBuf::from("blog"));
+        orchestrator.register_scoped_agent(StampAgent::boxed(AgentType::ContentAgent, "blog"), PathBuf::from("blog"));
+        let mut listed = task_for(AgentType::UIAgent, "unused");
+        listed.target_file = None;
+        listed.target_files = vec!["blog/index.html".to_string()];
+        let mut untargeted = task_for(AgentType::ContentAgent, "unused");
+        untargeted.target_file = None;
+        orchestrator.get_task_queue().add_task(listed);
+        orchestrator.get_task_queue().add_task(untargeted);
+
+        orchestrator.drain_queue().await;
+
+        assert!(read(&dir, "blog/index.html").contains("theme-color"));
+        assert_eq!(read(&dir, "index.html"), PAGE);
+        let journal = orchestrator.get_version_control();
+        let files: Vec<String> = journal.get_all_changes().into_iter().map(|c| c.file_path).collect();
+        assert_eq!(files, vec!["blog/index.html"]);
+        assert_eq!(journal.original_contents().into_keys().collect::<Vec<_>>(), vec!["blog/index.html"]);
+
+        assert_eq!(orchestrator.reset_to_original().unwrap().len(), 1);
+        assert_eq!(read(&dir, "blog/index.html"), PAGE);
+        assert_eq!(read(&dir, "index.html"), PAGE);
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn parallel_evaluation_matches_sequential() {
+        let dir = scratch_site(&[]);
+        let changes: Vec<Change> = (0..24)
+            .map(|i| {
+                let (file, after) = match i % 3 {
+                    0 => (format!("page{}.html", i), IMPROVED_PAGE.to_string()),
+                    1 => (format!("style{}.css", i), format!("body {{ margin: {}px; }}\n", i)),
+                    _ => (format!("app{}.js", i), format!("console.log({});\n", i)),
+                };
+                FileOperations::create_change("ui-agent", "UIAgent", file, ChangeType::Modify, PAGE.to_string(), after)
+            })
+            .collect();
+        let sequential = AgentOrchestrator::new(dir.clone());
+        let parallel = AgentOrchestrator::new(dir.clone()).with_evaluation_parallelism(4);
+
+        let expected = sequential.evaluate_batch(&changes).unwrap();
+        let actual = parallel.evaluate_batch(&changes).unwrap();
+
+        assert_eq!(actual.len(), changes.len());
+        for (expected, actual) in expected.iter().zip(&actual) {
+            assert_eq!(actual.change_id, expected.change_id);
+            assert_eq!(actual.overall_score, expected.overall_score);
+            assert_eq!(actual.should_keep, expected.should_keep);
+            assert_eq!(actual.issues, expected.issues);
+        }
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    /// Panics scoring changes to the named file
+    struct PanicsOn(&'static str);
+
+    impl ScoringRule for PanicsOn {
+        fn name(&self) -> &str {
+            "panics-on"
+        }
+
+        fn score(&self, change: &Change) -> RuleOutcome {
+            if change.file_path == self.0 {
+                panic!("cannot score {}", self.0);
+            }
+            RuleOutcome::pass()
+        }
+    }
+
+    #[test]
+    fn a_panicking_evaluation_worker_fails_the_batch_instead_of_the_cycle() {
+        let dir = scratch_site(&[]);
+        let mut evaluator = ChangeEvaluator::new();
+        evaluator.register_rule(Box::new(PanicsOn("broken.css")));
+        let orchestrator = AgentOrchestrator::new(dir.clone())
+            .with_evaluator(evaluator)
+            .with_evaluation_parallelism(2);
+        let changes: Vec<Change> = ["a.css", "broken.css", "b.css", "c.css"].iter()
+            .map(|file| FileOperations::create_change("ui-agent", "UIAgent", file.to_string(),
+                ChangeType::Modify, "body {}\n".to_string(), "body { margin: 0; }\n".to_string()))
+            .collect();
+
+        match orchestrator.evaluate_batch(&changes) {
+            Err(BrionError::EvaluationFailed(message)) => assert!(message.contains("cannot score broken.css")),
+            other => panic!("expected an evaluation failure, got {:?}", other.map(|e| e.len())),
+        }
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[tokio::test]
+    async fn task_snapshots_are_described_by_the_template() {
+        let dir = scratch_site(&[("index.html", PAGE)]);
+        let orchestrator = AgentOrchestrator::new(dir.clone())
+            .with_evaluator(ChangeEvaluator::new().with_threshold(0.0))
+            .with_snapshot_template("{agent_type}: {change_count} changes to {file_count} files".to_string());
+        orchestrator.register_agent(Box::new(ChainAgent { stamps: &["one", "two", "three"] }));
+        orchestrator.get_task_queue().add_task(task_for(AgentType::SEOAgent, "index.html"));
+
+        orchestrator.run_agent_once(AgentType::SEOAgent).await.unwrap();
+
+        let history = orchestrator.get_version_control().get_version_history();
+        assert_eq!(history.len(), 1);
+        assert_eq!(history[0].description, "SEOAgent: 3 changes to 1 files");
+        assert_eq!(history[0].changes.len(), 3);
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[tokio::test]
+    async fn evaluating_before_apply_never_writes_a_rejected_change() {
+        let css = "body {\n    color: #333;\n}\n";
+        let dir = scratch_site(&[("styles/main.css", css)]);
+        let path = dir.join("styles/main.css");
+        let untouched_since = std::time::UNIX_EPOCH + Duration::from_secs(1_000_000_000);
+        std::fs::File::options().write(true).open(&path).unwrap().set_modified(untouched_since).unwrap();
+        let orchestrator = AgentOrchestrator::new(dir.clone())
+            .with_evaluator(ChangeEvaluator::new().with_threshold(1.1))
+            .with_evaluate_before_apply(true);
+        orchestrator.register_agent(Box::new(EnhancedUIAgent::new()));
+        orchestrator.get_task_queue().add_task(task_for(AgentType::UIAgent, "styles/main.css"));
+
+        let result = orchestrator.run_agent_once(AgentType::UIAgent).await.unwrap().unwrap();
+
+        assert!(!result.changes.is_empty());
+        assert_eq!(orchestrator.get_stats().rolled_back_changes, result.changes.len());
+        // Apply-then-revert would leave the content alone but not the mtime
+        assert_eq!(read(&dir, "styles/main.css"), css);
+        assert_eq!(std::fs::metadata(&path).unwrap().modified().unwrap(), untouched_since);
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn rollback_preview_matches_the_rollback_it_previews() {
+        let dir = scratch_site(&[("a.html", "one\n"), ("b.html", PAGE), ("c.html", PAGE)]);
+        let orchestrator = AgentOrchestrator::new(dir.clone());
+        let journal = orchestrator.get_version_control();
+        let start = Utc::now();
+        let edits = [
+            ("a.html", ChangeType::Modify, "one\n", "one\ntwo\n"),
+            ("a.html", ChangeType::Modify, "one\ntwo\n", "one\ntwo\nthree\nfour\n"),
+            ("b.html", ChangeType::Modify, PAGE, IMPROVED_PAGE),
+            ("c.html", ChangeType::Modify, PAGE, IMPROVED_PAGE),
+            ("new.css", ChangeType::Create, "", "body {}\nh1 {}\n"),
+        ];
+        let mut change_ids: Vec<String> = edits.iter().enumerate()
+            .map(|(i, (file, change_type, before, after))| {
+                let mut change = FileOperations::create_change("ui-agent", "UIAgent", file.to_string(),
+                    change_type.clone(), before.to_string(), after.to_string());
+                change.timestamp = start + chrono::Duration::seconds(i as i64);
+                journal.record_change(change.clone()).unwrap();
+                FileOperations::apply_change(&change, &dir, DurabilityPolicy::Fast).unwrap();
+                change.id
+            })
+            .collect();
+        change_ids.reverse();
+        change_ids.push("missing".to_string());
+        std::fs::write(dir.join("c.html"), "<p>edited by hand</p>\n").unwrap();
+        let on_disk = |file: &str| std::fs::read_to_string(dir.join(file)).unwrap_or_default();
+
+        let preview = orchestrator.rollback_preview(&change_ids);
+
+        assert_eq!(on_disk("a.html"), "one\ntwo\nthree\nfour\n");
+        assert_eq!(preview.entries.len(), change_ids.len());
+        let mut files = HashSet::new();
+        for (change_id, entry) in change_ids.iter().zip(&preview.entries) {
+            let file = journal.get_change(change_id).map(|change| change.file_path);
+            let before = file.as_deref().map(on_disk).unwrap_or_default();
+            let report = orchestrator.rollback_changes(std::slice::from_ref(change_id), RollbackMode::ContinueOnConflict);
+            let after = file.as_deref().map(on_disk).unwrap_or_default();
+
+            assert_eq!(std::mem::discriminant(&entry.outcome), std::mem::discriminant(&report.entries[0].1), "{}", change_id);
+            let (added, removed) = VersionControl::line_delta(&before, &after);
+            assert_eq!(entry.lines_changed, added.len() + removed.len(), "{}", change_id);
+            if matches!(entry.outcome, RollbackOutcome::Reverted) {
+                files.insert(file.unwrap());
+            }
+        }
+        assert_eq!(preview.files_affected, files.len());
+        assert_eq!(preview.conflicts, 2);
+        assert_eq!(on_disk("a.html"), "one\n");
+        assert!(!dir.join("new.css").exists());
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    /// Collapses its target's whitespace, the way a minifier shrinks a file
+    struct MinifyAgent;
+
+    #[async_trait::async_trait]
+    impl Agent for MinifyAgent {
+        fn get_type(&self) -> AgentType {
+            AgentType::PerformanceAgent
+        }
+
+        fn get_id(&self) -> &str {
+            "minify-agent"
+        }
+
+        fn can_handle(&self, task: &AgentTask) -> bool {
+            task.agent_type == AgentType::PerformanceAgent
+        }
+
+        async fn execute_task(&self, task: &AgentTask, base_path: &Path) -> Result<AgentResult, String> {
+            let target = task.target_file.clone().unwrap_or_default();
+            let before = FileOperations::read_file(&base_path.join(&target))?;
+            let after = format!("{}\n", before.split_whitespace().collect::<Vec<_>>().join(" "));
+            let change = FileOperations::create_change("minify-agent", "PerformanceAgent", target, ChangeType::Optimize, before, after);
+            FileOperations::apply_change(&change, base_path, DurabilityPolicy::Fast)?;
+            Ok(AgentResult {
+                task_id: task.id.clone(),
+                agent_id: "minify-agent".to_string(),
+                success: true,
+                changes: vec![change.id.clone()],
+                records: vec![change],
+                message: "Minified".to_string(),
+                metrics: HashMap::new(),
+                confidence: None,
+                follow_up_tasks: Vec::new(),
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn a_trusted_minifier_is_kept_despite_a_low_heuristic_score() {
+        let script = "function greet(name) {\n    // Say hello\n    return 'Hello, ' + name;\n}\n\ngreet('you');\n";
+        let minified = "function greet(name) { // Say hello return 'Hello, ' + name; } greet('you');\n";
+        let mut outcomes = Vec::new();
+        for trusted in [false, true] {
+            let dir = scratch_site(&[("app.js", script)]);
+            let orchestrator = AgentOrchestrator::new(dir.clone())
+                .with_evaluator(ChangeEvaluator::new().with_threshold(0.95));
+            if trusted {
+                orchestrator.register_trusted_agent(Box::new(MinifyAgent));
+            } else {
+                orchestrator.register_agent(Box::new(MinifyAgent));
+            }
+            orchestrator.get_task_queue().add_task(task_for(AgentType::PerformanceAgent, "app.js"));
+
+            orchestrator.run_agent_once(AgentType::PerformanceAgent).await.unwrap();
+
+            outcomes.push(read(&dir, "app.js"));
+            let _ = std::fs::remove_dir_all(dir);
+        }
+
+        assert_eq!(outcomes, vec![script.to_string(), minified.to_string()]);
+    }
+
+    #[test]
+    fn reset_returns_every_touched_file_to_its_original() {
+        let dir = scratch_site(&[("index.html", "v1\n"), ("style.css", "body {}\n"), ("untouched.js", "x();\n")]);
+        let orchestrator = AgentOrchestrator::new(dir.clone());
+        let journal = orchestrator.get_version_control();
+        let start = Utc::now();
+        let edits = [
+            ("index.html", ChangeType::Modify, "v1\n", "v2\n"),
+            ("index.html", ChangeType::Modify, "v2\n", "v3\n"),
+            ("style.css", ChangeType::Modify, "body {}\n", "body { margin: 0; }\n"),
+            ("fonts/added.css", ChangeType::Create, "", "@font-face {}\n"),
+        ];
+        for (i, (file, change_type, before, after)) in edits.iter().enumerate() {
+            let mut change = FileOperations::create_change("ui-agent", "UIAgent", file.to_string(),
+                change_type.clone(), before.to_string(), after.to_string());
+            change.timestamp = start + chrono::Duration::seconds(i as i64);
+            journal.record_change(change.clone()).unwrap();
+            FileOperations::apply_change(&change, &dir, DurabilityPolicy::Fast).unwrap();
+        }
+
+        let reverts = orchestrator.reset_to_original().unwrap();
+
+        assert_eq!(reverts.len(), 3);
+        assert_eq!(read(&dir, "index.html"), "v1\n");
+        assert_eq!(read(&dir, "style.css"), "body {}\n");
+        assert_eq!(read(&dir, "untouched.js"), "x();\n");
+        assert!(!dir.join("fonts/added.css").exists());
+        assert!(reverts.iter().all(|id| journal.get_change(id).unwrap().metadata.contains_key(RESET_METADATA_KEY)));
+        assert!(orchestrator.reset_to_original().unwrap().is_empty());
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn recovery_after_a_reset_leaves_the_originals_in_place() {
+        let dir = scratch_site(&[("index.html", PAGE), ("style.css", "body {}\n")]);
+        let orchestrator = AgentOrchestrator::new(dir.clone());
+        let journal = orchestrator.get_version_control();
+        let edits = [
+            ("index.html", PAGE, "<p>edited</p>\n"),
+            ("style.css", "body {}\n", "body { margin: 0; }\n"),
+        ];
+        for (file, before, after) in edits {
+            let mut change = FileOperations::create_change("ui-agent", "UIAgent", file.to_string(),
+                ChangeType::Modify, before.to_string(), after.to_string());
+            change.evaluation_score = Some(0.9);
+            journal.record_change(change.clone()).unwrap();
+            FileOperations::apply_change(&change, &dir, DurabilityPolicy::Fast).unwrap();
+        }
+        assert_eq!(orchestrator.reset_to_original().unwrap().len(), 2);
+
+        let report = orchestrator.recover().unwrap();
+
+        assert_eq!(report.evaluated, 0);
+        assert_eq!(read(&dir, "index.html"), PAGE);
+        assert_eq!(read(&dir, "style.css"), "body {}\n");
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[tokio::test]
+    async fn tasks_without_a_target_use_the_configured_default() {
+        let dir = scratch_site(&[("index.html", PAGE), ("pages/landing.html", PAGE)]);
+        let orchestrator = AgentOrchestrator::new(dir.clone())
+            .with_evaluator(ChangeEvaluator::new().with_threshold(0.0))
+            .with_default_targets(AgentType::SEOAgent, vec!["pages/landing.html".to_string()]);
+        orchestrator.register_agent(StampAgent::boxed(AgentType::SEOAgent, "seo"));
+        let mut task = task_for(AgentType::SEOAgent, "");
+        task.target_file = None;
+        orchestrator.get_task_queue().add_task(task);
+
+        orchestrator.run_agent_once(AgentType::SEOAgent).await.unwrap();
+
+        assert_eq!(read(&dir, "pages/landing.html"), format!("{}<!-- seo -->\n", PAGE));
+        assert_eq!(read(&dir, "index.html"), PAGE);
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    /// Keeps every warning logged while it's installed
+    struct WarningLog;
+
+    static WARNINGS: std::sync::Mutex<Vec<String>> = std::sync::Mutex::new(Vec::new());
+
+    impl log::Log for WarningLog {
+        fn enabled(&self, metadata: &log::Metadata) -> bool {
+            metadata.level() <= log::Level::Warn
+        }
+
+        fn log(&self, record: &log::Record) {
+            if self.enabled(record.metadata()) {
+                WARNINGS.lock().unwrap().push(record.args().to_string());
+            }
+        }
+
+        fn flush(&self) {}
+    }
+
+    #[tokio::test]
+    async fn with_no_agents_generation_pauses_and_warns() {
+        static LOGGER: WarningLog = WarningLog;
+        let _ = log::set_logger(&LOGGER);
+        log::set_max_level(log::LevelFilter::Warn);
+
+        let dir = scratch_site(&[("index.html", PAGE)]);
+        let orchestrator = Arc::new(AgentOrchestrator::new(dir.clone()));
+        orchestrator.set_interval(Duration::from_millis(5)).unwrap();
+
+        let handle = Arc::clone(&orchestrator).start_continuous_improvement();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert!(orchestrator.shutdown().await);
+        handle.await.unwrap();
+
+        assert_eq!(orchestrator.get_task_queue().get_queue_size(), 0);
+        assert_eq!(orchestrator.get_stats().total_tasks_executed, 0);
+        assert!(WARNINGS.lock().unwrap().iter().any(|warning| warning.contains("No agents registered")));
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    /// A content agent with a bug: it deletes its target, recording the
+    /// change before applying it like the enhanced agents do
+    #[derive(Default)]
+    struct DeletingAgent {
+        version_control: Option<Arc<VersionControl>>,
+    }
+
+    #[async_trait::async_trait]
+    impl Agent for DeletingAgent {
+        fn get_type(&self) -> AgentType {
+            AgentType::ContentAgent
+        }
+
+        fn get_id(&self) -> &str {
+            "deleting-agent"
+        }
+
+        fn can_handle(&self, task: &AgentTask) -> bool {
+            task.agent_type == AgentType::ContentAgent
+        }
+
+        fn set_version_control(&mut self, vc: Arc<VersionControl>) {
+            self.version_control = Some(vc);
+        }
+
+        async fn execute_task(&self, task: &AgentTask, base_path: &Path) -> Result<AgentResult, String> {
+            let target = task.target_file.clone().unwrap_or_default();
+            let before = FileOperations::read_file(&base_path.join(&target))?;
+            let change = FileOperations::create_change("deleting-agent", "ContentAgent", target, ChangeType::Delete, before, String::new());
+            if let Some(ref vc) = self.version_control {
+                vc.record_change(change.clone())?;
+            }
+            FileOperations::apply_change(&change, base_path, DurabilityPolicy::Fast)?;
+            Ok(AgentResult {
+                task_id: task.id.clone(),
+                agent_id: "deleting-agent".to_string(),
+                success: true,
+                changes: vec![change.id.clone()],
+                records: vec![change],
+                message: "Deleted".to_string(),
+                metrics: HashMap::new(),
+                confidence: None,
+                follow_up_tasks: Vec::new(),
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn a_change_type_outside_the_allowlist_is_refused() {
+        let dir = scratch_site(&[("index.html", PAGE)]);
+        let orchestrator = AgentOrchestrator::new(dir.clone());
+        orchestrator.get_version_control().add_transform(Box::new(
+            ChangeTypeAllowlist::new().allow(AgentType::ContentAgent, vec![ChangeType::UpdateContent])
+        ));
+        orchestrator.register_agent(Box::new(DeletingAgent::default()));
+        orchestrator.get_task_queue().add_task(task_for(AgentType::ContentAgent, "index.html"));
+
+        let error = orchestrator.run_agent_once(AgentType::ContentAgent).await.unwrap_err();
+
+        assert!(error.to_string().contains("Delete is not allowed"), "{}", error);
+        assert_eq!(read(&dir, "index.html"), PAGE);
+        assert!(orchestrator.get_version_control().get_all_changes().is_empty());
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[tokio::test]
+    async fn a_run_of_no_op_ticks_is_detected_as_a_plateau() {
+        let dir = scratch_site(&[("index.html", PAGE)]);
+        let orchestrator = Arc::new(AgentOrchestrator::new(dir.clone())
+            .with_evaluator(ChangeEvaluator::new().with_threshold(0.0))
+            .with_plateau_detection(3, true));
+        orchestrator.register_agent(StampAgent::boxed(AgentType::SEOAgent, "seo"));
+        *orchestrator.is_running.write() = true;
+
+        for _ in 0..3 {
+            orchestrator.get_task_queue().add_task(task_for(AgentType::SEOAgent, "index.html"));
+            orchestrator.process_task_queue().await;
+        }
+        let productive = orchestrator.get_stats();
+        assert_eq!(productive.improvement_rate, 1.0);
+        assert!(!productive.plateau_detected);
+
+        let mut detected = Vec::new();
+        for _ in 0..3 {
+            orchestrator.process_task_queue().await;
+            detected.push(orchestrator.get_stats().plateau_detected);
+        }
+
+        assert_eq!(detected, vec![false, false, true]);
+        assert_eq!(orchestrator.get_stats().improvement_rate, 0.0);
+        assert!(!*orchestrator.is_running.read());
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    /// A StampAgent that reports how sure it is of its changes
+    struct UnsureAgent {
+        stamp: StampAgent,
+        confidence: f64,
+    }
+
+    #[async_trait::async_trait]
+    impl Agent for UnsureAgent {
+        fn get_type(&self) -> AgentType {
+            self.stamp.get_type()
+        }
+
+        fn get_id(&self) -> &str {
+            self.stamp.get_id()
+        }
+
+        fn can_handle(&self, task: &AgentTask) -> bool {
+            self.stamp.can_handle(task)
+        }
+
+        async fn execute_task(&self, task: &AgentTask, base_path: &Path) -> Result<AgentResult, String> {
+            let mut result = self.stamp.execute_task(task, base_path).await?;
+            result.confidence = Some(self.confidence);
+            Ok(result)
+        }
+    }
+
+    #[tokio::test]
+    async fn low_confidence_changes_wait_for_approval() {
+        let dir = scratch_site(&[("index.html", PAGE), ("about.html", PAGE)]);
+        let orchestrator = AgentOrchestrator::new(dir.clone())
+            .with_evaluator(ChangeEvaluator::new().with_threshold(0.0))
+            .with_min_confidence(0.7);
+        for (agent_type, stamp, confidence) in [(AgentType::SEOAgent, "unsure", 0.5), (AgentType::UIAgent, "sure", 0.9)] {
+            orchestrator.register_agent(Box::new(UnsureAgent {
+                stamp: StampAgent { id: format!("{}-agent", stamp), agent_type, stamp },
+                confidence,
+            }));
+        }
+        orchestrator.get_task_queue().add_task(task_for(AgentType::SEOAgent, "index.html"));
+        orchestrator.get_task_queue().add_task(task_for(AgentType::UIAgent, "about.html"));
+
+        orchestrator.run_agent_once(AgentType::SEOAgent).await.unwrap();
+        orchestrator.run_agent_once(AgentType::UIAgent).await.unwrap();
+
+        assert_eq!(read(&dir, "index.html"), PAGE);
+        assert_eq!(read(&dir, "about.html"), format!("{}<!-- sure -->\n", PAGE));
+        let suggestions = orchestrator.get_suggestions();
+        assert_eq!(suggestions.len(), 1);
+        assert_eq!(suggestions[0].change.agent_id, "unsure-agent");
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    /// A StampAgent that also asks for a content fix on the page it stamped
+    struct FollowUpAgent {
+        stamp: StampAgent,
+    }
+
+    #[async_trait::async_trait]
+    impl Agent for FollowUpAgent {
+        fn get_type(&self) -> AgentType {
+            self.stamp.get_type()
+        }
+
+        fn get_id(&self) -> &str {
+            self.stamp.get_id()
+        }
+
+        fn can_handle(&self, task: &AgentTask) -> bool {
+            self.stamp.can_handle(task)
+        }
+
+        async fn execute_task(&self, task: &AgentTask, base_path: &Path) -> Result<AgentResult, String> {
+            let mut result = self.stamp.execute_task(task, base_path).await?;
+            let mut follow_up = task_for(AgentType::ContentAgent, task.target_file.as_deref().unwrap_or_default());
+            follow_up.description = "Fix the broken link".to_string();
+            // Returned twice; dedup keeps one
+            result.follow_up_tasks = vec![follow_up.clone(), AgentTask { id: Uuid::new_v4().to_string(), ..follow_up }];
+            Ok(result)
+        }
+    }
+
+    #[tokio::test]
+    async fn follow_up_tasks_are_queued_once_the_result_is_processed() {
+        let dir = scratch_site(&[("index.html", PAGE)]);
+        let orchestrator = AgentOrchestrator::new(dir.clone())
+            .with_evaluator(ChangeEvaluator::new().with_threshold(0.0));
+        orchestrator.register_agent(Box::new(FollowUpAgent {
+            stamp: StampAgent { id: "links-agent".to_string(), agent_type: AgentType::SEOAgent, stamp: "links" },
+        }));
+        let queue = orchestrator.get_task_queue();
+        queue.add_task(task_for(AgentType::SEOAgent, "index.html"));
+
+        orchestrator.run_agent_once(AgentType::SEOAgent).await.unwrap();
+
+        assert_eq!(queue.get_queue_size(), 1);
+        let follow_up = queue.get_next_task(Some(AgentType::ContentAgent)).unwrap();
+        assert_eq!(follow_up.description, "Fix the broken link");
+        assert_eq!(follow_up.target_file.as_deref(), Some("index.html"));
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[tokio::test]
+    async fn draining_processes_every_queued_task_in_one_call() {
+        let dir = scratch_site(&[("a.html", PAGE), ("b.html", PAGE), ("c.html", PAGE)]);
+        let orchestrator = AgentOrchestrator::new(dir.clone())
+            .with_evaluator(ChangeEvaluator::new().with_threshold(0.0));
+        let types = [(AgentType::UIAgent, "ui", "a.html"), (AgentType::SEOAgent, "seo", "b.html"), (AgentType::ContentAgent, "content", "c.html")];
+        for (agent_type, stamp, _) in &types {
+            orchestrator.register_agent(StampAgent::boxed(agent_type.clone(), stamp));
+        }
+        let mut queued = HashSet::new();
+        for i in 0..10 {
+            let (agent_type, _, file) = &types[i % types.len()];
+            let task = task_for(agent_type.clone(), file);
+            queued.insert(task.id.clone());
+            orchestrator.get_task_queue().add_task(task);
+        }
+
+        let results = orchestrator.drain_queue().await;
+
+        assert_eq!(results.len(), 10);
+        assert_eq!(results.iter().map(|result| result.task_id.clone()).collect::<HashSet<_>>(), queued);
+        assert_eq!(orchestrator.get_task_queue().get_queue_size(), 0);
+        assert_eq!(read(&dir, "a.html").matches("<!-- ui -->").count(), 4);
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[tokio::test]
+    async fn a_task_over_the_file_cap_is_refused_or_split_as_configured() {
+        let pages: Vec<(String, &str)> = (1..=5).map(|i| (format!("pages/p{}.html", i), PAGE)).collect();
+        let files: Vec<(&str, &str)> = pages.iter().map(|(path, content)| (path.as_str(), *content)).collect();
+        let glob_task = |dir: &PathBuf| {
+            let mut task = task_for(AgentType::UIAgent, "");
+            task.target_file = None;
+            task.target_files = FileOperations::glob(dir, "pages/*.html");
+            task
+        };
+
+        let dir = scratch_site(&files);
+        let refusing = AgentOrchestrator::new(dir.clone())
+            .with_evaluator(ChangeEvaluator::new().with_threshold(0.0))
+            .with_max_files_per_task(2, FileLimitPolicy::Fail);
+        refusing.register_agent(StampAgent::boxed(AgentType::UIAgent, "ui"));
+        let task = glob_task(&dir);
+        assert_eq!(task.target_files.len(), 5);
+        let task_id = task.id.clone();
+        refusing.get_task_queue().add_task(task);
+
+        assert!(refusing.run_next_task(AgentType::UIAgent).await.is_err());
+        assert!(matches!(refusing.get_task_queue().get_task_status(&task_id), Some(TaskStatus::Failed(_))));
+        assert_eq!(refusing.get_task_queue().get_queue_size(), 0);
+        assert!((1..=5).all(|i| read(&dir, &format!("pages/p{}.html", i)) == PAGE));
+        let _ = std::fs::remove_dir_all(dir);
+
+        let dir = scratch_site(&files);
+        let splitting = AgentOrchestrator::new(dir.clone())
+            .with_evaluator(ChangeEvaluator::new().with_threshold(0.0))
+            .with_max_files_per_task(2, FileLimitPolicy::Split);
+        splitting.register_agent(StampAgent::boxed(AgentType::UIAgent, "ui"));
+        splitting.get_task_queue().add_task(glob_task(&dir));
+
+        assert!(splitting.run_next_task(AgentType::UIAgent).await.is_err());
+        assert_eq!(splitting.get_task_queue().get_queue_size(), 3);
+        assert!((1..=5).all(|i| read(&dir, &format!("pages/p{}.html", i)) == PAGE));
+
+        let results = splitting.drain_queue().await;
+        assert_eq!(results.len(), 3);
+        assert!((1..=5).all(|i| read(&dir, &format!("pages/p{}.html", i)).matches("<!-- ui -->").count() == 1));
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn a_protected_low_scoring_change_is_never_rolled_back() {
+        let dir = scratch_site(&[("index.html", PAGE), ("about.html", PAGE)]);
+        let mut evaluator = ChangeEvaluator::new().with_threshold(0.3);
+        evaluator.register_rule(Box::new(RejectMarked));
+        let orchestrator = AgentOrchestrator::new(dir.clone()).with_evaluator(evaluator);
+        let vc = orchestrator.get_version_control();
+        let marked = format!("{}<!-- reject-me -->\n", PAGE);
+        let mut ids = Vec::new();
+        for file in ["index.html", "about.html"] {
+            let change = FileOperations::create_change("ui-agent", "UIAgent", file.to_string(),
+                ChangeType::Modify, PAGE.to_string(), marked.clone());
+            vc.record_change(change.clone()).unwrap();
+            FileOperations::apply_change(&change, &dir, DurabilityPolicy::Fast).unwrap();
+            ids.push(change.id);
+        }
+        vc.protect_change(&ids[0]).unwrap();
+
+        let kept = orchestrator.evaluate_pending().unwrap();
+
+        assert_eq!(kept, vec![ids[0].clone()]);
+        assert_eq!(read(&dir, "index.html"), marked);
+        assert_eq!(read(&dir, "about.html"), PAGE);
+        assert_eq!(vc.get_change(&ids[0]).unwrap().evaluation_score, None);
+        assert_eq!(orchestrator.evaluate_pending().unwrap(), vec![ids[0].clone()]);
+        assert_eq!(read(&dir, "index.html"), marked);
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
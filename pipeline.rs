@@ -0,0 +1,26 @@
+// Agent Pipelines
+// Ordered agent stages run on a single target, each working on the previous stage's output
+
+use crate::agents::agents::AgentType;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Pipeline {
+    pub name: String,
+    pub stages: Vec<AgentType>,
+}
+
+impl Pipeline {
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            stages: Vec::new(),
+        }
+    }
+
+    /// Append a stage that runs after every stage added so far
+    pub fn then(mut self, agent_type: AgentType) -> Self {
+        self.stages.push(agent_type);
+        self
+    }
+}
@@ -0,0 +1,78 @@
+// Bounded Ring Buffer
+// Fixed-capacity history that drops the oldest entries, for long-running processes
+
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BoundedRingBuffer<T> {
+    items: VecDeque<T>,
+    capacity: usize,
+}
+
+impl<T> BoundedRingBuffer<T> {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            items: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    /// Append an item, evicting the oldest once at capacity
+    pub fn push(&mut self, item: T) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.items.len() == self.capacity {
+            self.items.pop_front();
+        }
+        self.items.push_back(item);
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Oldest to newest
+    pub fn iter(&self) -> impl Iterator<Item = &T> {
+        self.items.iter()
+    }
+
+    pub fn clear(&mut self) {
+        self.items.clear();
+    }
+}
+
+impl<T: Clone> BoundedRingBuffer<T> {
+    pub fn to_vec(&self) -> Vec<T> {
+        self.items.iter().cloned().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_the_last_n_items_in_order() {
+        let mut buffer = BoundedRingBuffer::new(3);
+        for i in 0..10 {
+            buffer.push(i);
+        }
+
+        assert_eq!(buffer.len(), 3);
+        assert_eq!(buffer.to_vec(), vec![7, 8, 9]);
+
+        let mut disabled = BoundedRingBuffer::new(0);
+        disabled.push(1);
+        assert!(disabled.is_empty());
+    }
+}
@@ -0,0 +1,307 @@
+// JSON-RPC 2.0 Control Interface
+// Lets scripts drive the orchestrator through a single endpoint, served
+// line by line over a unix socket
+
+use crate::agents::{
+    agents::AgentType,
+    orchestrator::{AgentOrchestrator, RollbackMode},
+    version_control::ChangeQuery,
+};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::sync::Arc;
+#[cfg(unix)]
+use crate::agents::error::BrionError;
+#[cfg(unix)]
+use std::path::Path;
+
+pub const PARSE_ERROR: i64 = -32700;
+pub const INVALID_REQUEST: i64 = -32600;
+pub const METHOD_NOT_FOUND: i64 = -32601;
+pub const INVALID_PARAMS: i64 = -32602;
+pub const SERVER_ERROR: i64 = -32000;
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct RpcRequest {
+    pub jsonrpc: String,
+    pub method: String,
+    #[serde(default)]
+    pub params: Value,
+    #[serde(default)]
+    pub id: Value,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RpcError {
+    pub code: i64,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RpcResponse {
+    pub jsonrpc: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub result: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<RpcError>,
+    pub id: Value,
+}
+
+impl RpcError {
+    fn new(code: i64, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+        }
+    }
+}
+
+impl RpcResponse {
+    fn success(id: Value, result: Value) -> Self {
+        Self {
+            jsonrpc: "2.0".to_string(),
+            result: Some(result),
+            error: None,
+            id,
+        }
+    }
+
+    fn failure(id: Value, error: RpcError) -> Self {
+        Self {
+            jsonrpc: "2.0".to_string(),
+            result: None,
+            error: Some(error),
+            id,
+        }
+    }
+}
+
+pub struct RpcHandler {
+    orchestrator: Arc<AgentOrchestrator>,
+}
+
+impl RpcHandler {
+    pub fn new(orchestrator: Arc<AgentOrchestrator>) -> Self {
+        Self { orchestrator }
+    }
+
+    /// Handle a raw JSON-RPC request body and return the serialized response.
+    /// Body that isn't JSON is a parse error; JSON that isn't a request
+    /// object is an invalid request.
+    pub async fn handle(&self, body: &str) -> String {
+        let response = match serde_json::from_str::<Value>(body) {
+            Ok(value) => match serde_json::from_value::<RpcRequest>(value) {
+                Ok(request) => self.handle_request(request).await,
+                Err(e) => RpcResponse::failure(Value::Null, RpcError::new(INVALID_REQUEST, e.to_string())),
+            },
+            Err(e) => RpcResponse::failure(Value::Null, RpcError::new(PARSE_ERROR, e.to_string())),
+        };
+
+        serde_json::to_string(&response).unwrap_or_default()
+    }
+
+    pub async fn handle_request(&self, request: RpcRequest) -> RpcResponse {
+        if request.jsonrpc != "2.0" {
+            return RpcResponse::failure(
+                request.id,
+                RpcError::new(INVALID_REQUEST, "jsonrpc must be \"2.0\""),
+            );
+        }
+
+        match self.dispatch(&request.method, &request.params).await {
+            Ok(result) => RpcResponse::success(request.id, result),
+            Err(error) => RpcResponse::failure(request.id, error),
+        }
+    }
+
+    async fn dispatch(&self, method: &str, params: &Value) -> Result<Value, RpcError> {
+        match method {
+            "get_stats" => Ok(json!(self.orchestrator.get_stats())),
+            "get_agent_stats" => Ok(json!(self.orchestrator.get_agent_stats())),
+            "run_agent_once" => {
+                let agent_type: AgentType = Self::param(params, "agent_type")?;
+                let result = self.orchestrator.run_agent_once(agent_type).await
+                    .map_err(|e| RpcError::new(SERVER_ERROR, e.to_string()))?;
+                Ok(json!(result))
+            }
+            "rollback_change" => {
+                let change_id: String = Self::param(params, "change_id")?;
+                self.orchestrator.rollback_change(&change_id)
+                    .map_err(|e| RpcError::new(SERVER_ERROR, e.to_string()))?;
+                Ok(json!({ "change_id": change_id }))
+            }
+            "create_snapshot" => {
+                let description: String = Self::param(params, "description")?;
+                let version_id = self.orchestrator.create_snapshot(description);
+                Ok(json!({ "version_id": version_id }))
+            }
+            "rollback_to_version" => {
+                let version_id: String = Self::param(params, "version_id")?;
+                let stop_on_conflict: bool = Self::optional_param(params, "stop_on_conflict")?.unwrap_or(true);
+                let mode = if stop_on_conflict {
+                    RollbackMode::StopOnConflict
+                } else {
+                    RollbackMode::ContinueOnConflict
+                };
+                let report = self.orchestrator.rollback_to_version(&version_id, mode)
+                    .map_err(|e| RpcError::new(SERVER_ERROR, e.to_string()))?;
+                Ok(json!({ "version_id": version_id, "report": report }))
+            }
+            "add_note" => {
+                let change_id: String = Self::param(params, "change_id")?;
+                let author: String = Self::param(params, "author")?;
+                let text: String = Self::param(params, "text")?;
+                self.orchestrator.get_version_control().add_note(&change_id, &author, &text)
+                    .map_err(|e| RpcError::new(SERVER_ERROR, e.to_string()))?;
+                Ok(json!({ "change_id": change_id }))
+            }
+            "protect_change" => {
+                let change_id: String = Self::param(params, "change_id")?;
+                self.orchestrator.get_version_control().protect_change(&change_id)
+                    .map_err(|e| RpcError::new(SERVER_ERROR, e.to_string()))?;
+                Ok(json!({ "change_id": change_id }))
+            }
+            "reenable_agent" => {
+                let agent_id: String = Self::param(params, "agent_id")?;
+                let reenabled = self.orchestrator.reenable_agent(&agent_id);
+                Ok(json!({ "agent_id": agent_id, "reenabled": reenabled }))
+            }
+            "get_evaluation" => {
+                let change_id: String = Self::param(params, "change_id")?;
+                Ok(json!(self.orchestrator.get_version_control().get_evaluation(&change_id)))
+            }
+            "query_changes" => {
+                let query: ChangeQuery = if params.is_null() {
+                    ChangeQuery::default()
+                } else {
+                    serde_json::from_value(params.clone())
+                        .map_err(|e| RpcError::new(INVALID_PARAMS, format!("Invalid query: {}", e)))?
+                };
+                Ok(json!(self.orchestrator.get_version_control().query(&query)))
+            }
+            other => Err(RpcError::new(METHOD_NOT_FOUND, format!("Method {} not found", other))),
+        }
+    }
+
+    fn param<T: DeserializeOwned>(params: &Value, name: &str) -> Result<T, RpcError> {
+        Self::optional_param(params, name)?
+            .ok_or_else(|| RpcError::new(INVALID_PARAMS, format!("Missing parameter {}", name)))
+    }
+
+    fn optional_param<T: DeserializeOwned>(params: &Value, name: &str) -> Result<Option<T>, RpcError> {
+        params.get(name)
+            .cloned()
+            .map(|value| serde_json::from_value(value)
+                .map_err(|e| RpcError::new(INVALID_PARAMS, format!("Invalid parameter {}: {}", name, e))))
+            .transpose()
+    }
+}
+
+#[cfg(unix)]
+impl RpcHandler {
+    /// Serve requests on a unix socket at `path`, one request per line,
+    /// each answered with one response line. A socket left at `path` by an
+    /// earlier run is replaced. Runs until accepting a connection fails.
+    pub async fn serve_unix(self: Arc<Self>, path: &Path) -> Result<(), BrionError> {
+        use std::os::unix::fs::FileTypeExt;
+        use tokio::net::UnixListener;
+
+        if std::fs::symlink_metadata(path).map(|m| m.file_type().is_socket()).unwrap_or(false) {
+            std::fs::remove_file(path).map_err(|e| BrionError::io_at(path, e))?;
+        }
+        let listener = UnixListener::bind(path).map_err(|e| BrionError::io_at(path, e))?;
+        loop {
+            let (stream, _) = listener.accept().await.map_err(|e| BrionError::io_at(path, e))?;
+            let handler = Arc::clone(&self);
+            tokio::spawn(async move {
+                if let Err(e) = handler.serve_connection(stream).await {
+                    log::warn!("RPC connection dropped: {}", e);
+                }
+            });
+        }
+    }
+
+    async fn serve_connection(&self, stream: tokio::net::UnixStream) -> std::io::Result<()> {
+        use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+
+        let (reader, mut writer) = stream.into_split();
+        let mut lines = BufReader::new(reader).lines();
+        while let Some(line) = lines.next_line().await? {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let mut response = self.handle(&line).await;
+            response.push('\n');
+            writer.write_all(response.as_bytes()).await?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use uuid::Uuid;
+
+    #[tokio::test]
+    async fn get_stats_answers_with_a_json_rpc_envelope() {
+        let dir = std::env::temp_dir().join(format!("brion-rpc-{}", Uuid::new_v4()));
+        let handler = RpcHandler::new(Arc::new(AgentOrchestrator::new(dir.clone())));
+
+        let body = handler.handle(r#"{"jsonrpc":"2.0","method":"get_stats","id":7}"#).await;
+
+        let response: Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(response["jsonrpc"], "2.0");
+        assert_eq!(response["id"], 7);
+        assert!(response.get("error").is_none());
+        assert_eq!(response["result"]["agents_active"], 0);
+        assert!(response["result"]["agent_scores"].is_object());
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[tokio::test]
+    async fn only_bodies_that_are_not_json_are_parse_errors() {
+        let dir = std::env::temp_dir().join(format!("brion-rpc-{}", Uuid::new_v4()));
+        let handler = RpcHandler::new(Arc::new(AgentOrchestrator::new(dir.clone())));
+
+        for (body, code) in [("{", PARSE_ERROR), ("{}", INVALID_REQUEST), ("[1, 2]", INVALID_REQUEST), ("\"get_stats\"", INVALID_REQUEST)] {
+            let response: Value = serde_json::from_str(&handler.handle(body).await).unwrap();
+            assert_eq!(response["error"]["code"], code, "{}", body);
+            assert_eq!(response["id"], Value::Null);
+        }
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn the_unix_socket_answers_each_request_line() {
+        use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+        use tokio::net::UnixStream;
+
+        let dir = std::env::temp_dir().join(format!("brion-rpc-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let socket = dir.join("rpc.sock");
+        let handler = Arc::new(RpcHandler::new(Arc::new(AgentOrchestrator::new(dir.clone()))));
+        let server = tokio::spawn({
+            let socket = socket.clone();
+            async move { handler.serve_unix(&socket).await }
+        });
+        while !socket.exists() {
+            tokio::task::yield_now().await;
+        }
+
+        let (reader, mut writer) = UnixStream::connect(&socket).await.unwrap().into_split();
+        writer.write_all(b"{\"jsonrpc\":\"2.0\",\"method\":\"get_stats\",\"id\":1}\n{\"jsonrpc\":\"2.0\",\"method\":\"nope\",\"id\":2}\n").await.unwrap();
+        let mut lines = BufReader::new(reader).lines();
+        let stats: Value = serde_json::from_str(&lines.next_line().await.unwrap().unwrap()).unwrap();
+        let unknown: Value = serde_json::from_str(&lines.next_line().await.unwrap().unwrap()).unwrap();
+
+        assert_eq!(stats["id"], 1);
+        assert_eq!(stats["result"]["agents_active"], 0);
+        assert_eq!(unknown["id"], 2);
+        assert_eq!(unknown["error"]["code"], METHOD_NOT_FOUND);
+        server.abort();
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
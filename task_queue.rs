@@ -2,10 +2,22 @@
 // Manages and prioritizes tasks for agents
 
 use crate::agents::agents::{AgentTask, AgentType};
-use std::collections::BinaryHeap;
+use crate::agents::error::BrionError;
+use crate::agents::file_ops::{DurabilityPolicy, FileOperations};
+use std::collections::{BTreeMap, BinaryHeap, HashMap, HashSet};
 use std::cmp::Ordering;
-use parking_lot::RwLock;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering as AtomicOrdering};
+use std::time::{Duration, Instant};
+use parking_lot::{Mutex, RwLock};
+use serde::{Deserialize, Serialize};
 use chrono::Utc;
+use log::{info, warn};
+use uuid::Uuid;
+
+/// Least time between two saves of a persisted queue; changes in between
+/// are written by the next save or `flush`
+pub const DEFAULT_SAVE_INTERVAL: Duration = Duration::from_secs(1);
 
 #[derive(Debug, Clone)]
 struct PrioritizedTask {
@@ -14,7 +26,7 @@ struct PrioritizedTask {
 
 impl PartialEq for PrioritizedTask {
     fn eq(&self, other: &Self) -> bool {
-        self.task.priority == other.task.priority
+        self.cmp(other) == Ordering::Equal
     }
 }
 
@@ -28,71 +40,501 @@ impl PartialOrd for PrioritizedTask {
 
 impl Ord for PrioritizedTask {
     fn cmp(&self, other: &Self) -> Ordering {
-        // Higher priority first, then by creation time
-        match other.task.priority.cmp(&self.task.priority) {
-            Ordering::Equal => self.task.created_at.cmp(&other.task.created_at),
-            other => other,
+        // Higher priority first, then by creation time, then by id so
+        // tasks created in the same instant still pop in a fixed order
+        self.task.priority.cmp(&other.task.priority)
+            .then_with(|| other.task.created_at.cmp(&self.task.created_at))
+            .then_with(|| other.task.id.cmp(&self.task.id))
+    }
+}
+
+/// Where a task is in its lifecycle
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TaskStatus {
+    Pending,
+    Running,
+    Completed,
+    Failed(String),
+}
+
+/// Whether a queued task's dependencies let it run
+enum Readiness {
+    Ready,
+    Waiting,         // A dependency is still pending, running, or not queued yet
+    Blocked(String), // A dependency failed, so this task never can run
+}
+
+/// The queue as `save_to` writes it
+#[derive(Serialize, Deserialize)]
+struct SavedQueue {
+    queued: Vec<AgentTask>, // Highest priority first
+    #[serde(default)]
+    in_flight: Vec<AgentTask>, // Taken but not finished; queued again on load
+    #[serde(default)]
+    statuses: BTreeMap<String, TaskStatus>,
+}
+
+/// Tasks with the same key do the same work
+type TaskKey = (AgentType, String, Option<String>, Vec<String>);
+
+/// Lookups over every queued task, kept alongside the heaps
+#[derive(Default)]
+struct PendingIndex {
+    ids: HashSet<String>,
+    keys: HashMap<TaskKey, usize>,
+}
+
+impl PendingIndex {
+    fn key(task: &AgentTask) -> TaskKey {
+        (task.agent_type.clone(), task.description.clone(), task.target_file.clone(), task.target_files.clone())
+    }
+
+    fn insert(&mut self, task: &AgentTask) {
+        self.ids.insert(task.id.clone());
+        *self.keys.entry(Self::key(task)).or_insert(0) += 1;
+    }
+
+    fn remove(&mut self, task: &AgentTask) {
+        self.ids.remove(&task.id);
+        let key = Self::key(task);
+        if let Some(count) = self.keys.get_mut(&key) {
+            *count -= 1;
+            if *count == 0 {
+                self.keys.remove(&key);
+            }
         }
     }
 }
 
 pub struct TaskQueue {
-    tasks: Arc<RwLock<BinaryHeap<PrioritizedTask>>>,
+    // One heap per agent type, so a typed fetch never touches other types
+    tasks: Arc<RwLock<HashMap<AgentType, BinaryHeap<PrioritizedTask>>>>,
+    pending: Arc<RwLock<PendingIndex>>,
     completed_tasks: Arc<RwLock<Vec<AgentTask>>>,
+    statuses: Arc<RwLock<HashMap<String, TaskStatus>>>,
+    in_flight: Arc<RwLock<HashMap<String, AgentTask>>>, // Taken by `get_next_task`, not completed or failed yet
+    persist_path: Option<PathBuf>, // Saved as the queue changes, at most once per `save_interval`
+    save_interval: Duration,
+    last_save: Arc<Mutex<Option<Instant>>>,
+    unsaved: Arc<AtomicBool>, // Changed since the last save
+    durability: Arc<RwLock<DurabilityPolicy>>,
+    save_lock: Arc<Mutex<()>>, // Held from reading the queue to renaming the file, so saves can't interleave
 }
 
 impl TaskQueue {
     pub fn new() -> Self {
         Self {
-            tasks: Arc::new(RwLock::new(BinaryHeap::new())),
+            tasks: Arc::new(RwLock::new(HashMap::new())),
+            pending: Arc::new(RwLock::new(PendingIndex::default())),
             completed_tasks: Arc::new(RwLock::new(Vec::new())),
+            statuses: Arc::new(RwLock::new(HashMap::new())),
+            in_flight: Arc::new(RwLock::new(HashMap::new())),
+            persist_path: None,
+            save_interval: DEFAULT_SAVE_INTERVAL,
+            last_save: Arc::new(Mutex::new(None)),
+            unsaved: Arc::new(AtomicBool::new(false)),
+            durability: Arc::new(RwLock::new(DurabilityPolicy::Fast)),
+            save_lock: Arc::new(Mutex::new(())),
+        }
+    }
+
+    /// Save the queue to `path` as it changes, so a restart can pick it up
+    /// again with `load_from`. Saves are at most `save_interval` apart;
+    /// `flush` writes what's left.
+    pub fn with_persistence(mut self, path: PathBuf) -> Self {
+        self.persist_path = Some(path);
+        self
+    }
+
+    /// Save a persisted queue at most once per `interval`
+    pub fn with_save_interval(mut self, interval: Duration) -> Self {
+        self.save_interval = interval;
+        self
+    }
+
+    /// Save the queue under `policy`
+    pub fn set_durability_policy(&self, policy: DurabilityPolicy) {
+        *self.durability.write() = policy;
+    }
+
+    /// Write the queue to `path` as JSON: queued tasks highest priority
+    /// first, tasks taken but not finished, and every task's status.
+    /// Concurrent saves run one at a time, each writing the queue as it is
+    /// when its turn comes, so the last save always holds the latest queue.
+    pub fn save_to(&self, path: &Path) -> Result<(), BrionError> {
+        let _saving = self.save_lock.lock();
+        let saved = {
+            let tasks = self.tasks.read();
+            let mut queued: Vec<&PrioritizedTask> = tasks.values().flat_map(|heap| heap.iter()).collect();
+            queued.sort_by(|a, b| b.cmp(a));
+            let mut in_flight: Vec<AgentTask> = self.in_flight.read().values().cloned().collect();
+            in_flight.sort_by(|a, b| (a.created_at, &a.id).cmp(&(b.created_at, &b.id)));
+            SavedQueue {
+                queued: queued.into_iter().map(|p| p.task.clone()).collect(),
+                in_flight,
+                statuses: self.statuses.read().iter().map(|(id, status)| (id.clone(), status.clone())).collect(),
+            }
+        };
+        FileOperations::write_file(path, &serde_json::to_string_pretty(&saved)?, *self.durability.read())
+    }
+
+    /// Queue the tasks saved at `path`, skipping any already queued, and
+    /// restore the saved statuses. Tasks that were taken but never finished
+    /// are queued again. A missing file is an empty queue, and a plain task
+    /// list from before statuses were saved is read as queued tasks.
+    /// Returns how many were queued.
+    pub fn load_from(&self, path: &Path) -> Result<usize, BrionError> {
+        let content = match FileOperations::read_file(path) {
+            Ok(content) => content,
+            Err(BrionError::FileNotFound(_)) => return Ok(0),
+            Err(e) => return Err(e),
+        };
+        let saved: SavedQueue = match serde_json::from_str(&content) {
+            Ok(saved) => saved,
+            Err(_) => SavedQueue {
+                queued: serde_json::from_str(&content)?,
+                in_flight: Vec::new(),
+                statuses: BTreeMap::new(),
+            },
+        };
+
+        {
+            // Statuses first, so loaded tasks see how their dependencies went
+            let mut statuses = self.statuses.write();
+            for (id, status) in saved.statuses {
+                statuses.entry(id).or_insert(status);
+            }
+        }
+        if !saved.in_flight.is_empty() {
+            info!("Queueing {} tasks again that were running when the queue was saved", saved.in_flight.len());
+        }
+
+        let mut loaded = 0;
+        {
+            let mut tasks = self.tasks.write();
+            for task in saved.queued.into_iter().chain(saved.in_flight) {
+                if self.pending.read().ids.contains(&task.id) || self.in_flight.read().contains_key(&task.id) {
+                    continue;
+                }
+                self.push(&mut tasks, task);
+                loaded += 1;
+            }
+        }
+        self.persist();
+        Ok(loaded)
+    }
+
+    /// Note that the queue changed and save it, if it's persisted and the
+    /// last save is at least `save_interval` old. Failures are logged
+    /// rather than returned so queue operations never fail on disk errors.
+    pub fn persist(&self) {
+        if self.persist_path.is_none() {
+            return;
+        }
+        self.unsaved.store(true, AtomicOrdering::SeqCst);
+        let due = self.last_save.lock().map(|at| at.elapsed() >= self.save_interval).unwrap_or(true);
+        if due {
+            self.flush();
+        }
+    }
+
+    /// Save a persisted queue now if it changed since the last save
+    pub fn flush(&self) {
+        let path = match &self.persist_path {
+            Some(path) => path,
+            None => return,
+        };
+        if !self.unsaved.swap(false, AtomicOrdering::SeqCst) {
+            return;
+        }
+        *self.last_save.lock() = Some(Instant::now());
+        if let Err(e) = self.save_to(path) {
+            self.unsaved.store(true, AtomicOrdering::SeqCst);
+            warn!("Failed to save the task queue to {}: {}", path.display(), e);
         }
     }
 
     pub fn add_task(&self, task: AgentTask) {
-        self.tasks.write().push(PrioritizedTask { task });
+        {
+            let mut tasks = self.tasks.write();
+            self.push(&mut tasks, task);
+        }
+        self.persist();
+    }
+
+    /// Queue `task` unless an equivalent one (same agent type, description
+    /// and target file) is already pending. Returns whether it was queued.
+    pub fn add_task_dedup(&self, task: AgentTask) -> bool {
+        {
+            let mut tasks = self.tasks.write();
+            if self.pending.read().keys.contains_key(&PendingIndex::key(&task)) {
+                return false;
+            }
+            self.push(&mut tasks, task);
+        }
+        self.persist();
+        true
+    }
+
+    fn push(&self, tasks: &mut HashMap<AgentType, BinaryHeap<PrioritizedTask>>, mut task: AgentTask) {
+        // Drop any dependency that would close a cycle back to this task,
+        // otherwise every task in the cycle would wait forever
+        if !task.depends_on.is_empty() {
+            let graph: HashMap<String, Vec<String>> = tasks.values()
+                .flat_map(|heap| heap.iter())
+                .map(|p| (p.task.id.clone(), p.task.depends_on.clone()))
+                .collect();
+            let task_id = task.id.clone();
+            task.depends_on.retain(|dep| {
+                let cyclic = Self::reaches(&graph, dep, &task_id);
+                if cyclic {
+                    warn!("Task {} depending on {} would create a cycle; dropping dependency", task_id, dep);
+                }
+                !cyclic
+            });
+        }
+
+        self.pending.write().insert(&task);
+        self.in_flight.write().remove(&task.id);
+        self.statuses.write().insert(task.id.clone(), TaskStatus::Pending);
+        tasks.entry(task.agent_type.clone()).or_default().push(PrioritizedTask { task });
+    }
+
+    fn reaches(graph: &HashMap<String, Vec<String>>, from: &str, target: &str) -> bool {
+        let mut stack = vec![from.to_string()];
+        let mut seen = HashSet::new();
+        while let Some(id) = stack.pop() {
+            if id == target {
+                return true;
+            }
+            if seen.insert(id.clone()) {
+                if let Some(deps) = graph.get(&id) {
+                    stack.extend(deps.iter().cloned());
+                }
+            }
+        }
+        false
+    }
+
+    /// Whether every dependency of `task` has completed. A dependency the
+    /// queue doesn't know about yet is waited for. A failed dependency
+    /// blocks the task for good.
+    fn readiness(task: &AgentTask, statuses: &HashMap<String, TaskStatus>) -> Readiness {
+        let mut waiting = false;
+        for dep in &task.depends_on {
+            match statuses.get(dep) {
+                Some(TaskStatus::Completed) => {}
+                Some(TaskStatus::Failed(reason)) => {
+                    return Readiness::Blocked(format!("Dependency {} failed: {}", dep, reason));
+                }
+                None | Some(TaskStatus::Pending) | Some(TaskStatus::Running) => waiting = true,
+            }
+        }
+        if waiting { Readiness::Waiting } else { Readiness::Ready }
+    }
+
+    /// Pop the best task in `heap` whose dependencies have all completed.
+    /// Tasks still waiting are put back; tasks whose dependency failed are
+    /// taken out and added to `failed` with the reason.
+    fn pop_ready(
+        heap: &mut BinaryHeap<PrioritizedTask>,
+        statuses: &HashMap<String, TaskStatus>,
+        failed: &mut Vec<(AgentTask, String)>,
+    ) -> Option<PrioritizedTask> {
+        let mut waiting = Vec::new();
+        let mut found = None;
+        while let Some(prioritized) = heap.pop() {
+            match Self::readiness(&prioritized.task, statuses) {
+                Readiness::Ready => {
+                    found = Some(prioritized);
+                    break;
+                }
+                Readiness::Waiting => waiting.push(prioritized),
+                Readiness::Blocked(reason) => failed.push((prioritized.task, reason)),
+            }
+        }
+        heap.extend(waiting);
+        found
     }
 
+    /// Pop the best ready task, of `agent_type` if given. It counts as in
+    /// flight until its status is set to completed or failed. Queued tasks
+    /// found depending on a failed task fail too, rather than waiting forever.
     pub fn get_next_task(&self, agent_type: Option<AgentType>) -> Option<AgentTask> {
         let mut tasks = self.tasks.write();
-        let mut temp_heap = BinaryHeap::new();
-        let mut found_task = None;
+        let mut pending = self.pending.write();
+        let statuses = self.statuses.read();
+        let mut failed = Vec::new();
 
-        while let Some(prioritized) = tasks.pop() {
-            if let Some(ref filter_type) = agent_type {
-                if prioritized.task.agent_type == *filter_type {
-                    found_task = Some(prioritized.task);
-                    break;
+        let found = match agent_type {
+            Some(agent_type) => tasks.get_mut(&agent_type).and_then(|heap| Self::pop_ready(heap, &statuses, &mut failed)),
+            None => {
+                // Take the best ready task of each type, keep the overall
+                // best and put the others back
+                let mut candidates: Vec<PrioritizedTask> = tasks.values_mut()
+                    .filter_map(|heap| Self::pop_ready(heap, &statuses, &mut failed))
+                    .collect();
+                candidates.sort();
+                let best = candidates.pop();
+                for candidate in candidates {
+                    tasks.entry(candidate.task.agent_type.clone()).or_default().push(candidate);
                 }
-            } else {
-                found_task = Some(prioritized.task);
-                break;
+                best
             }
-            temp_heap.push(prioritized);
+        };
+
+        drop(statuses);
+
+        let next = found.map(|prioritized| {
+            pending.remove(&prioritized.task);
+            self.in_flight.write().insert(prioritized.task.id.clone(), prioritized.task.clone());
+            prioritized.task
+        });
+        for (task, reason) in &failed {
+            warn!("Failing task {}: {}", task.id, reason);
+            pending.remove(task);
+            self.statuses.write().insert(task.id.clone(), TaskStatus::Failed(reason.clone()));
+        }
+        drop(pending);
+        drop(tasks);
+
+        if next.is_some() || !failed.is_empty() {
+            self.persist();
+        }
+        next
+    }
+
+    /// Take a queued task out of the queue by id. It counts as failed, so
+    /// tasks depending on it fail too. Returns `None` if no queued task has
+    /// that id.
+    pub fn remove_task(&self, task_id: &str) -> Option<AgentTask> {
+        let removed = self.take_task(task_id);
+        if removed.is_some() {
+            self.persist();
         }
+        removed
+    }
 
-        // Put remaining tasks back
-        while let Some(prioritized) = temp_heap.pop() {
-            tasks.push(prioritized);
+    fn take_task(&self, task_id: &str) -> Option<AgentTask> {
+        let mut tasks = self.tasks.write();
+        let mut pending = self.pending.write();
+        if !pending.ids.contains(task_id) {
+            return None;
         }
 
-        found_task
+        for heap in tasks.values_mut() {
+            if heap.iter().any(|p| p.task.id == task_id) {
+                let mut remaining: Vec<PrioritizedTask> = std::mem::take(heap).into_vec();
+                let position = remaining.iter().position(|p| p.task.id == task_id)?;
+                let removed = remaining.swap_remove(position);
+                *heap = BinaryHeap::from(remaining);
+                pending.remove(&removed.task);
+                self.statuses.write().insert(task_id.to_string(), Self::removed());
+                return Some(removed.task);
+            }
+        }
+        None
+    }
+
+    /// Remove every queued task for `agent_type`, e.g. after its agents are
+    /// deregistered. Like `remove_task`, they count as failed. Returns the
+    /// removed tasks.
+    pub fn cancel_all_for_type(&self, agent_type: AgentType) -> Vec<AgentTask> {
+        let mut tasks = self.tasks.write();
+        let mut pending = self.pending.write();
+        let removed: Vec<AgentTask> = tasks.remove(&agent_type)
+            .map(|heap| heap.into_sorted_vec().into_iter().rev().map(|p| p.task).collect())
+            .unwrap_or_default();
+        let mut statuses = self.statuses.write();
+        for task in &removed {
+            pending.remove(task);
+            statuses.insert(task.id.clone(), Self::removed());
+        }
+        drop(statuses);
+        drop(pending);
+        drop(tasks);
+
+        if !removed.is_empty() {
+            self.persist();
+        }
+        removed
+    }
+
+    /// Status of a task taken out of the queue without running
+    fn removed() -> TaskStatus {
+        TaskStatus::Failed("Removed from the queue".to_string())
     }
 
     pub fn mark_completed(&self, task: AgentTask) {
-        self.completed_tasks.write().push(task);
+        self.completed_tasks.write().push(task.clone());
+        self.set_status(&task.id, TaskStatus::Completed);
+    }
+
+    /// Set a task's status. A completed or failed task is no longer in flight.
+    pub fn set_status(&self, task_id: &str, status: TaskStatus) {
+        if matches!(status, TaskStatus::Completed | TaskStatus::Failed(_)) {
+            self.in_flight.write().remove(task_id);
+        }
+        self.statuses.write().insert(task_id.to_string(), status);
+        self.persist();
+    }
+
+    pub fn get_task_status(&self, task_id: &str) -> Option<TaskStatus> {
+        self.statuses.read().get(task_id).cloned()
+    }
+
+    /// IDs of tasks in the same state as `status`; any `Failed` matches
+    /// every failed task regardless of message
+    pub fn get_tasks_by_status(&self, status: &TaskStatus) -> Vec<String> {
+        let mut ids: Vec<String> = self.statuses.read()
+            .iter()
+            .filter(|(_, s)| std::mem::discriminant(*s) == std::mem::discriminant(status))
+            .map(|(id, _)| id.clone())
+            .collect();
+        ids.sort();
+        ids
+    }
+
+    /// Re-enqueue a copy of a completed task under a fresh id and creation
+    /// time, e.g. after fixing the agent that ran it. Returns the new id.
+    pub fn requeue_completed(&self, task_id: &str) -> Result<String, String> {
+        let mut task = self.completed_tasks.read()
+            .iter()
+            .find(|t| t.id == task_id)
+            .cloned()
+            .ok_or_else(|| format!("Completed task {} not found", task_id))?;
+
+        task.id = Uuid::new_v4().to_string();
+        task.created_at = Utc::now();
+        let new_id = task.id.clone();
+        self.add_task(task);
+        Ok(new_id)
     }
 
     pub fn get_queue_size(&self) -> usize {
-        self.tasks.read().len()
+        self.tasks.read().values().map(|heap| heap.len()).sum()
     }
 
     pub fn get_completed_count(&self) -> usize {
         self.completed_tasks.read().len()
     }
 
+    /// Forget completed tasks, keeping the status of those a queued or in
+    /// flight task still depends on
     pub fn clear_completed(&self) {
-        self.completed_tasks.write().clear();
+        let needed: HashSet<String> = self.tasks.read().values()
+            .flat_map(|heap| heap.iter().map(|p| &p.task))
+            .chain(self.in_flight.read().values())
+            .flat_map(|task| task.depends_on.iter().cloned())
+            .collect();
+        let mut statuses = self.statuses.write();
+        for task in self.completed_tasks.write().drain(..) {
+            if !needed.contains(&task.id) {
+                statuses.remove(&task.id);
+            }
+        }
+        drop(statuses);
+        self.persist();
     }
 }
 
@@ -104,3 +546,165 @@ impl Default for TaskQueue {
     }
 }
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn task(agent_type: AgentType, priority: u8) -> AgentTask {
+        AgentTask {
+            id: Uuid::new_v4().to_string(),
+            agent_type,
+            priority,
+            description: "Test task".to_string(),
+            target_file: None,
+            target_files: Vec::new(),
+            parameters: HashMap::new(),
+            created_at: Utc::now(),
+            depends_on: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn two_task_cycle_is_broken_without_stalling_the_queue() {
+        let queue = TaskQueue::new();
+        let mut a = task(AgentType::UIAgent, 5);
+        let mut b = task(AgentType::SEOAgent, 5);
+        a.depends_on = vec![b.id.clone()];
+        b.depends_on = vec![a.id.clone()];
+        queue.add_task(a.clone());
+        queue.add_task(b.clone());
+
+        // b's edge back to a closed the cycle, so b runs first and a after it
+        let first = queue.get_next_task(None).expect("the cycle must not stall the queue");
+        assert_eq!(first.id, b.id);
+        assert!(first.depends_on.is_empty());
+        assert!(queue.get_next_task(None).is_none());
+
+        queue.mark_completed(first);
+        assert_eq!(queue.get_next_task(None).map(|t| t.id), Some(a.id));
+        assert_eq!(queue.get_queue_size(), 0);
+    }
+
+    #[test]
+    fn dependents_wait_until_their_dependency_completes() {
+        let queue = TaskQueue::new();
+        let dependency = task(AgentType::UIAgent, 5);
+        let mut dependent = task(AgentType::UIAgent, 9);
+        dependent.depends_on = vec![dependency.id.clone()];
+        queue.add_task(dependency.clone());
+        queue.add_task(dependent.clone());
+
+        let running = queue.get_next_task(None).unwrap();
+        assert_eq!(running.id, dependency.id);
+        queue.set_status(&running.id, TaskStatus::Running);
+        assert!(queue.get_next_task(None).is_none(), "a running dependency hasn't finished");
+
+        queue.mark_completed(running);
+        assert_eq!(queue.get_next_task(None).map(|t| t.id), Some(dependent.id));
+    }
+
+    #[test]
+    fn dependents_of_a_failed_task_fail() {
+        let queue = TaskQueue::new();
+        let dependency = task(AgentType::UIAgent, 5);
+        let mut dependent = task(AgentType::SEOAgent, 5);
+        dependent.depends_on = vec![dependency.id.clone()];
+        queue.add_task(dependency.clone());
+        queue.add_task(dependent.clone());
+
+        let running = queue.get_next_task(None).unwrap();
+        queue.set_status(&running.id, TaskStatus::Failed("agent error".to_string()));
+
+        assert!(queue.get_next_task(None).is_none());
+        assert_eq!(queue.get_queue_size(), 0);
+        assert!(matches!(queue.get_task_status(&dependent.id), Some(TaskStatus::Failed(_))));
+    }
+
+    #[test]
+    fn tasks_tied_on_priority_and_time_pop_in_a_fixed_order() {
+        let created_at = Utc::now();
+        let tasks: Vec<AgentTask> = ["c", "a", "d", "b"].iter()
+            .map(|id| AgentTask { id: id.to_string(), created_at, ..task(AgentType::UIAgent, 5) })
+            .collect();
+
+        let pop_order = |order: &[usize]| {
+            let queue = TaskQueue::new();
+            for &i in order {
+                queue.add_task(tasks[i].clone());
+            }
+            std::iter::from_fn(|| queue.get_next_task(None)).map(|task| task.id).collect::<Vec<_>>()
+        };
+
+        assert_eq!(pop_order(&[0, 1, 2, 3]), vec!["a", "b", "c", "d"]);
+        assert_eq!(pop_order(&[3, 2, 1, 0]), vec!["a", "b", "c", "d"]);
+    }
+
+    #[test]
+    fn a_requeued_completed_task_comes_back_with_a_new_id() {
+        let queue = TaskQueue::new();
+        queue.add_task(task(AgentType::SEOAgent, 7));
+        let done = queue.get_next_task(None).unwrap();
+        queue.mark_completed(done.clone());
+        assert_eq!(queue.get_queue_size(), 0);
+
+        let new_id = queue.requeue_completed(&done.id).unwrap();
+
+        assert_ne!(new_id, done.id);
+        let again = queue.get_next_task(None).unwrap();
+        assert_eq!(again.id, new_id);
+        assert_eq!((again.agent_type, again.priority, again.description), (done.agent_type, done.priority, done.description));
+        assert_eq!(queue.get_task_status(&done.id), Some(TaskStatus::Completed));
+        assert!(queue.requeue_completed("unknown").is_err());
+    }
+
+    #[test]
+    fn a_saved_queue_reloads_its_statuses_and_requeues_running_tasks() {
+        let path = std::env::temp_dir().join(format!("brion-queue-{}.json", Uuid::new_v4()));
+        let saved_tasks = |path: &Path| {
+            let saved: SavedQueue = serde_json::from_str(&FileOperations::read_file(path).unwrap()).unwrap();
+            saved.queued.len() + saved.in_flight.len()
+        };
+        let queue = TaskQueue::new().with_persistence(path.clone()).with_save_interval(Duration::from_secs(3600));
+        let (done, failed, running) = (task(AgentType::UIAgent, 9), task(AgentType::SEOAgent, 8), task(AgentType::UIAgent, 7));
+        let queued = task(AgentType::ContentAgent, 5);
+        let mut dependent = task(AgentType::SEOAgent, 1);
+        dependent.depends_on = vec![done.id.clone()];
+        for task in [&done, &failed, &running, &queued, &dependent] {
+            queue.add_task(task.clone());
+        }
+
+        queue.mark_completed(queue.get_next_task(None).unwrap());
+        let taken = queue.get_next_task(None).unwrap();
+        queue.set_status(&taken.id, TaskStatus::Failed("agent error".to_string()));
+        let taken = queue.get_next_task(None).unwrap();
+        queue.set_status(&taken.id, TaskStatus::Running);
+        assert_eq!(saved_tasks(&path), 1, "only the first change is saved within the interval");
+        queue.flush();
+        assert_eq!(saved_tasks(&path), 3);
+
+        let reloaded = TaskQueue::new();
+        assert_eq!(reloaded.load_from(&path).unwrap(), 3);
+        assert_eq!(reloaded.get_task_status(&done.id), Some(TaskStatus::Completed));
+        assert!(matches!(reloaded.get_task_status(&failed.id), Some(TaskStatus::Failed(_))));
+        assert_eq!(reloaded.get_task_status(&running.id), Some(TaskStatus::Pending));
+        let order: Vec<String> = std::iter::from_fn(|| reloaded.get_next_task(None)).map(|task| task.id).collect();
+        assert_eq!(order, vec![running.id, queued.id, dependent.id]);
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[test]
+    fn a_dependency_the_queue_has_not_seen_is_waited_for() {
+        let queue = TaskQueue::new();
+        let dependency = task(AgentType::UIAgent, 5);
+        let mut dependent = task(AgentType::SEOAgent, 9);
+        dependent.depends_on = vec![dependency.id.clone()];
+        queue.add_task(dependent.clone());
+
+        assert!(queue.get_next_task(None).is_none());
+        queue.add_task(dependency.clone());
+        queue.mark_completed(queue.get_next_task(None).unwrap());
+        queue.clear_completed();
+        assert_eq!(queue.get_next_task(None).map(|task| task.id), Some(dependent.id));
+    }
+}
@@ -0,0 +1,96 @@
+// OpenTelemetry Integration
+// Exports the orchestrator's tracing spans over OTLP (enabled with the `otel` feature)
+
+use opentelemetry_otlp::WithExportConfig;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+
+/// Install a global tracing subscriber that ships spans to the OTLP
+/// collector at `endpoint` (e.g. `http://localhost:4317`)
+pub fn init_otel(endpoint: &str) -> Result<(), String> {
+    let tracer = opentelemetry_otlp::new_pipeline()
+        .tracing()
+        .with_exporter(
+            opentelemetry_otlp::new_exporter()
+                .tonic()
+                .with_endpoint(endpoint),
+        )
+        .install_batch(opentelemetry_sdk::runtime::Tokio)
+        .map_err(|e| format!("Failed to install OTLP pipeline: {}", e))?;
+
+    tracing_subscriber::registry()
+        .with(tracing_opentelemetry::layer().with_tracer(tracer))
+        .try_init()
+        .map_err(|e| format!("Failed to install tracing subscriber: {}", e))
+}
+
+// Not part of a plain `cargo test`: run with `cargo test --features otel`
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agents::agent_impl::EnhancedUIAgent;
+    use crate::agents::{AgentOrchestrator, AgentTask, AgentType, ChangeEvaluator};
+    use chrono::Utc;
+    use opentelemetry::trace::TracerProvider as _;
+    use opentelemetry_sdk::export::trace::SpanData;
+    use opentelemetry_sdk::testing::trace::InMemorySpanExporterBuilder;
+    use opentelemetry_sdk::trace::TracerProvider;
+    use std::collections::HashMap;
+    use uuid::Uuid;
+
+    fn attribute(span: &SpanData, key: &str) -> Option<String> {
+        span.attributes.iter()
+            .find(|kv| kv.key.as_str() == key)
+            .map(|kv| kv.value.to_string())
+    }
+
+    #[tokio::test]
+    async fn a_task_produces_nested_spans_with_their_attributes() {
+        let exporter = InMemorySpanExporterBuilder::new().build();
+        let provider = TracerProvider::builder().with_simple_exporter(exporter.clone()).build();
+        let subscriber = tracing_subscriber::registry()
+            .with(tracing_opentelemetry::layer().with_tracer(provider.tracer("brion-test")));
+        let _default = tracing::subscriber::set_default(subscriber);
+
+        let dir = std::env::temp_dir().join(format!("brion-otel-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(dir.join("styles")).unwrap();
+        std::fs::write(dir.join("styles/main.css"), "body {\n    color: #333;\n}\n").unwrap();
+        let orchestrator = AgentOrchestrator::new(dir.clone())
+            .with_evaluator(ChangeEvaluator::new().with_threshold(0.0));
+        orchestrator.register_agent(Box::new(EnhancedUIAgent::new()));
+        let task = AgentTask {
+            id: Uuid::new_v4().to_string(),
+            agent_type: AgentType::UIAgent,
+            priority: 5,
+            description: "Test task".to_string(),
+            target_file: Some("styles/main.css".to_string()),
+            target_files: Vec::new(),
+            parameters: HashMap::new(),
+            created_at: Utc::now(),
+            depends_on: Vec::new(),
+        };
+        orchestrator.get_task_queue().add_task(task.clone());
+
+        orchestrator.run_agent_once(AgentType::UIAgent).await.unwrap();
+
+        // The simple processor may still be exporting the closing spans
+        let _ = provider.force_flush();
+        let spans = exporter.get_finished_spans().unwrap();
+        let named = |name: &str| spans.iter().filter(|span| span.name == name).collect::<Vec<_>>();
+        let (task_span, execute, evaluate) = (named("task"), named("execute"), named("evaluate"));
+        assert_eq!((task_span.len(), execute.len()), (1, 1));
+        assert!(!evaluate.is_empty());
+        assert_eq!(execute[0].parent_span_id, task_span[0].span_context.span_id());
+        assert_eq!(attribute(task_span[0], "task_id"), Some(task.id.clone()));
+        assert_eq!(attribute(task_span[0], "agent_type"), Some("UIAgent".to_string()));
+        assert_eq!(attribute(execute[0], "task_id"), Some(task.id));
+
+        let applied: Vec<Option<String>> = named("apply").iter().map(|span| attribute(span, "change_id")).collect();
+        for span in evaluate {
+            assert_eq!(span.parent_span_id, execute[0].span_context.span_id());
+            assert!(attribute(span, "score").is_some());
+            assert!(applied.contains(&attribute(span, "change_id")));
+        }
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
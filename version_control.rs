@@ -2,11 +2,36 @@
 // Tracks all modifications with full rollback capability
 
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use crate::agents::agents::AgentType;
+use crate::agents::experiments::EXPERIMENT_METADATA_KEY;
+use crate::agents::error::BrionError;
+use crate::agents::evaluator::EvaluationResult;
+use crate::agents::file_ops::{DiskIo, DurabilityPolicy, FileOperations, OsDisk};
+use crate::agents::line_diff::LineDiff;
+use std::collections::{HashMap, HashSet};
 use std::path::PathBuf;
 use chrono::{DateTime, Utc};
 use parking_lot::RwLock;
 use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use hmac::{Hmac, Mac};
+use log::warn;
+use sha2::Sha256;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Metadata key shared by changes that must be kept or rolled back together
+pub const CHANGE_GROUP_METADATA_KEY: &str = "change_group";
+
+/// Metadata key on a revert change naming the change it undoes
+pub const REVERTS_METADATA_KEY: &str = "reverts";
+
+/// Metadata key on a change that reset its file to the original content
+pub const RESET_METADATA_KEY: &str = "reset";
+
+/// Metadata key on a change a transform rewrote, whose recorded content
+/// isn't what was written to disk
+pub const REDACTED_METADATA_KEY: &str = "redacted";
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Change {
@@ -20,9 +45,51 @@ pub struct Change {
     pub after: String,
     pub metadata: HashMap<String, String>,
     pub evaluation_score: Option<f64>,
+    #[serde(default)]
+    pub signature: Option<String>, // Hex HMAC-SHA256, set when the journal has a signing key
+    #[serde(default)]
+    pub notes: Vec<ChangeNote>, // Reviewer notes, append-only
+    #[serde(default)]
+    pub protected: bool, // Hand-reviewed; never rolled back or re-evaluated automatically
+    #[serde(default)]
+    pub sequence: u64, // Position in the journal, set when first recorded; 0 in older entries
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChangeNote {
+    pub author: String,
+    pub text: String,
+    pub timestamp: DateTime<Utc>,
+}
+
+/// Lines of unchanged context kept around each diff hunk
+const DIFF_CONTEXT_LINES: usize = 3;
+
+impl Change {
+    /// Lines added and removed by the change
+    pub fn diff_stat(&self) -> (usize, usize) {
+        let before: Vec<&str> = self.before.lines().collect();
+        let after: Vec<&str> = self.after.lines().collect();
+        LineDiff::stat(&before, &after)
+    }
+
+    /// Full unified diff from `before` to `after`, one hunk per group of
+    /// nearby edits. A created or deleted file is diffed against `/dev/null`.
+    pub fn unified_diff(&self) -> String {
+        let before: Vec<&str> = self.before.lines().collect();
+        let after: Vec<&str> = self.after.lines().collect();
+
+        let created = before.is_empty() && self.change_type != ChangeType::Delete;
+        let deleted = after.is_empty() && self.change_type == ChangeType::Delete;
+        let old_name = if created { "/dev/null".to_string() } else { format!("a/{}", self.file_path) };
+        let new_name = if deleted { "/dev/null".to_string() } else { format!("b/{}", self.file_path) };
+        let mut diff = format!("--- {}\n+++ {}\n", old_name, new_name);
+        diff.push_str(&LineDiff::hunks(&before, &after, DIFF_CONTEXT_LINES));
+        diff
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum ChangeType {
     Create,
     Modify,
@@ -44,40 +111,577 @@ pub struct VersionSnapshot {
     pub description: String,
 }
 
+/// Snapshot history as kept in the storage directory
+#[derive(Serialize, Deserialize)]
+struct SnapshotHistory {
+    current_version: String,
+    versions: Vec<VersionSnapshot>,
+    #[serde(default)]
+    pruned_changes: Vec<String>, // Changes of snapshots dropped by `max_snapshots`
+}
+
+/// Hook run on every change before it is recorded. Returning a modified
+/// change lets callers redact content; returning an error rejects it.
+pub trait ChangeTransform: Send + Sync {
+    fn transform(&self, change: Change) -> Result<Change, BrionError>;
+}
+
+/// Restricts which change types each agent type may record. Agent types
+/// without an entry are unrestricted.
+#[derive(Debug, Clone, Default)]
+pub struct ChangeTypeAllowlist {
+    allowed: HashMap<String, HashSet<ChangeType>>,
+}
+
+impl ChangeTypeAllowlist {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn allow(mut self, agent_type: AgentType, change_types: Vec<ChangeType>) -> Self {
+        self.allowed.insert(format!("{:?}", agent_type), change_types.into_iter().collect());
+        self
+    }
+}
+
+impl ChangeTransform for ChangeTypeAllowlist {
+    fn transform(&self, change: Change) -> Result<Change, BrionError> {
+        match self.allowed.get(&change.agent_type) {
+            Some(allowed) if !allowed.contains(&change.change_type) => Err(BrionError::EvaluationFailed(format!(
+                "{} change {} to {} refused: {:?} is not allowed for this agent",
+                change.agent_type, change.id, change.file_path, change.change_type
+            ))),
+            _ => Ok(change),
+        }
+    }
+}
+
+/// Filters for `VersionControl::query`; unset fields match every change
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ChangeQuery {
+    pub file_path: Option<String>,
+    pub agent: Option<String>,         // Agent ID or agent type
+    pub start: Option<DateTime<Utc>>,  // Inclusive
+    pub end: Option<DateTime<Utc>>,    // Inclusive
+}
+
+impl ChangeQuery {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_file(mut self, file_path: &str) -> Self {
+        self.file_path = Some(file_path.to_string());
+        self
+    }
+
+    pub fn with_agent(mut self, agent: &str) -> Self {
+        self.agent = Some(agent.to_string());
+        self
+    }
+
+    pub fn with_range(mut self, start: DateTime<Utc>, end: DateTime<Utc>) -> Self {
+        self.start = Some(start);
+        self.end = Some(end);
+        self
+    }
+
+    pub fn matches(&self, change: &Change) -> bool {
+        self.file_path.as_ref().map(|path| &change.file_path == path).unwrap_or(true)
+            && self.agent.as_ref().map(|agent| &change.agent_id == agent || &change.agent_type == agent).unwrap_or(true)
+            && self.start.map(|start| change.timestamp >= start).unwrap_or(true)
+            && self.end.map(|end| change.timestamp <= end).unwrap_or(true)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FileDiff {
+    pub file_path: String,
+    pub from_content: Option<String>, // None if the file didn't exist yet
+    pub to_content: Option<String>,
+    pub added_lines: Vec<String>,
+    pub removed_lines: Vec<String>,
+}
+
 pub struct VersionControl {
     changes: Arc<RwLock<HashMap<String, Change>>>,
+    transforms: Arc<RwLock<Vec<Box<dyn ChangeTransform>>>>,
+    rolled_back: Arc<RwLock<HashSet<String>>>,
+    evaluations: Arc<RwLock<HashMap<String, EvaluationResult>>>, // change id -> latest evaluation
+    signing_key: Option<Vec<u8>>,
     versions: Arc<RwLock<Vec<VersionSnapshot>>>,
+    pruned_changes: Arc<RwLock<HashSet<String>>>, // Snapshotted once, in snapshots since pruned
     current_version: Arc<RwLock<String>>,
-    base_path: PathBuf,
+    version_sequence: Arc<AtomicU64>,
+    record_sequence: Arc<AtomicU64>, // Journal position of the next change recorded
+    originals: Arc<RwLock<HashMap<String, Option<String>>>>, // Each file before its first recorded change
+    unredacted: Arc<RwLock<HashMap<String, (String, String)>>>, // Real before/after of redacted changes; memory only
+    unredacted_originals: Arc<RwLock<HashMap<String, Option<String>>>>, // Real originals of redacted files; memory only
+    redacted_originals: Arc<RwLock<HashSet<String>>>, // Files whose kept original a transform rewrote
+    journal_lock: Arc<RwLock<()>>, // Shared by journal writes, exclusive while compaction swaps the journal
+    storage_dir: PathBuf,
+    max_diff_lines: Option<usize>,
+    max_snapshots: Option<usize>,
+    durability: Arc<RwLock<DurabilityPolicy>>, // For the journal, and for agents applying changes they record here
 }
 
 impl VersionControl {
     pub fn new(base_path: PathBuf) -> Self {
-        let initial_version = format!("v{}", Utc::now().timestamp());
+        let version_sequence = Arc::new(AtomicU64::new(0));
+        let initial_version = Self::version_id(&version_sequence);
         
         Self {
             changes: Arc::new(RwLock::new(HashMap::new())),
+            transforms: Arc::new(RwLock::new(Vec::new())),
+            rolled_back: Arc::new(RwLock::new(HashSet::new())),
+            evaluations: Arc::new(RwLock::new(HashMap::new())),
+            signing_key: None,
             versions: Arc::new(RwLock::new(Vec::new())),
+            pruned_changes: Arc::new(RwLock::new(HashSet::new())),
             current_version: Arc::new(RwLock::new(initial_version)),
-            base_path,
+            version_sequence,
+            record_sequence: Arc::new(AtomicU64::new(1)),
+            originals: Arc::new(RwLock::new(HashMap::new())),
+            unredacted: Arc::new(RwLock::new(HashMap::new())),
+            unredacted_originals: Arc::new(RwLock::new(HashMap::new())),
+            redacted_originals: Arc::new(RwLock::new(HashSet::new())),
+            journal_lock: Arc::new(RwLock::new(())),
+            storage_dir: base_path.join(".brion"),
+            max_diff_lines: None,
+            max_snapshots: None,
+            durability: Arc::new(RwLock::new(DurabilityPolicy::Fast)),
+        }
+    }
+
+    /// `v<millis>-<sequence>`, zero-padded so IDs sort in creation order
+    /// and stay unique within the same millisecond
+    fn version_id(sequence: &AtomicU64) -> String {
+        format!("v{:013}-{:06}", Utc::now().timestamp_millis(), sequence.fetch_add(1, Ordering::SeqCst))
+    }
+
+    /// Keep the journal under `dir` instead of `<base_path>/.brion`
+    pub fn with_storage_dir(mut self, dir: PathBuf) -> Self {
+        self.storage_dir = dir;
+        self
+    }
+
+    /// Sign every recorded change with HMAC-SHA256 under `key`
+    pub fn with_signing_key(mut self, key: Vec<u8>) -> Self {
+        self.signing_key = Some(key);
+        self
+    }
+
+    /// Cap the diff lines shown per change in rendered reports. The full
+    /// diff stays available through `Change::unified_diff`.
+    pub fn with_max_diff_lines(mut self, max_lines: usize) -> Self {
+        self.max_diff_lines = Some(max_lines);
+        self
+    }
+
+    /// Keep at most `max` snapshots, dropping the oldest as new ones are
+    /// created. Their changes stay in the journal.
+    pub fn with_max_snapshots(mut self, max: usize) -> Self {
+        self.max_snapshots = Some(max.max(1));
+        self
+    }
+
+    /// Write the journal, and have agents recording here write site files,
+    /// under `policy`
+    pub fn set_durability_policy(&self, policy: DurabilityPolicy) {
+        *self.durability.write() = policy;
+    }
+
+    pub fn durability_policy(&self) -> DurabilityPolicy {
+        *self.durability.read()
+    }
+
+    fn sign(key: &[u8], change: &Change) -> String {
+        let mut mac = HmacSha256::new_from_slice(key).expect("HMAC accepts keys of any length");
+
+        let mut metadata: Vec<(&String, &String)> = change.metadata.iter().collect();
+        metadata.sort();
+        let timestamp = change.timestamp.to_rfc3339();
+        let change_type = format!("{:?}", change.change_type);
+        let score = change.evaluation_score.map(|s| s.to_string()).unwrap_or_default();
+
+        let mut fields: Vec<&str> = vec![
+            change.id.as_str(), timestamp.as_str(), change.agent_id.as_str(),
+            change.agent_type.as_str(), change.file_path.as_str(), change_type.as_str(),
+            change.before.as_str(), change.after.as_str(), score.as_str(),
+        ];
+        for (key, value) in &metadata {
+            fields.push(key.as_str());
+            fields.push(value.as_str());
+        }
+        let note_times: Vec<String> = change.notes.iter().map(|n| n.timestamp.to_rfc3339()).collect();
+        for (note, time) in change.notes.iter().zip(&note_times) {
+            fields.push(note.author.as_str());
+            fields.push(note.text.as_str());
+            fields.push(time.as_str());
+        }
+        // Only when set, so changes signed before these existed still verify
+        if change.protected {
+            fields.push("protected");
+        }
+        let sequence = change.sequence.to_string();
+        if change.sequence != 0 {
+            fields.push(sequence.as_str());
+        }
+
+        // Length-prefix each field so boundaries can't be shifted
+        for field in fields {
+            mac.update(&(field.len() as u64).to_le_bytes());
+            mac.update(field.as_bytes());
+        }
+
+        mac.finalize().into_bytes().iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    /// IDs of changes whose signature is missing or doesn't match their
+    /// content. Always empty when no signing key is configured.
+    pub fn verify_integrity(&self) -> Vec<String> {
+        let key = match &self.signing_key {
+            Some(key) => key,
+            None => return Vec::new(),
+        };
+
+        let mut tampered: Vec<String> = self.changes.read()
+            .values()
+            .filter(|c| c.signature.as_deref() != Some(Self::sign(key, c).as_str()))
+            .map(|c| c.id.clone())
+            .collect();
+        tampered.sort();
+        tampered
+    }
+
+    pub fn add_transform(&self, transform: Box<dyn ChangeTransform>) {
+        self.transforms.write().push(transform);
+    }
+
+    pub fn record_change(&self, change: Change) -> Result<String, BrionError> {
+        self.record(change).map(|change| change.id)
+    }
+
+    /// Record `change` and return it as stored, after transforms and signing.
+    /// If a transform rewrote its content, the change is marked redacted and
+    /// the real content is kept in memory only, for `full_change`.
+    pub fn record(&self, change: Change) -> Result<Change, BrionError> {
+        let (before, after) = (change.before.clone(), change.after.clone());
+        let mut change = self.apply_transforms(change)?;
+        let redacted = change.before != before || change.after != after;
+
+        // Recording a change again, e.g. with its score, keeps its place,
+        // and a change redacted once stays marked as such
+        let mut marked = redacted;
+        change.sequence = match self.changes.read().get(&change.id) {
+            Some(existing) => {
+                marked |= existing.metadata.contains_key(REDACTED_METADATA_KEY);
+                existing.sequence
+            }
+            None => self.record_sequence.fetch_add(1, Ordering::SeqCst),
+        };
+        if marked {
+            change.metadata.insert(REDACTED_METADATA_KEY.to_string(), "true".to_string());
+        }
+        if let Some(ref key) = self.signing_key {
+            change.signature = Some(Self::sign(key, &change));
+        }
+
+        // Persist first so a change is never in memory without being on disk
+        let full = Change { before, after, ..change.clone() };
+        self.keep_original(&change, &full)?;
+        self.persist_change(&change)?;
+
+        if redacted {
+            self.unredacted.write().insert(change.id.clone(), (full.before, full.after));
+        }
+        self.changes.write().insert(change.id.clone(), change.clone());
+        Ok(change)
+    }
+
+    /// A recorded change with the content that was actually written, for
+    /// applying or rolling it back. A redacted change only has that content
+    /// in memory, so once the journal is reloaded it can't be written back.
+    pub fn full_change(&self, change_id: &str) -> Result<Change, BrionError> {
+        let mut change = self.get_change(change_id)
+            .ok_or_else(|| BrionError::ChangeNotFound(change_id.to_string()))?;
+        if !change.metadata.contains_key(REDACTED_METADATA_KEY) {
+            return Ok(change);
+        }
+        match self.unredacted.read().get(change_id) {
+            Some((before, after)) => {
+                change.before = before.clone();
+                change.after = after.clone();
+                Ok(change)
+            }
+            None => Err(BrionError::Other(format!(
+                "Change {} was recorded redacted and its full content is gone; it can't be written back", change_id
+            ))),
+        }
+    }
+
+    /// `change` as every registered transform leaves it, without recording it
+    pub fn apply_transforms(&self, change: Change) -> Result<Change, BrionError> {
+        let mut change = change;
+        for transform in self.transforms.read().iter() {
+            change = transform.transform(change)?;
+        }
+        Ok(change)
+    }
+
+    fn changes_dir(&self) -> PathBuf {
+        self.storage_dir.join("changes")
+    }
+
+    /// Where compaction writes the new journal before swapping it in
+    fn compacted_dir(&self) -> PathBuf {
+        self.storage_dir.join("changes.compacted")
+    }
+
+    /// Where compaction moves the old journal during the swap
+    fn superseded_dir(&self) -> PathBuf {
+        self.storage_dir.join("changes.superseded")
+    }
+
+    fn rolled_back_path(&self) -> PathBuf {
+        self.storage_dir.join("rolled_back.json")
+    }
+
+    fn evaluations_dir(&self) -> PathBuf {
+        self.storage_dir.join("evaluations")
+    }
+
+    fn versions_path(&self) -> PathBuf {
+        self.storage_dir.join("versions.json")
+    }
+
+    fn originals_path(&self) -> PathBuf {
+        self.storage_dir.join("originals.json")
+    }
+
+    fn redacted_originals_path(&self) -> PathBuf {
+        self.storage_dir.join("redacted_originals.json")
+    }
+
+    /// Journal file for `id` under `dir`. IDs become file names, so only
+    /// letters, digits, `-` and `_` are accepted; anything else could name
+    /// a path outside the journal.
+    fn entry_path(dir: PathBuf, id: &str) -> Result<PathBuf, BrionError> {
+        if id.is_empty() || !id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+            return Err(BrionError::Other(format!("Invalid change ID for the journal: {:?}", id)));
+        }
+        Ok(dir.join(format!("{}.json", id)))
+    }
+
+    fn persist_change(&self, change: &Change) -> Result<(), BrionError> {
+        let json = serde_json::to_string_pretty(change)?;
+        let _journal = self.journal_lock.read();
+        FileOperations::write_file(&Self::entry_path(self.changes_dir(), &change.id)?, &json, self.durability_policy())
+    }
+
+    fn persist_versions(&self, versions: &[VersionSnapshot], pruned: &HashSet<String>, current_version: &str) -> Result<(), BrionError> {
+        let mut pruned_changes: Vec<String> = pruned.iter().cloned().collect();
+        pruned_changes.sort();
+        let history = SnapshotHistory {
+            current_version: current_version.to_string(),
+            versions: versions.to_vec(),
+            pruned_changes,
+        };
+        FileOperations::write_file(&self.versions_path(), &serde_json::to_string_pretty(&history)?, self.durability_policy())
+    }
+
+    /// Back up what `change`'s file held before agents first touched it,
+    /// so the original outlives the changes it could be replayed from.
+    /// `full` is the change before transforms; if they rewrote the original,
+    /// the real one is kept in memory only and the file marked redacted.
+    fn keep_original(&self, change: &Change, full: &Change) -> Result<(), BrionError> {
+        let mut originals = self.originals.write();
+        if originals.contains_key(&change.file_path)
+            || self.changes.read().values().any(|c| c.file_path == change.file_path)
+        {
+            return Ok(());
         }
+        let original = Self::content_before(change);
+        let full_original = Self::content_before(full);
+        if full_original != original {
+            self.unredacted_originals.write().insert(change.file_path.clone(), full_original);
+            let mut redacted = self.redacted_originals.write();
+            redacted.insert(change.file_path.clone());
+            let files: std::collections::BTreeSet<&String> = redacted.iter().collect();
+            FileOperations::write_file(&self.redacted_originals_path(), &serde_json::to_string_pretty(&files)?, self.durability_policy())?;
+        }
+        originals.insert(change.file_path.clone(), original);
+
+        let sorted: std::collections::BTreeMap<&String, &Option<String>> = originals.iter().collect();
+        FileOperations::write_file(&self.originals_path(), &serde_json::to_string_pretty(&sorted)?, self.durability_policy())
     }
 
-    pub fn record_change(&self, change: Change) -> String {
-        let change_id = change.id.clone();
-        self.changes.write().insert(change_id.clone(), change);
-        change_id
+    fn persist_rolled_back(&self, rolled_back: &HashSet<String>) -> Result<(), BrionError> {
+        let mut ids: Vec<&String> = rolled_back.iter().collect();
+        ids.sort();
+        let json = serde_json::to_string_pretty(&ids)?;
+        FileOperations::write_file(&self.rolled_back_path(), &json, self.durability_policy())
+    }
+
+    /// Repopulate the journal and snapshot history from the storage
+    /// directory. Returns the
+    /// number of changes loaded; a missing directory loads nothing.
+    pub fn load_from_disk(&self) -> Result<usize, BrionError> {
+        self.finish_compaction()?;
+        let dir = self.changes_dir();
+        if !dir.exists() {
+            return Ok(0);
+        }
+
+        let entries = std::fs::read_dir(&dir).map_err(|e| BrionError::io_at(&dir, e))?;
+        let mut loaded = HashMap::new();
+        for entry in entries.flatten() {
+            let path = entry.path();
+            // Leftover temp files are writes that never completed
+            if path.extension().and_then(|e| e.to_str()) != Some("json") {
+                continue;
+            }
+            let json = FileOperations::read_file(&path)?;
+            let change: Change = serde_json::from_str(&json)
+                .map_err(|e| BrionError::Parse(format!("Journal entry {}: {}", path.display(), e)))?;
+            loaded.insert(change.id.clone(), change);
+        }
+
+        let evaluations_dir = self.evaluations_dir();
+        if evaluations_dir.exists() {
+            let entries = std::fs::read_dir(&evaluations_dir).map_err(|e| BrionError::io_at(&evaluations_dir, e))?;
+            let mut evaluations = self.evaluations.write();
+            for entry in entries.flatten() {
+                let path = entry.path();
+                if path.extension().and_then(|e| e.to_str()) != Some("json") {
+                    continue;
+                }
+                let evaluation: EvaluationResult = serde_json::from_str(&FileOperations::read_file(&path)?)
+                    .map_err(|e| BrionError::Parse(format!("Evaluation {}: {}", path.display(), e)))?;
+                evaluations.insert(evaluation.change_id.clone(), evaluation);
+            }
+        }
+
+        let originals_path = self.originals_path();
+        if originals_path.exists() {
+            let originals: HashMap<String, Option<String>> = serde_json::from_str(&FileOperations::read_file(&originals_path)?)
+                .map_err(|e| BrionError::Parse(format!("{}: {}", originals_path.display(), e)))?;
+            self.originals.write().extend(originals);
+        }
+
+        let redacted_originals_path = self.redacted_originals_path();
+        if redacted_originals_path.exists() {
+            let files: Vec<String> = serde_json::from_str(&FileOperations::read_file(&redacted_originals_path)?)
+                .map_err(|e| BrionError::Parse(format!("{}: {}", redacted_originals_path.display(), e)))?;
+            self.redacted_originals.write().extend(files);
+        }
+
+        let rolled_back_path = self.rolled_back_path();
+        if rolled_back_path.exists() {
+            let ids: Vec<String> = serde_json::from_str(&FileOperations::read_file(&rolled_back_path)?)
+                .map_err(|e| BrionError::Parse(format!("{}: {}", rolled_back_path.display(), e)))?;
+            self.rolled_back.write().extend(ids);
+        }
+
+        let versions_path = self.versions_path();
+        if versions_path.exists() {
+            let history: SnapshotHistory = serde_json::from_str(&FileOperations::read_file(&versions_path)?)
+                .map_err(|e| BrionError::Parse(format!("{}: {}", versions_path.display(), e)))?;
+            let mut versions = self.versions.write();
+            *versions = history.versions;
+            *self.pruned_changes.write() = history.pruned_changes.into_iter().collect();
+            *self.current_version.write() = history.current_version;
+        }
+
+        let count = loaded.len();
+        let next = loaded.values().map(|c| c.sequence + 1).max().unwrap_or(1);
+        self.record_sequence.fetch_max(next, Ordering::SeqCst);
+        self.changes.write().extend(loaded);
+        Ok(count)
+    }
+
+    /// Append a reviewer note to a change's audit trail
+    pub fn add_note(&self, change_id: &str, author: &str, text: &str) -> Result<(), BrionError> {
+        self.update_change(change_id, |change| {
+            change.notes.push(ChangeNote {
+                author: author.to_string(),
+                text: text.to_string(),
+                timestamp: Utc::now(),
+            });
+        })
+    }
+
+    /// Mark a change as hand-reviewed so the orchestrator never rolls it
+    /// back or re-evaluates it on its own
+    pub fn protect_change(&self, change_id: &str) -> Result<(), BrionError> {
+        self.update_change(change_id, |change| change.protected = true)
+    }
+
+    pub fn is_protected(&self, change_id: &str) -> bool {
+        self.changes.read().get(change_id).map(|c| c.protected).unwrap_or(false)
+    }
+
+    /// Edit a recorded change in place, re-signing and persisting it
+    fn update_change<F: FnOnce(&mut Change)>(&self, change_id: &str, edit: F) -> Result<(), BrionError> {
+        let mut changes = self.changes.write();
+        let change = changes.get_mut(change_id)
+            .ok_or_else(|| BrionError::ChangeNotFound(change_id.to_string()))?;
+
+        let mut updated = change.clone();
+        edit(&mut updated);
+        if let Some(ref key) = self.signing_key {
+            updated.signature = Some(Self::sign(key, &updated));
+        }
+
+        self.persist_change(&updated)?;
+        *change = updated;
+        Ok(())
     }
 
     pub fn get_change(&self, change_id: &str) -> Option<Change> {
         self.changes.read().get(change_id).cloned()
     }
 
+    /// Keep the full evaluation of a change, issues and recommendations
+    /// included, replacing any earlier one for the same change
+    pub fn record_evaluation(&self, evaluation: EvaluationResult) -> Result<(), BrionError> {
+        let json = serde_json::to_string_pretty(&evaluation)?;
+        FileOperations::write_file(&Self::entry_path(self.evaluations_dir(), &evaluation.change_id)?, &json, self.durability_policy())?;
+        self.evaluations.write().insert(evaluation.change_id.clone(), evaluation);
+        Ok(())
+    }
+
+    pub fn get_evaluation(&self, change_id: &str) -> Option<EvaluationResult> {
+        self.evaluations.read().get(change_id).cloned()
+    }
+
+    /// Evaluated changes whose overall score is below `threshold`, lowest
+    /// first, each with its evaluation
+    pub fn get_low_scoring_changes(&self, threshold: f64) -> Vec<(Change, EvaluationResult)> {
+        let changes = self.changes.read();
+        let mut low: Vec<(Change, EvaluationResult)> = self.evaluations.read()
+            .values()
+            .filter(|evaluation| evaluation.overall_score < threshold)
+            .filter_map(|evaluation| changes.get(&evaluation.change_id).map(|c| (c.clone(), evaluation.clone())))
+            .collect();
+        low.sort_by(|a, b| a.1.overall_score.total_cmp(&b.1.overall_score));
+        low
+    }
+
     pub fn create_snapshot(&self, description: String) -> String {
-        let version_id = format!("v{}", Utc::now().timestamp_millis());
+        self.create_snapshot_for(description, |_| true)
+    }
+
+    /// Snapshot only the qualifying changes whose file path matches `file_filter`
+    pub fn create_snapshot_for<F>(&self, description: String, file_filter: F) -> String
+    where
+        F: Fn(&str) -> bool,
+    {
+        let version_id = Self::version_id(&self.version_sequence);
         let changes: Vec<String> = self.changes.read()
             .values()
             .filter(|c| c.evaluation_score.is_none() || c.evaluation_score.unwrap() > 0.5)
+            .filter(|c| file_filter(&c.file_path))
             .map(|c| c.id.clone())
             .collect();
         
@@ -89,16 +693,55 @@ impl VersionControl {
             description,
         };
         
-        self.versions.write().push(snapshot);
+        // Update both under the versions lock so readers never see a
+        // current version that isn't in the history yet
+        let mut versions = self.versions.write();
+        let mut pruned = self.pruned_changes.write();
+        versions.push(snapshot);
+        if let Some(max) = self.max_snapshots {
+            let excess = versions.len().saturating_sub(max);
+            // Remembered so their changes don't count as never snapshotted
+            for dropped in versions.drain(..excess) {
+                pruned.extend(dropped.changes);
+            }
+        }
         *self.current_version.write() = version_id.clone();
+        // Persisted under the versions lock so concurrent snapshots can't
+        // leave an older history on disk
+        if let Err(e) = self.persist_versions(&versions, &pruned, &version_id) {
+            warn!("Failed to persist snapshot {}: {}", version_id, e);
+        }
         version_id
     }
 
-    pub fn rollback_to_version(&self, version_id: &str) -> Result<Vec<Change>, String> {
+    /// Snapshot described by `template`, with `{agent_type}`,
+    /// `{change_count}`, `{file_count}` and `{files}` filled from `change_ids`
+    pub fn create_templated_snapshot(&self, template: &str, change_ids: &[String]) -> String {
+        let changes: Vec<Change> = change_ids.iter()
+            .filter_map(|id| self.get_change(id))
+            .collect();
+
+        let mut agent_types: Vec<&str> = changes.iter().map(|c| c.agent_type.as_str()).collect();
+        agent_types.sort();
+        agent_types.dedup();
+        let mut files: Vec<&str> = changes.iter().map(|c| c.file_path.as_str()).collect();
+        files.sort();
+        files.dedup();
+
+        let description = template
+            .replace("{agent_type}", &agent_types.join(", "))
+            .replace("{change_count}", &changes.len().to_string())
+            .replace("{file_count}", &files.len().to_string())
+            .replace("{files}", &files.join(", "));
+
+        self.create_snapshot(description)
+    }
+
+    pub fn rollback_to_version(&self, version_id: &str) -> Result<Vec<Change>, BrionError> {
         let versions = self.versions.read();
         let version = versions.iter()
             .find(|v| v.version_id == version_id)
-            .ok_or_else(|| format!("Version {} not found", version_id))?;
+            .ok_or_else(|| BrionError::VersionNotFound(version_id.to_string()))?;
         
         let changes_to_rollback: Vec<Change> = version.changes.iter()
             .filter_map(|change_id| self.get_change(change_id))
@@ -107,25 +750,470 @@ impl VersionControl {
         Ok(changes_to_rollback)
     }
 
-    pub fn rollback_change(&self, change_id: &str) -> Result<Change, String> {
+    pub fn rollback_change(&self, change_id: &str) -> Result<Change, BrionError> {
         let change = self.changes.read()
             .get(change_id)
             .cloned()
-            .ok_or_else(|| format!("Change {} not found", change_id))?;
+            .ok_or_else(|| BrionError::ChangeNotFound(change_id.to_string()))?;
         
+        let mut rolled_back = self.rolled_back.write();
+        rolled_back.insert(change_id.to_string());
+        self.persist_rolled_back(&rolled_back)?;
         Ok(change)
     }
 
+    /// The inverse of `change_id` as a change of its own: `before` and
+    /// `after` swapped and linked back through `REVERTS_METADATA_KEY`.
+    /// Edits revert as `Modify`; a created file is reverted by a `Delete`
+    /// and a deleted one by a `Create`. Nothing is recorded or applied. The
+    /// revert carries the full content, so recording it may redact it again.
+    pub fn create_revert(&self, change_id: &str) -> Result<Change, BrionError> {
+        let original = self.full_change(change_id)?;
+
+        let change_type = match original.change_type {
+            ChangeType::Create | ChangeType::AddImage | ChangeType::AddModule => ChangeType::Delete,
+            ChangeType::Delete => ChangeType::Create,
+            _ => ChangeType::Modify,
+        };
+        let mut revert = FileOperations::create_change(
+            "version-control",
+            "VersionControl",
+            original.file_path,
+            change_type,
+            original.after,
+            original.before,
+        );
+        revert.metadata.insert(REVERTS_METADATA_KEY.to_string(), change_id.to_string());
+        Ok(revert)
+    }
+
+    /// Recorded reverts whose original isn't marked rolled back yet, left
+    /// behind when a rollback was interrupted
+    pub fn pending_reverts(&self) -> Vec<Change> {
+        let rolled_back = self.rolled_back.read();
+        let mut reverts: Vec<Change> = self.changes.read()
+            .values()
+            .filter(|c| !rolled_back.contains(&c.id))
+            .filter(|c| c.metadata.get(REVERTS_METADATA_KEY).map(|id| !rolled_back.contains(id)).unwrap_or(false))
+            .cloned()
+            .collect();
+        reverts.sort_by(Self::journal_order);
+        reverts
+    }
+
+    pub fn is_rolled_back(&self, change_id: &str) -> bool {
+        self.rolled_back.read().contains(change_id)
+    }
+
+    /// Replay every kept change to `file_path` in order and return the
+    /// content the journal says the file should have, independent of disk
+    pub fn current_content(&self, file_path: &str) -> Option<String> {
+        self.content_at(file_path, None)
+    }
+
+    /// Like `current_content`, but only replaying changes made at or
+    /// before `until`. Replay starts from the content before the file's
+    /// first recorded change, so a file whose changes were all rolled back
+    /// comes out as it originally was, revert recorded or not.
+    pub fn content_at(&self, file_path: &str, until: Option<DateTime<Utc>>) -> Option<String> {
+        let rolled_back = self.rolled_back.read();
+        let mut history: Vec<Change> = self.changes.read()
+            .values()
+            .filter(|c| c.file_path == file_path)
+            .cloned()
+            .collect();
+        history.sort_by(Self::journal_order);
+
+        let original = history.first().and_then(Self::content_before);
+        history.into_iter()
+            .filter(|c| !rolled_back.contains(&c.id))
+            .filter(|c| until.map(|t| c.timestamp <= t).unwrap_or(true))
+            .fold(original, |_, change| match change.change_type {
+                ChangeType::Delete => None,
+                _ => Some(change.after),
+            })
+    }
+
+    /// The order changes were recorded in. Entries from before journal
+    /// positions were kept come first, by timestamp.
+    fn journal_order(a: &Change, b: &Change) -> std::cmp::Ordering {
+        (a.sequence, a.timestamp).cmp(&(b.sequence, b.timestamp))
+    }
+
+    /// Whether `change` only puts back content an earlier change replaced
+    fn is_restoration(change: &Change) -> bool {
+        change.metadata.contains_key(REVERTS_METADATA_KEY) || change.metadata.contains_key(RESET_METADATA_KEY)
+    }
+
+    /// What the file held before `change`, or `None` if the change created it
+    fn content_before(change: &Change) -> Option<String> {
+        match change.change_type {
+            ChangeType::Create | ChangeType::AddImage | ChangeType::AddModule => None,
+            _ => Some(change.before.clone()),
+        }
+    }
+
+    /// Drop superseded changes, keeping the latest kept change per file
+    /// (which carries the full current content), the first change of a file
+    /// whose changes were all rolled back (which carries what it went back
+    /// to), plus any change referenced by a snapshot. The retained entries
+    /// are written to a new journal directory, synced, and renamed into
+    /// place, so a crash leaves either the old journal or the new one.
+    /// Returns the number of changes removed.
+    pub fn compact(&self) -> Result<usize, BrionError> {
+        let versions = self.versions.read();
+        let mut rolled_back = self.rolled_back.write();
+        let mut changes = self.changes.write();
+
+        let mut by_file: HashMap<&str, Vec<&Change>> = HashMap::new();
+        for change in changes.values() {
+            by_file.entry(change.file_path.as_str()).or_default().push(change);
+        }
+        let mut retained: HashSet<String> = HashSet::new();
+        for history in by_file.values() {
+            let latest_kept = history.iter()
+                .filter(|c| !rolled_back.contains(&c.id))
+                .max_by(|a, b| Self::journal_order(a, b));
+            let keep = latest_kept.or_else(|| history.iter().min_by(|a, b| Self::journal_order(a, b)));
+            retained.extend(keep.map(|c| c.id.clone()));
+        }
+        for version in versions.iter() {
+            retained.extend(version.changes.iter().cloned());
+        }
+
+        let dir = self.changes_dir();
+        let compacted = self.compacted_dir();
+        let superseded = self.superseded_dir();
+        {
+            let _journal = self.journal_lock.write();
+            std::fs::create_dir_all(&dir).map_err(|e| BrionError::io_at(&dir, e))?;
+            // Left behind by a compaction that crashed before its swap
+            if compacted.exists() {
+                std::fs::remove_dir_all(&compacted).map_err(|e| BrionError::io_at(&compacted, e))?;
+            }
+            std::fs::create_dir_all(&compacted).map_err(|e| BrionError::io_at(&compacted, e))?;
+            for change in changes.values().filter(|c| retained.contains(&c.id)) {
+                let json = serde_json::to_string_pretty(change)?;
+                FileOperations::write_file(&Self::entry_path(compacted.clone(), &change.id)?, &json, DurabilityPolicy::Strict)?;
+            }
+
+            std::fs::rename(&dir, &superseded).map_err(|e| BrionError::io_at(&dir, e))?;
+            std::fs::rename(&compacted, &dir).map_err(|e| BrionError::io_at(&compacted, e))?;
+            OsDisk.sync_dir(&self.storage_dir).map_err(|e| BrionError::io_at(&self.storage_dir, e))?;
+            if let Err(e) = std::fs::remove_dir_all(&superseded) {
+                warn!("Failed to remove the superseded journal {}: {}", superseded.display(), e);
+            }
+        }
+
+        let before = changes.len();
+        let removed: Vec<String> = changes.keys()
+            .filter(|id| !retained.contains(*id))
+            .cloned()
+            .collect();
+        changes.retain(|id, _| retained.contains(id));
+        rolled_back.retain(|id| changes.contains_key(id));
+
+        // The new journal is in place, so a crash from here on only leaves
+        // IDs of removed changes listed, which load harmlessly
+        if let Err(e) = self.persist_rolled_back(&rolled_back) {
+            warn!("Failed to persist rolled back changes after compaction: {}", e);
+        }
+        let mut evaluations = self.evaluations.write();
+        for id in removed {
+            evaluations.remove(&id);
+            let path = self.evaluations_dir().join(format!("{}.json", id));
+            if let Err(e) = std::fs::remove_file(&path) {
+                if e.kind() != std::io::ErrorKind::NotFound {
+                    warn!("Failed to remove compacted evaluation {}: {}", path.display(), e);
+                }
+            }
+        }
+
+        Ok(before - changes.len())
+    }
+
+    /// Settle a compaction a crash interrupted. A new journal that was
+    /// already swapped halfway is moved into place; one written before the
+    /// old journal was moved aside may be incomplete and is discarded.
+    fn finish_compaction(&self) -> Result<(), BrionError> {
+        let dir = self.changes_dir();
+        let compacted = self.compacted_dir();
+        let superseded = self.superseded_dir();
+        if compacted.exists() {
+            if dir.exists() {
+                std::fs::remove_dir_all(&compacted).map_err(|e| BrionError::io_at(&compacted, e))?;
+            } else {
+                std::fs::rename(&compacted, &dir).map_err(|e| BrionError::io_at(&compacted, e))?;
+            }
+        }
+        if superseded.exists() && dir.exists() {
+            std::fs::remove_dir_all(&superseded).map_err(|e| BrionError::io_at(&superseded, e))?;
+        }
+        Ok(())
+    }
+
+    /// Net per-file difference between two snapshots, reconstructing each
+    /// file's content as of both snapshot times
+    pub fn diff_versions(&self, from: &str, to: &str) -> Result<Vec<FileDiff>, BrionError> {
+        let snapshot_time = |version_id: &str| {
+            self.versions.read()
+                .iter()
+                .find(|v| v.version_id == version_id)
+                .map(|v| v.timestamp)
+                .ok_or_else(|| BrionError::VersionNotFound(version_id.to_string()))
+        };
+        let from_time = snapshot_time(from)?;
+        let to_time = snapshot_time(to)?;
+
+        let mut diffs = Vec::new();
+        for file_path in self.tracked_files() {
+            let from_content = self.content_at(&file_path, Some(from_time));
+            let to_content = self.content_at(&file_path, Some(to_time));
+            if from_content == to_content {
+                continue;
+            }
+
+            let (added_lines, removed_lines) = Self::line_delta(
+                from_content.as_deref().unwrap_or(""),
+                to_content.as_deref().unwrap_or(""),
+            );
+            diffs.push(FileDiff {
+                file_path,
+                from_content,
+                to_content,
+                added_lines,
+                removed_lines,
+            });
+        }
+
+        Ok(diffs)
+    }
+
+    /// Lines only in `after` and lines only in `before`, counting duplicates
+    pub fn line_delta(before: &str, after: &str) -> (Vec<String>, Vec<String>) {
+        let mut remaining: HashMap<&str, usize> = HashMap::new();
+        for line in before.lines() {
+            *remaining.entry(line).or_insert(0) += 1;
+        }
+
+        let mut added = Vec::new();
+        for line in after.lines() {
+            match remaining.get_mut(line) {
+                Some(count) if *count > 0 => *count -= 1,
+                _ => added.push(line.to_string()),
+            }
+        }
+
+        let mut removed = Vec::new();
+        for line in before.lines() {
+            if let Some(count) = remaining.get_mut(line) {
+                if *count > 0 {
+                    *count -= 1;
+                    removed.push(line.to_string());
+                }
+            }
+        }
+
+        (added, removed)
+    }
+
+    /// Number of recorded changes per file
+    pub fn change_frequency(&self) -> HashMap<String, usize> {
+        let mut frequency = HashMap::new();
+        for change in self.changes.read().values() {
+            *frequency.entry(change.file_path.clone()).or_insert(0) += 1;
+        }
+        frequency
+    }
+
+    /// Per-file change counts grouped into `bucket`-sized windows aligned to
+    /// the Unix epoch, oldest first. Empty windows are omitted.
+    pub fn change_frequency_over_time(&self, bucket: chrono::Duration) -> Vec<(DateTime<Utc>, HashMap<String, usize>)> {
+        let bucket_ms = bucket.num_milliseconds().max(1);
+        let mut buckets: HashMap<i64, HashMap<String, usize>> = HashMap::new();
+
+        for change in self.changes.read().values() {
+            let start = change.timestamp.timestamp_millis().div_euclid(bucket_ms) * bucket_ms;
+            *buckets.entry(start)
+                .or_default()
+                .entry(change.file_path.clone())
+                .or_insert(0) += 1;
+        }
+
+        let mut series: Vec<(DateTime<Utc>, HashMap<String, usize>)> = buckets.into_iter()
+            .filter_map(|(start, counts)| {
+                chrono::TimeZone::timestamp_millis_opt(&Utc, start).single().map(|t| (t, counts))
+            })
+            .collect();
+        series.sort_by_key(|(start, _)| *start);
+        series
+    }
+
+    /// Every file path with at least one recorded change
+    pub fn tracked_files(&self) -> Vec<String> {
+        let mut files: Vec<String> = self.changes.read()
+            .values()
+            .map(|c| c.file_path.clone())
+            .collect::<HashSet<_>>()
+            .into_iter()
+            .collect();
+        files.sort();
+        files
+    }
+
+    /// Content of every tracked file before agents first touched it, or
+    /// `None` if an agent created the file. Taken from the backup kept when
+    /// the file was first recorded, which outlives compaction, and for
+    /// journals older than the backup from the file's first change in
+    /// journal order, reverts and resets aside.
+    pub fn original_contents(&self) -> HashMap<String, Option<String>> {
+        let mut originals = self.originals.read().clone();
+        let mut first: HashMap<String, Change> = HashMap::new();
+        for change in self.changes.read().values().filter(|c| !Self::is_restoration(c)) {
+            let is_first = first.get(&change.file_path)
+                .map(|f| Self::journal_order(change, f).is_lt())
+                .unwrap_or(true);
+            if is_first {
+                first.insert(change.file_path.clone(), change.clone());
+            }
+        }
+
+        for (file, change) in first {
+            originals.entry(file).or_insert_with(|| Self::content_before(&change));
+        }
+        originals
+    }
+
+    /// `original_contents` with the real content of originals a transform
+    /// redacted, for writing back. Fails if any of those didn't survive a
+    /// reload.
+    pub fn restorable_originals(&self) -> Result<HashMap<String, Option<String>>, BrionError> {
+        let mut originals = self.original_contents();
+        let full = self.unredacted_originals.read();
+        for (file, original) in originals.iter_mut() {
+            if self.redacted_originals.read().contains(file) {
+                *original = full.get(file).cloned().ok_or_else(|| BrionError::Other(format!(
+                    "The original of {} was recorded redacted and its full content is gone; it can't be restored", file
+                )))?;
+            }
+        }
+        Ok(originals)
+    }
+
+    /// Changes an agent made behind the named experiment flag
+    pub fn changes_for_experiment(&self, name: &str) -> Vec<Change> {
+        self.changes.read()
+            .values()
+            .filter(|c| c.metadata.get(EXPERIMENT_METADATA_KEY).map(|n| n == name).unwrap_or(false))
+            .cloned()
+            .collect()
+    }
+
+    /// Recorded changes that haven't been scored or rolled back, oldest first
+    pub fn unevaluated_changes(&self) -> Vec<Change> {
+        let rolled_back = self.rolled_back.read();
+        let mut changes: Vec<Change> = self.changes.read()
+            .values()
+            .filter(|c| c.evaluation_score.is_none() && !rolled_back.contains(&c.id))
+            .filter(|c| !Self::is_restoration(c)) // Reverts and resets aren't up for review
+            .cloned()
+            .collect();
+        changes.sort_by(Self::journal_order);
+        changes
+    }
+
+    /// Kept changes not referenced by any snapshot, including snapshots
+    /// since pruned
+    pub fn unsnapshotted_changes(&self) -> Vec<String> {
+        let mut snapshotted: HashSet<String> = self.versions.read()
+            .iter()
+            .flat_map(|v| v.changes.iter().cloned())
+            .collect();
+        snapshotted.extend(self.pruned_changes.read().iter().cloned());
+        let rolled_back = self.rolled_back.read();
+        self.changes.read()
+            .keys()
+            .filter(|id| !snapshotted.contains(*id) && !rolled_back.contains(*id))
+            .cloned()
+            .collect()
+    }
+
     pub fn get_all_changes(&self) -> Vec<Change> {
         self.changes.read().values().cloned().collect()
     }
 
+    /// Changes matching every filter in `query`, oldest first
+    pub fn query(&self, query: &ChangeQuery) -> Vec<Change> {
+        let mut changes: Vec<Change> = self.changes.read()
+            .values()
+            .filter(|c| query.matches(c))
+            .cloned()
+            .collect();
+        changes.sort_by(Self::journal_order);
+        changes
+    }
+
+    pub fn get_changes_for_file(&self, file_path: &str) -> Vec<Change> {
+        self.query(&ChangeQuery::new().with_file(file_path))
+    }
+
+    /// Changes made by the agent with this ID, or by any agent of this type
+    pub fn get_changes_by_agent(&self, agent: &str) -> Vec<Change> {
+        self.query(&ChangeQuery::new().with_agent(agent))
+    }
+
+    pub fn get_changes_between(&self, start: DateTime<Utc>, end: DateTime<Utc>) -> Vec<Change> {
+        self.query(&ChangeQuery::new().with_range(start, end))
+    }
+
     pub fn get_recent_changes(&self, limit: usize) -> Vec<Change> {
         let mut changes: Vec<Change> = self.changes.read().values().cloned().collect();
-        changes.sort_by(|a, b| b.timestamp.cmp(&a.timestamp));
+        changes.sort_by_key(|c| std::cmp::Reverse(c.timestamp));
         changes.into_iter().take(limit).collect()
     }
 
+    /// Human-readable report of the `limit` most recent changes with their
+    /// diffs, truncated to `max_diff_lines` when set
+    pub fn render_report(&self, limit: usize) -> String {
+        let rolled_back = self.rolled_back.read().clone();
+        let mut report = String::new();
+
+        for change in self.get_recent_changes(limit) {
+            let score = change.evaluation_score
+                .map(|s| format!("{:.2}", s))
+                .unwrap_or_else(|| "unscored".to_string());
+            let status = if rolled_back.contains(&change.id) { " (rolled back)" } else { "" };
+            report.push_str(&format!("Change {} by {} [{:?}] score {}{}\n",
+                change.id, change.agent_id, change.change_type, score, status));
+            for note in &change.notes {
+                report.push_str(&format!("  Note from {} at {}: {}\n",
+                    note.author, note.timestamp.to_rfc3339(), note.text));
+            }
+
+            let diff = change.unified_diff();
+            match self.max_diff_lines {
+                Some(max) => report.push_str(&Self::truncate_diff(&diff, max)),
+                None => report.push_str(&diff),
+            }
+            report.push('\n');
+        }
+
+        report
+    }
+
+    fn truncate_diff(diff: &str, max_lines: usize) -> String {
+        let lines: Vec<&str> = diff.lines().collect();
+        if lines.len() <= max_lines {
+            return diff.to_string();
+        }
+
+        let mut truncated: String = lines[..max_lines].iter()
+            .map(|line| format!("{}\n", line))
+            .collect();
+        truncated.push_str(&format!("... {} more lines ...\n", lines.len() - max_lines));
+        truncated
+    }
+
     pub fn get_current_version(&self) -> String {
         self.current_version.read().clone()
     }
@@ -135,3 +1223,403 @@ impl VersionControl {
     }
 }
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agents::evaluator::Recommendation;
+
+    fn scratch_vc() -> (VersionControl, PathBuf) {
+        let dir = std::env::temp_dir().join(format!("brion-vc-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        (VersionControl::new(dir.clone()), dir)
+    }
+
+    fn change(file: &str, change_type: ChangeType, before: &str, after: &str) -> Change {
+        FileOperations::create_change("test-agent", "UIAgent", file.to_string(), change_type, before.to_string(), after.to_string())
+    }
+
+    #[test]
+    fn current_content_replays_sequential_changes() {
+        let (vc, dir) = scratch_vc();
+        let first = change("index.html", ChangeType::Modify, "<p>one</p>", "<p>two</p>");
+        let mut second = change("index.html", ChangeType::Modify, "<p>two</p>", "<p>three</p>");
+        second.timestamp = first.timestamp + chrono::Duration::seconds(1);
+        vc.record_change(first).unwrap();
+        vc.record_change(second.clone()).unwrap();
+
+        assert_eq!(vc.current_content("index.html"), Some(second.after));
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn current_content_is_the_original_once_every_change_is_rolled_back() {
+        let (vc, dir) = scratch_vc();
+        let edit = vc.record_change(change("index.html", ChangeType::Modify, "original", "edited")).unwrap();
+        let created = vc.record_change(change("new.css", ChangeType::Create, "", "body {}")).unwrap();
+        vc.rollback_change(&edit).unwrap();
+        vc.rollback_change(&created).unwrap();
+
+        assert_eq!(vc.current_content("index.html"), Some("original".to_string()));
+        assert_eq!(vc.current_content("new.css"), None);
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn report_truncates_long_diffs_but_the_change_keeps_the_full_diff() {
+        let (vc, dir) = scratch_vc();
+        let vc = vc.with_max_diff_lines(10);
+        let generated: String = (0..100).map(|i| format!("line {}\n", i)).collect();
+        let id = vc.record_change(change("generated.txt", ChangeType::Create, "", &generated)).unwrap();
+
+        // Two file headers, one hunk header and a hundred added lines
+        assert!(vc.render_report(1).contains("... 93 more lines ..."));
+        let diff = vc.get_change(&id).unwrap().unified_diff();
+        assert_eq!(diff.lines().filter(|line| line.starts_with("+line")).count(), 100);
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn snapshots_survive_a_restart() {
+        let (vc, dir) = scratch_vc();
+        let id = vc.record_change(change("index.html", ChangeType::Modify, "old", "new")).unwrap();
+        let version = vc.create_snapshot("First".to_string());
+
+        let reloaded = VersionControl::new(dir.clone());
+        assert_eq!(reloaded.load_from_disk().unwrap(), 1);
+        assert_eq!(reloaded.get_current_version(), version);
+        assert_eq!(reloaded.get_version_history().len(), 1);
+        assert!(reloaded.unsnapshotted_changes().is_empty());
+        assert_eq!(reloaded.rollback_to_version(&version).unwrap()[0].id, id);
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn changes_of_pruned_snapshots_stay_snapshotted() {
+        let (vc, dir) = scratch_vc();
+        let vc = vc.with_max_snapshots(1);
+        vc.record_change(change("index.html", ChangeType::Modify, "old", "new")).unwrap();
+        vc.create_snapshot("First".to_string());
+        vc.record_change(change("style.css", ChangeType::Modify, "old", "new")).unwrap();
+        // Only the newest snapshot survives, and it doesn't hold index.html
+        vc.create_snapshot_for("Second".to_string(), |file| file == "style.css");
+
+        assert_eq!(vc.get_version_history().len(), 1);
+        assert!(vc.unsnapshotted_changes().is_empty());
+        let reloaded = VersionControl::new(dir.clone()).with_max_snapshots(1);
+        reloaded.load_from_disk().unwrap();
+        assert!(reloaded.unsnapshotted_changes().is_empty());
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn change_ids_cannot_name_paths_outside_the_journal() {
+        let (vc, dir) = scratch_vc();
+        let mut escaping = change("index.html", ChangeType::Modify, "old", "new");
+        escaping.id = "../escaped".to_string();
+
+        assert!(vc.record_change(escaping).is_err());
+        assert!(!dir.join(".brion").join("escaped.json").exists());
+        assert!(vc.get_all_changes().is_empty());
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn distant_edits_diff_as_separate_hunks() {
+        let before: String = (0..1000).map(|i| format!("line {}\n", i)).collect();
+        let after = before.replace("line 10\n", "edited 10\n").replace("line 990\n", "edited 990\n");
+        let edit = change("data.txt", ChangeType::Modify, &before, &after);
+
+        assert_eq!(edit.diff_stat(), (2, 2));
+        let diff = edit.unified_diff();
+        assert!(diff.starts_with("--- a/data.txt\n+++ b/data.txt\n@@ -8,7 +8,7 @@\n"));
+        assert_eq!(diff.matches("\n@@ ").count(), 2);
+        assert_eq!(diff.lines().count(), 2 + 2 * 9);
+    }
+
+    #[test]
+    fn compacted_journal_replays_to_the_same_content() {
+        let (vc, dir) = scratch_vc();
+        let start = Utc::now();
+        for (i, file) in ["index.html", "style.css"].iter().cycle().take(20).enumerate() {
+            let version = i / 2;
+            let mut edit = change(file, ChangeType::Modify, &format!("v{}", version), &format!("v{}", version + 1));
+            edit.timestamp = start + chrono::Duration::seconds(i as i64);
+            vc.record_change(edit).unwrap();
+        }
+        let expected: Vec<Option<String>> = ["index.html", "style.css"].iter().map(|file| vc.current_content(file)).collect();
+
+        assert_eq!(vc.compact().unwrap(), 18);
+
+        let reloaded = VersionControl::new(dir.clone());
+        assert_eq!(reloaded.load_from_disk().unwrap(), 2);
+        let replayed: Vec<Option<String>> = ["index.html", "style.css"].iter().map(|file| reloaded.current_content(file)).collect();
+        assert_eq!(replayed, expected);
+        assert_eq!(expected, vec![Some("v10".to_string()), Some("v10".to_string())]);
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn compaction_orders_ties_by_journal_and_keeps_fully_rolled_back_files() {
+        let (vc, dir) = scratch_vc();
+        let first = change("style.css", ChangeType::Modify, "v0", "v1");
+        let mut second = change("style.css", ChangeType::Modify, "v1", "v2");
+        second.timestamp = first.timestamp;
+        vc.record_change(first).unwrap();
+        vc.record_change(second).unwrap();
+        let undone = vc.record_change(change("index.html", ChangeType::Modify, "original", "edited")).unwrap();
+        vc.rollback_change(&undone).unwrap();
+
+        assert_eq!(vc.compact().unwrap(), 1);
+
+        let reloaded = VersionControl::new(dir.clone());
+        assert_eq!(reloaded.load_from_disk().unwrap(), 2);
+        assert_eq!(reloaded.current_content("style.css"), Some("v2".to_string()));
+        assert_eq!(reloaded.current_content("index.html"), Some("original".to_string()));
+        assert!(reloaded.is_rolled_back(&undone));
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn loading_settles_a_compaction_interrupted_either_side_of_the_swap() {
+        let (vc, dir) = scratch_vc();
+        for version in 0..3 {
+            vc.record_change(change("index.html", ChangeType::Modify, &format!("v{}", version), &format!("v{}", version + 1))).unwrap();
+        }
+        let storage = dir.join(".brion");
+
+        // Crashed while writing the new journal: the old one is still whole
+        std::fs::create_dir_all(storage.join("changes.compacted")).unwrap();
+        std::fs::write(storage.join("changes.compacted").join("partial.json"), "{").unwrap();
+        let reloaded = VersionControl::new(dir.clone());
+        assert_eq!(reloaded.load_from_disk().unwrap(), 3);
+        assert!(!storage.join("changes.compacted").exists());
+
+        // Crashed between moving the old journal aside and the new one in
+        vc.compact().unwrap();
+        std::fs::rename(storage.join("changes"), storage.join("changes.compacted")).unwrap();
+        std::fs::create_dir_all(storage.join("changes.superseded")).unwrap();
+        let reloaded = VersionControl::new(dir.clone());
+        assert_eq!(reloaded.load_from_disk().unwrap(), 1);
+        assert_eq!(reloaded.current_content("index.html"), Some("v3".to_string()));
+        assert!(!storage.join("changes.superseded").exists());
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn original_contents_survive_compaction_and_reload() {
+        let (vc, dir) = scratch_vc();
+        for version in 0..5 {
+            vc.record_change(change("index.html", ChangeType::Modify, &format!("v{}", version), &format!("v{}", version + 1))).unwrap();
+        }
+        vc.record_change(change("new.css", ChangeType::Create, "", "body {}")).unwrap();
+        vc.record_change(change("new.css", ChangeType::Modify, "body {}", "body { margin: 0 }")).unwrap();
+
+        assert!(vc.compact().unwrap() > 0);
+
+        let reloaded = VersionControl::new(dir.clone());
+        reloaded.load_from_disk().unwrap();
+        let originals = reloaded.original_contents();
+        assert_eq!(originals.get("index.html"), Some(&Some("v0".to_string())));
+        assert_eq!(originals.get("new.css"), Some(&None));
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn verify_integrity_flags_exactly_the_tampered_change() {
+        let (vc, dir) = scratch_vc();
+        let key = b"journal-key".to_vec();
+        let vc = vc.with_signing_key(key.clone());
+        let ids: Vec<String> = ["index.html", "style.css", "app.js"].iter()
+            .map(|file| vc.record_change(change(file, ChangeType::Modify, "old", "new")).unwrap())
+            .collect();
+        assert!(vc.verify_integrity().is_empty());
+
+        let entry = dir.join(".brion").join("changes").join(format!("{}.json", ids[1]));
+        let stored = std::fs::read_to_string(&entry).unwrap();
+        std::fs::write(&entry, stored.replace("\"after\": \"new\"", "\"after\": \"injected\"")).unwrap();
+
+        let reloaded = VersionControl::new(dir.clone()).with_signing_key(key);
+        reloaded.load_from_disk().unwrap();
+        assert_eq!(reloaded.get_change(&ids[1]).unwrap().after, "injected");
+        assert_eq!(reloaded.verify_integrity(), vec![ids[1].clone()]);
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn diff_versions_reports_only_the_file_that_changed_between_snapshots() {
+        let (vc, dir) = scratch_vc();
+        vc.record_change(change("index.html", ChangeType::Modify, "<h1>Old</h1>\n", "<h1>New</h1>\n")).unwrap();
+        let first = vc.create_snapshot("First".to_string());
+        vc.record_change(change("style.css", ChangeType::Modify, "body {}\n", "body {}\nh1 { color: red; }\n")).unwrap();
+        let second = vc.create_snapshot("Second".to_string());
+
+        let diffs = vc.diff_versions(&first, &second).unwrap();
+
+        assert_eq!(diffs.len(), 1);
+        assert_eq!(diffs[0].file_path, "style.css");
+        assert_eq!(diffs[0].added_lines, vec!["h1 { color: red; }"]);
+        assert!(diffs[0].removed_lines.is_empty());
+        assert!(vc.diff_versions(&first, "missing").is_err());
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn change_frequency_counts_changes_per_file() {
+        let (vc, dir) = scratch_vc();
+        let start = chrono::TimeZone::timestamp_opt(&Utc, 1_700_000_000, 0).unwrap();
+        for (i, file) in ["index.html", "style.css", "index.html", "app.js", "index.html", "style.css"].iter().enumerate() {
+            let mut edit = change(file, ChangeType::Modify, "old", "new");
+            edit.timestamp = start + chrono::Duration::minutes(i as i64 * 20);
+            vc.record_change(edit).unwrap();
+        }
+
+        let frequency = vc.change_frequency();
+        assert_eq!(frequency.len(), 3);
+        assert_eq!((frequency["index.html"], frequency["style.css"], frequency["app.js"]), (3, 2, 1));
+
+        let hourly = vc.change_frequency_over_time(chrono::Duration::hours(1));
+        let totals: Vec<usize> = hourly.iter().map(|(_, counts)| counts.values().sum()).collect();
+        assert_eq!(totals.iter().sum::<usize>(), 6);
+        assert!(hourly.windows(2).all(|pair| pair[0].0 < pair[1].0));
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn file_scoped_snapshots_hold_only_matching_changes() {
+        let (vc, dir) = scratch_vc();
+        let css: Vec<String> = ["main.css", "theme.css"].iter()
+            .map(|file| vc.record_change(change(file, ChangeType::Modify, "old", "new")).unwrap())
+            .collect();
+        let js = vc.record_change(change("app.js", ChangeType::Modify, "old", "new")).unwrap();
+
+        let version = vc.create_snapshot_for("CSS only".to_string(), |file| file.ends_with(".css"));
+
+        let snapshot = vc.get_version_history().into_iter().find(|v| v.version_id == version).unwrap();
+        let mut snapshotted = snapshot.changes.clone();
+        snapshotted.sort();
+        let mut expected = css.clone();
+        expected.sort();
+        assert_eq!(snapshotted, expected);
+        assert_eq!(snapshot.total_files_changed, 2);
+        assert_eq!(vc.unsnapshotted_changes(), vec![js]);
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn changes_stay_unevaluated_until_scored() {
+        let (vc, dir) = scratch_vc();
+        let pending = change("index.html", ChangeType::Modify, "old", "new");
+        vc.record_change(pending.clone()).unwrap();
+
+        assert_eq!(vc.unevaluated_changes().iter().map(|c| c.id.as_str()).collect::<Vec<_>>(), vec![pending.id.as_str()]);
+
+        let mut scored = pending;
+        scored.evaluation_score = Some(0.8);
+        vc.record_change(scored).unwrap();
+        assert!(vc.unevaluated_changes().is_empty());
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn reviewer_notes_are_kept_in_order_with_authors_and_times() {
+        let (vc, dir) = scratch_vc();
+        let id = vc.record_change(change("index.html", ChangeType::Modify, "old", "new")).unwrap();
+        let before = Utc::now();
+
+        vc.add_note(&id, "alice", "Looks good").unwrap();
+        vc.add_note(&id, "bob", "Check the mobile layout").unwrap();
+
+        let notes = vc.get_change(&id).unwrap().notes;
+        let written: Vec<(&str, &str)> = notes.iter().map(|n| (n.author.as_str(), n.text.as_str())).collect();
+        assert_eq!(written, vec![("alice", "Looks good"), ("bob", "Check the mobile layout")]);
+        assert!(before <= notes[0].timestamp && notes[0].timestamp <= notes[1].timestamp);
+        assert!(vc.add_note("missing", "alice", "Lost").is_err());
+
+        let reloaded = VersionControl::new(dir.clone());
+        reloaded.load_from_disk().unwrap();
+        assert_eq!(reloaded.get_change(&id).unwrap().notes.len(), 2);
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn queries_combine_file_agent_and_time_filters() {
+        let (vc, dir) = scratch_vc();
+        let start = Utc::now();
+        let at = |seconds: i64, file: &str, agent: (&str, &str)| {
+            let mut c = change(file, ChangeType::Modify, "old", "new");
+            c.agent_id = agent.0.to_string();
+            c.agent_type = agent.1.to_string();
+            c.timestamp = start + chrono::Duration::seconds(seconds);
+            vc.record_change(c).unwrap()
+        };
+        let (ui, seo) = (("ui-1", "UIAgent"), ("seo-1", "SEOAgent"));
+        let early_ui_index = at(0, "index.html", ui);
+        let early_seo_index = at(1, "index.html", seo);
+        let late_ui_index = at(10, "index.html", ui);
+        let late_ui_about = at(11, "about.html", ui);
+        let ids = |query: ChangeQuery| vc.query(&query).into_iter().map(|c| c.id).collect::<Vec<_>>();
+
+        assert_eq!(ids(ChangeQuery::new().with_file("index.html").with_agent("ui-1")), vec![early_ui_index.clone(), late_ui_index.clone()]);
+        assert_eq!(ids(ChangeQuery::new().with_file("index.html").with_agent("SEOAgent")), vec![early_seo_index]);
+        assert_eq!(ids(ChangeQuery::new().with_agent("UIAgent").with_range(start + chrono::Duration::seconds(5), start + chrono::Duration::seconds(20))),
+            vec![late_ui_index.clone(), late_ui_about]);
+        // Both ends of the range are inclusive
+        assert_eq!(ids(ChangeQuery::new().with_file("index.html").with_agent("ui-1").with_range(start, start + chrono::Duration::seconds(10))),
+            vec![early_ui_index, late_ui_index]);
+        assert!(ids(ChangeQuery::new().with_file("about.html").with_agent("seo-1")).is_empty());
+        assert!(ids(ChangeQuery::new().with_file("about.html").with_range(start, start + chrono::Duration::seconds(10))).is_empty());
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn evaluations_survive_a_restart() {
+        let (vc, dir) = scratch_vc();
+        let evaluate = |change_id: &str, score: f64| EvaluationResult {
+            change_id: change_id.to_string(),
+            aesthetic_score: score,
+            functionality_score: score,
+            overall_score: score,
+            issues: vec![format!("scored {}", score)],
+            recommendations: vec![Recommendation::new("contrast", "Raise the text contrast")],
+            should_keep: score >= 0.5,
+        };
+        let poor = vc.record_change(change("index.html", ChangeType::Modify, "old", "new")).unwrap();
+        let worse = vc.record_change(change("style.css", ChangeType::Modify, "a {}", "b {}")).unwrap();
+        let good = vc.record_change(change("about.html", ChangeType::Modify, "old", "new")).unwrap();
+        vc.record_evaluation(evaluate(&poor, 0.4)).unwrap();
+        vc.record_evaluation(evaluate(&worse, 0.9)).unwrap();
+        // A later evaluation replaces the earlier one, on disk too
+        vc.record_evaluation(evaluate(&worse, 0.2)).unwrap();
+        vc.record_evaluation(evaluate(&good, 0.8)).unwrap();
+
+        let reloaded = VersionControl::new(dir.clone());
+        reloaded.load_from_disk().unwrap();
+        let low: Vec<(String, f64)> = reloaded.get_low_scoring_changes(0.5)
+            .into_iter()
+            .map(|(change, evaluation)| (change.id, evaluation.overall_score))
+            .collect();
+        assert_eq!(low, vec![(worse.clone(), 0.2), (poor, 0.4)]);
+        let kept = reloaded.get_evaluation(&worse).unwrap();
+        assert_eq!(kept.issues, vec!["scored 0.2".to_string()]);
+        assert_eq!(kept.recommendations, vec![Recommendation::new("contrast", "Raise the text contrast")]);
+        assert!(!kept.should_keep);
+        assert!(reloaded.get_evaluation(&good).unwrap().should_keep);
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn snapshots_in_a_tight_loop_get_distinct_sortable_ids() {
+        let (vc, dir) = scratch_vc();
+        let initial = vc.get_current_version();
+
+        let ids: Vec<String> = (0..50).map(|i| vc.create_snapshot(format!("Snapshot {}", i))).collect();
+
+        let distinct: HashSet<&String> = ids.iter().collect();
+        assert_eq!(distinct.len(), ids.len());
+        assert!(!ids.contains(&initial));
+        assert!(ids.windows(2).all(|pair| pair[0] < pair[1]));
+        assert_eq!(vc.get_current_version(), ids[49]);
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}